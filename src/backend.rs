@@ -0,0 +1,77 @@
+// everything that talks to the screen and keyboard goes through these, so the game can run
+// in a tcod window (`TcodBackend`) or entirely in memory (`Headless`) for tests and servers.
+// the world holds them as the `Screen` and `Keyboard` resources.
+use std::ops::{Deref, DerefMut};
+
+pub mod headless;
+pub mod tcod_backend;
+
+pub use headless::Headless;
+pub use tcod_backend::TcodBackend;
+
+pub type Color = (u8, u8, u8);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Escape,
+    Enter,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+pub trait Renderer: Send + Sync {
+    // in characters
+    fn size(&self) -> (i32, i32);
+    fn clear(&mut self);
+    fn background(&self, x: i32, y: i32) -> Color;
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, fg_color: Color, bg_color: Color);
+    // wraps `text` to fit the rectangle, anything past its bottom is cut off
+    fn print_rect(&mut self, x: i32, y: i32, width: i32, height: i32, text: &str);
+    // 0 for as fast as possible
+    fn set_fps(&mut self, fps: i32);
+    // shows everything drawn since the last flush
+    fn flush(&mut self);
+    fn is_closed(&self) -> bool;
+}
+
+pub trait InputSource: Send + Sync {
+    // the next key pressed, without waiting for one
+    fn poll_key(&mut self) -> Option<Key>;
+}
+
+pub struct Screen(pub Box<dyn Renderer>);
+
+impl Deref for Screen {
+    type Target = dyn Renderer;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl DerefMut for Screen {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut()
+    }
+}
+
+pub struct Keyboard(pub Box<dyn InputSource>);
+
+impl Deref for Keyboard {
+    type Target = dyn InputSource;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl DerefMut for Keyboard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut()
+    }
+}
@@ -0,0 +1,330 @@
+use super::{Color, InputSource, Key, Renderer};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub glyph: char,
+    pub fg_color: Color,
+    pub bg_color: Color,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            glyph: ' ',
+            fg_color: (255, 255, 255),
+            bg_color: (0, 0, 0),
+        }
+    }
+}
+
+struct HeadlessState {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+    keys: VecDeque<Key>,
+    closed: bool,
+}
+
+impl HeadlessState {
+    fn cell_mut(&mut self, x: i32, y: i32) -> Option<&mut Cell> {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return None;
+        }
+        self.cells.get_mut((y * self.width + x) as usize)
+    }
+}
+
+// a screen and keyboard that only exist in memory. clones share the same screen, so one can be
+// handed to the world while the other presses keys and reads back what was drawn:
+//
+//     let headless = Headless::new(80, 33);
+//     let config = Config::default();
+//     let mut ecs = ecs::world_setup_with(headless.screen(), headless.keyboard(), 1234, config)?;
+//     headless.press(Key::Char('l'));
+//     ecs.step();
+//     assert_eq!(headless.glyph(41, 12), '@');
+#[derive(Clone)]
+pub struct Headless {
+    state: Arc<Mutex<HeadlessState>>,
+}
+
+impl Headless {
+    pub fn new(width: i32, height: i32) -> Self {
+        let state = HeadlessState {
+            width,
+            height,
+            cells: vec![Cell::default(); (width * height) as usize],
+            keys: VecDeque::new(),
+            closed: false,
+        };
+
+        Headless {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn screen(&self) -> super::Screen {
+        super::Screen(Box::new(self.clone()))
+    }
+
+    pub fn keyboard(&self) -> super::Keyboard {
+        super::Keyboard(Box::new(self.clone()))
+    }
+
+    // queued up, the game reads one key per frame
+    pub fn press(&self, key: Key) {
+        self.state.lock().unwrap().keys.push_back(key);
+    }
+
+    pub fn type_str(&self, text: &str) {
+        for c in text.chars() {
+            self.press(Key::Char(c));
+        }
+    }
+
+    // the same as closing the window
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+    }
+
+    pub fn cell(&self, x: i32, y: i32) -> Option<Cell> {
+        self.state.lock().unwrap().cell_mut(x, y).map(|cell| *cell)
+    }
+
+    pub fn glyph(&self, x: i32, y: i32) -> char {
+        self.cell(x, y).map_or(' ', |cell| cell.glyph)
+    }
+
+    // the screen as rows of characters, trailing spaces left in
+    pub fn lines(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .cells
+            .chunks(state.width as usize)
+            .map(|row| row.iter().map(|cell| cell.glyph).collect())
+            .collect()
+    }
+
+    pub fn text(&self) -> String {
+        self.lines().join("\n")
+    }
+}
+
+impl Renderer for Headless {
+    fn size(&self) -> (i32, i32) {
+        let state = self.state.lock().unwrap();
+        (state.width, state.height)
+    }
+
+    fn clear(&mut self) {
+        for cell in self.state.lock().unwrap().cells.iter_mut() {
+            *cell = Cell::default();
+        }
+    }
+
+    fn background(&self, x: i32, y: i32) -> Color {
+        self.cell(x, y).unwrap_or_default().bg_color
+    }
+
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, fg_color: Color, bg_color: Color) {
+        if let Some(cell) = self.state.lock().unwrap().cell_mut(x, y) {
+            *cell = Cell {
+                glyph,
+                fg_color,
+                bg_color,
+            };
+        }
+    }
+
+    // wraps at the edge of the rectangle rather than between words like tcod does
+    fn print_rect(&mut self, x: i32, y: i32, width: i32, height: i32, text: &str) {
+        let mut state = self.state.lock().unwrap();
+        let mut row = 0;
+        for line in text.split('\n') {
+            let chars: Vec<char> = line.chars().collect();
+            for chunk in chars.chunks(width.max(1) as usize) {
+                if row >= height {
+                    return;
+                }
+                for (col, glyph) in chunk.iter().enumerate() {
+                    if let Some(cell) = state.cell_mut(x + col as i32, y + row) {
+                        cell.glyph = *glyph;
+                    }
+                }
+                row += 1;
+            }
+            if chars.is_empty() {
+                row += 1;
+            }
+        }
+    }
+
+    fn set_fps(&mut self, _fps: i32) {}
+
+    fn flush(&mut self) {}
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+}
+
+impl InputSource for Headless {
+    fn poll_key(&mut self) -> Option<Key> {
+        self.state.lock().unwrap().keys.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{Corporeal, PlayerControl, Position, Renderable};
+    use crate::config::Config;
+    use crate::ecs::{self, GameState, State};
+    use crate::replay::{Recorder, Recording, Replay, Settings};
+    use specs::prelude::*;
+
+    #[test]
+    fn draws_and_wraps_text() {
+        let headless = Headless::new(4, 3);
+        let mut screen = headless.screen();
+        screen.put_char(1, 0, '@', (255, 0, 0), (0, 0, 0));
+        // off the edge, ignored
+        screen.put_char(4, 0, 'x', (255, 0, 0), (0, 0, 0));
+        screen.print_rect(0, 1, 3, 2, "abcdefgh");
+        assert_eq!(headless.lines(), vec![" @  ", "abc ", "def "]);
+        assert_eq!(headless.cell(1, 0).unwrap().fg_color, (255, 0, 0));
+
+        screen.clear();
+        assert_eq!(headless.text(), "    \n    \n    ");
+    }
+
+    fn player_turn(ecs: &mut ecs::Ecs) {
+        for _ in 0..100 {
+            if ecs.world().read_resource::<GameState>().current() == State::PlayerTurn {
+                return;
+            }
+            assert!(ecs.step());
+        }
+        panic!("the player never got a turn");
+    }
+
+    #[test]
+    fn plays_a_few_turns() {
+        let config = Config::default();
+        let headless = Headless::new(config.screen_width, config.screen_height);
+        let mut ecs =
+            ecs::world_setup_with(headless.screen(), headless.keyboard(), 1234, config).unwrap();
+        let player_pos = |ecs: &ecs::Ecs| {
+            let world = ecs.world();
+            let positions = world.read_storage::<Position>();
+            let players = world.read_storage::<PlayerControl>();
+            let (pos, _) = (&positions, &players).join().next().unwrap();
+            *pos
+        };
+
+        player_turn(&mut ecs);
+        // the frame the player's turn starts on is drawn on the next one
+        ecs.step();
+        let start = player_pos(&ecs);
+        let tick = ecs.world().read_resource::<GameState>().world_time.tick;
+        assert!(headless.text().contains('@'));
+        assert!(headless.text().contains('#'));
+
+        for _ in 0..3 {
+            headless.press(Key::Char('.'));
+            ecs.step();
+            player_turn(&mut ecs);
+        }
+        ecs.step();
+        // waiting passes time but doesn't move anyone
+        assert!(ecs.world().read_resource::<GameState>().world_time.tick > tick);
+        assert_eq!(player_pos(&ecs), start);
+        assert!(headless.text().contains('@'));
+    }
+
+    // everything a seed decides: where things are, how hurt they are and what they look like
+    fn snapshot(world: &World) -> Vec<(i32, i32, char, Option<i32>)> {
+        let positions = world.read_storage::<Position>();
+        let renderables = world.read_storage::<Renderable>();
+        let corporeal = world.read_storage::<Corporeal>();
+        let mut snapshot: Vec<_> = (&positions, &renderables, corporeal.maybe())
+            .join()
+            .map(|(pos, renderable, corporeal)| {
+                (pos.x, pos.y, renderable.glyph, corporeal.map(|c| c.hp))
+            })
+            .collect();
+        snapshot.sort();
+        snapshot
+    }
+
+    fn play(seed: u64, keys: &str) -> (Vec<(i32, i32, char, Option<i32>)>, String) {
+        let config = Config::default();
+        let headless = Headless::new(config.screen_width, config.screen_height);
+        let mut ecs =
+            ecs::world_setup_with(headless.screen(), headless.keyboard(), seed, config).unwrap();
+        player_turn(&mut ecs);
+        for key in keys.chars() {
+            headless.press(Key::Char(key));
+            ecs.step();
+            player_turn(&mut ecs);
+        }
+        ecs.step();
+        (snapshot(ecs.world()), headless.text())
+    }
+
+    #[test]
+    fn the_same_seed_plays_the_same() {
+        let keys = "hhjjkl.l..yubn..";
+        let (first, first_screen) = play(99, keys);
+        let (second, second_screen) = play(99, keys);
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+        assert_eq!(first_screen, second_screen);
+    }
+
+    // a recorded game played back from its seed ends up where the recorded one did
+    #[test]
+    fn replays_match_the_recorded_game() {
+        let path = std::env::temp_dir().join(format!("rust_rl_{}.replay", std::process::id()));
+        let config = Config::default();
+        let headless = Headless::new(config.screen_width, config.screen_height);
+        let settings = Settings::from_config(&config);
+        let mut ecs =
+            ecs::world_setup_with(headless.screen(), headless.keyboard(), 7, config.clone())
+                .unwrap();
+        let recorder = Recorder::create(&path, 7, false, settings).unwrap();
+        ecs.world_mut().insert(recorder);
+        player_turn(&mut ecs);
+        for key in "llljjj.hh.kkyb".chars() {
+            headless.press(Key::Char(key));
+            ecs.step();
+            player_turn(&mut ecs);
+        }
+        let recorded = snapshot(ecs.world());
+
+        let recording = Recording::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut recording = recording.unwrap();
+        assert!(!recording.commands.is_empty());
+        recording.check_settings(&config).unwrap();
+        // a game on a bigger map would go differently
+        recording.settings.map_width += 1;
+        assert!(recording.check_settings(&config).is_err());
+        recording.settings.map_width -= 1;
+
+        let headless = Headless::new(config.screen_width, config.screen_height);
+        let seed = recording.seed;
+        let mut ecs =
+            ecs::world_setup_with(headless.screen(), headless.keyboard(), seed, config).unwrap();
+        ecs.world_mut().insert(Replay::new(recording, true, None));
+        while ecs.world().read_resource::<Replay>().is_playing() {
+            assert!(ecs.step());
+        }
+        // the last command was only just given, let it play out like a key press
+        ecs.step();
+        player_turn(&mut ecs);
+        assert_eq!(snapshot(ecs.world()), recorded);
+    }
+}
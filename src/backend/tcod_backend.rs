@@ -0,0 +1,99 @@
+use super::{Color, InputSource, Key, Renderer};
+use std::sync::{Arc, Mutex};
+use tcod::colors;
+use tcod::console::*;
+use tcod::input::{KeyCode, KeyPressFlags};
+
+fn tcod_color((r, g, b): Color) -> colors::Color {
+    colors::Color { r, g, b }
+}
+
+// a tcod window. clones share the same window, one is the `Screen` and one the `Keyboard`
+#[derive(Clone)]
+pub struct TcodBackend {
+    root: Arc<Mutex<Root>>,
+}
+
+impl TcodBackend {
+    pub fn open(width: i32, height: i32) -> Self {
+        let root = Root::initializer()
+            .size(width, height)
+            .font("term.png", FontLayout::AsciiInRow)
+            .init();
+
+        TcodBackend {
+            root: Arc::new(Mutex::new(root)),
+        }
+    }
+}
+
+impl Renderer for TcodBackend {
+    fn size(&self) -> (i32, i32) {
+        let root = self.root.lock().unwrap();
+        (root.width(), root.height())
+    }
+
+    fn clear(&mut self) {
+        self.root.lock().unwrap().clear();
+    }
+
+    fn background(&self, x: i32, y: i32) -> Color {
+        let color = self.root.lock().unwrap().get_char_background(x, y);
+        (color.r, color.g, color.b)
+    }
+
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, fg_color: Color, bg_color: Color) {
+        self.root.lock().unwrap().put_char_ex(
+            x,
+            y,
+            glyph,
+            tcod_color(fg_color),
+            tcod_color(bg_color),
+        );
+    }
+
+    fn print_rect(&mut self, x: i32, y: i32, width: i32, height: i32, text: &str) {
+        self.root
+            .lock()
+            .unwrap()
+            .print_rect(x, y, width, height, text);
+    }
+
+    fn set_fps(&mut self, fps: i32) {
+        tcod::system::set_fps(fps);
+    }
+
+    fn flush(&mut self) {
+        self.root.lock().unwrap().flush();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.root.lock().unwrap().window_closed()
+    }
+}
+
+impl InputSource for TcodBackend {
+    fn poll_key(&mut self) -> Option<Key> {
+        let key = self
+            .root
+            .lock()
+            .unwrap()
+            .check_for_keypress(KeyPressFlags::all())?;
+        if !key.pressed {
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Escape => Some(Key::Escape),
+            KeyCode::Enter | KeyCode::NumPadEnter => Some(Key::Enter),
+            KeyCode::Backspace => Some(Key::Backspace),
+            KeyCode::Tab => Some(Key::Tab),
+            KeyCode::Up => Some(Key::Up),
+            KeyCode::Down => Some(Key::Down),
+            KeyCode::Left => Some(Key::Left),
+            KeyCode::Right => Some(Key::Right),
+            _ if key.printable != '\0' => Some(Key::Char(key.printable)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+extern crate rust_rl;
+extern crate specs;
+extern crate tcod;
+extern crate vecmap;
+
+use rust_rl::components::{Actor, BlockMovement, BlockSight, Position, Stats};
+use rust_rl::map::{BlockTile, EntityMap, View};
+use rust_rl::systems::movement::CollisionMapUpdater;
+use rust_rl::terrain::Terrain;
+use specs::prelude::*;
+use std::env;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tcod::map::Map as TcodMap;
+use vecmap::VecMap;
+
+const USAGE: &str = "usage: bench-collision [size] [frames] [moves per frame]";
+
+// times `CollisionMapUpdater` against rebuilding the whole collision map, the way it was done
+// before it kept track of what changed. the map is `size` by `size` tiles with a floor entity on
+// each, walls dotted around and an actor on every fiftieth tile. every frame some of the actors
+// take a step and every actor's turn is updated, like the time system does.
+//
+// after every frame the maps from both are compared, so this doubles as a check that the
+// incremental version doesn't drift. run it with --release:
+//
+//     cargo run --release --bin bench-collision -- 400 200 50
+fn main() {
+    let args: Vec<usize> = env::args()
+        .skip(1)
+        .map(|arg| arg.parse().unwrap_or_else(|_| exit_with(USAGE)))
+        .collect();
+    let size = args.first().cloned().unwrap_or(400);
+    let frames = args.get(1).cloned().unwrap_or(200);
+    let moves = args.get(2).cloned().unwrap_or(50);
+
+    let mut world = World::new();
+    let mut updater = CollisionMapUpdater::new();
+    System::setup(&mut updater, &mut world);
+    world.insert(EntityMap::new(size, size));
+    world.insert(View {
+        map: Arc::new(Mutex::new(TcodMap::new(size as i32, size as i32))),
+        block_map: VecMap::filled_with(BlockTile::default(), size as i32, size as i32),
+    });
+    // the walls are entities here, so the rebuild has as much to walk as it used to
+    world.insert(Terrain::new(size as i32, size as i32));
+
+    let actors = populate(&mut world, size as i32);
+    println!(
+        "{}x{} tiles, {} entities, {} actors, {} moves per frame",
+        size,
+        size,
+        world.entities().join().count(),
+        actors.len(),
+        moves
+    );
+
+    // the first run builds the map from scratch either way
+    updater.run_now(&world);
+
+    let mut incremental = Duration::default();
+    let mut full = Duration::default();
+    for frame in 0..frames {
+        step_actors(&world, &actors, frame, moves);
+
+        let start = Instant::now();
+        updater.run_now(&world);
+        incremental += start.elapsed();
+        let expected = snapshot(&world);
+
+        let start = Instant::now();
+        full_rebuild(&world);
+        full += start.elapsed();
+
+        if snapshot(&world) != expected {
+            eprintln!("the maps differ after frame {}", frame);
+            process::exit(1);
+        }
+    }
+
+    let per_frame = |total: Duration| total.as_secs_f64() * 1000.0 / frames.max(1) as f64;
+    println!("full rebuild: {:>10.3} ms/frame", per_frame(full));
+    println!("incremental:  {:>10.3} ms/frame", per_frame(incremental));
+}
+
+fn populate(world: &mut World, size: i32) -> Vec<Entity> {
+    let mut actors = Vec::new();
+    for y in 0..size {
+        for x in 0..size {
+            world.create_entity().with(Position { x, y }).build();
+
+            let edge = x == 0 || y == 0 || x == size - 1 || y == size - 1;
+            if edge || (x * 7 + y * 13) % 11 == 0 {
+                world
+                    .create_entity()
+                    .with(Position { x, y })
+                    .with(BlockMovement)
+                    .with(BlockSight)
+                    .build();
+            } else if (x + y * size) % 50 == 0 {
+                let actor = world
+                    .create_entity()
+                    .with(Position { x, y })
+                    .with(Actor {
+                        next_turn: 0,
+                        stats: Stats::default(),
+                    })
+                    .build();
+                actors.push(actor);
+            }
+        }
+    }
+    actors
+}
+
+// steps `moves` of the actors east or west onto a free tile, and gives every actor a new turn
+fn step_actors(world: &World, actors: &[Entity], frame: usize, moves: usize) {
+    let entity_map = world.read_resource::<EntityMap>();
+    let view = world.read_resource::<View>();
+    let mut positions = world.write_storage::<Position>();
+
+    let dx = if frame % 2 == 1 { -1 } else { 1 };
+    for &actor in actors.iter().cycle().skip(frame * moves).take(moves) {
+        let pos = *positions.get(actor).unwrap();
+        let (x, y) = (pos.x + dx, pos.y);
+        let free = match (
+            view.block_map.retrieve(x, y),
+            entity_map.actors.retrieve(x, y),
+        ) {
+            (Ok(tile), Ok(None)) => !tile.blocks_movement,
+            _ => false,
+        };
+        if free {
+            *positions.get_mut(actor).unwrap() = Position { x, y };
+        }
+    }
+
+    for actor in (&mut world.write_storage::<Actor>()).join() {
+        actor.next_turn += 1;
+    }
+}
+
+// what `CollisionMapUpdater` did before it kept track of changes
+fn full_rebuild(world: &World) {
+    let entities = world.entities();
+    let positions = world.read_storage::<Position>();
+    let actors = world.read_storage::<Actor>();
+    let sight_blockers = world.read_storage::<BlockSight>();
+    let movement_blockers = world.read_storage::<BlockMovement>();
+    let mut map = world.write_resource::<EntityMap>();
+    let mut view = world.write_resource::<View>();
+
+    map.actors.reset_map();
+    view.block_map.reset_map();
+
+    for (ent, pos) in (&entities, &positions).join() {
+        let id = view.block_map.xy_idx(pos.x, pos.y);
+
+        if sight_blockers.get(ent).is_some() {
+            view.block_map.items[id].blocks_sight = true
+        }
+        if movement_blockers.get(ent).is_some() {
+            view.block_map.items[id].blocks_movement = true
+        }
+        if actors.get(ent).is_some() {
+            map.actors.set_point(pos.x, pos.y, Some(ent));
+            view.block_map.items[id].blocks_movement = true
+        }
+    }
+
+    for i in 0..view.block_map.items.len() {
+        let (x, y) = view.block_map.idx_xy(i);
+        let transparent = !view.block_map.items[i].blocks_sight;
+        let walkable = !view.block_map.items[i].blocks_movement;
+        view.map.lock().unwrap().set(x, y, transparent, walkable);
+    }
+}
+
+fn snapshot(world: &World) -> Vec<(Option<Entity>, bool, bool)> {
+    let map = world.read_resource::<EntityMap>();
+    let view = world.read_resource::<View>();
+    let tcod_map = view.map.lock().unwrap();
+
+    (0..view.block_map.items.len())
+        .map(|i| {
+            let (x, y) = view.block_map.idx_xy(i);
+            let tile = view.block_map.items[i];
+            assert_eq!(tcod_map.is_transparent(x, y), !tile.blocks_sight);
+            assert_eq!(tcod_map.is_walkable(x, y), !tile.blocks_movement);
+            (map.actors.items[i], tile.blocks_movement, tile.blocks_sight)
+        })
+        .collect()
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
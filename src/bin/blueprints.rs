@@ -0,0 +1,82 @@
+extern crate rust_rl;
+
+use rust_rl::entity_factory::EntityFactory;
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage:
+    blueprints check [--dir <blueprints dir>]
+    blueprints export [--json] [--dir <blueprints dir>] [name...]";
+
+// tools for working with the blueprint tree.
+//
+// `check` loads every blueprint and reports everything wrong with them, like
+// validate-blueprints. `export` prints blueprints with everything they inherit filled in, as
+// ron (the default) or json, each component annotated with the files it came from. with no
+// names given, every blueprint that can be built is exported.
+fn main() {
+    let mut args = env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+    if command != "check" && command != "export" {
+        exit_with(USAGE);
+    }
+
+    let mut json = false;
+    let mut dir = String::from("blueprints");
+    let mut names = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--ron" => json = false,
+            "--dir" => match args.next() {
+                Some(path) => dir = path,
+                None => exit_with(USAGE),
+            },
+            _ => names.push(arg),
+        }
+    }
+
+    let factory = match EntityFactory::new(&dir) {
+        Ok(factory) => factory,
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("{}", err);
+            }
+            exit_with(&format!("{} blueprint error(s)", errors.len()));
+        }
+    };
+
+    match command.as_str() {
+        "check" => println!("{} blueprints OK", factory.len()),
+        _ => export(&factory, names, json),
+    }
+}
+
+fn export(factory: &EntityFactory, mut names: Vec<String>, json: bool) {
+    if names.is_empty() {
+        names = factory.names();
+    }
+
+    let mut exported = Vec::new();
+    for name in &names {
+        match factory.resolve(name) {
+            Ok(blueprint) if json => exported.push(blueprint.to_json()),
+            Ok(blueprint) => exported.push(blueprint.to_ron()),
+            Err(err) => exit_with(&err.to_string()),
+        }
+    }
+
+    if !json {
+        print!("{}", exported.join("\n"));
+    } else if exported.len() == 1 {
+        print!("{}", exported[0]);
+    } else {
+        // several blueprints make an array
+        print!("[\n{}]\n", exported.join(",\n"));
+    }
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,22 @@
+extern crate rust_rl;
+
+use rust_rl::entity_factory::EntityFactory;
+use std::env;
+use std::process;
+
+// loads every blueprint under the given directory (default "blueprints") and reports
+// every broken file, missing parent, inheritance cycle and unknown component key.
+fn main() {
+    let path = env::args().nth(1).unwrap_or(String::from("blueprints"));
+
+    match EntityFactory::new(&path) {
+        Ok(factory) => println!("{} blueprints OK", factory.len()),
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("{}", err);
+            }
+            eprintln!("{} blueprint error(s)", errors.len());
+            process::exit(1);
+        }
+    }
+}
@@ -1,6 +1,7 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum BodyPartTag {
     // for the main body that everything attaches to
     Core,
@@ -22,7 +23,7 @@ pub enum BodyPartTag {
     ThoughtCenter,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 // Tells the system what kind of armor can be worn on this body part
 pub enum ArmorTag {
     Core,
@@ -37,12 +38,19 @@ pub enum ArmorTag {
     Jewelry(i32), // Amount of jewelry that can be worn on this body part, i.e. hands get 5 each
 }
 
-#[derive(Debug)]
+// body plans are trees of these, see `blueprints/bodies/`. a part in a plan file only needs
+// its name, everything else defaults to empty
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BodyPart {
     pub name: String,
+    #[serde(default)]
     pub children: Vec<BodyPart>,
+    #[serde(default)]
     pub tags: Vec<BodyPartTag>,
+    #[serde(default)]
     pub armor_tags: Vec<ArmorTag>,
+    // saves write these out separately, see `save.rs`
+    #[serde(skip)]
     pub equipped_armor: Vec<Entity>,
 }
 
@@ -50,4 +58,19 @@ impl BodyPart {
     pub fn add_child(&mut self, body_part: BodyPart) {
         self.children.push(body_part);
     }
+
+    // this part, then each child and its children in order
+    pub fn walk(&self, visit: &mut dyn FnMut(&BodyPart)) {
+        visit(self);
+        for child in &self.children {
+            child.walk(visit);
+        }
+    }
+
+    pub fn walk_mut(&mut self, visit: &mut dyn FnMut(&mut BodyPart)) {
+        visit(self);
+        for child in &mut self.children {
+            child.walk_mut(visit);
+        }
+    }
 }
@@ -1,18 +1,40 @@
 use crate::systems::movement::Dir;
-use serde::Deserialize;
+use crate::systems::wizard::WizardCommand;
 use specs::prelude::*;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     Move(Dir),
     Attack(Dir),
+    Descend,
+    Ascend,
+    PickUp,
     _Use(Entity),
     _Rest,
     _ToggleRealTime,
     EndGame,
+    Pause,
+    OpenInventory,
+    Target,
+    OpenConsole,
+    Wizard(WizardCommand),
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+impl Command {
+    // doesn't act for the player, so it can be given outside their turn or during a replay
+    pub fn is_meta(&self) -> bool {
+        matches!(
+            self,
+            Command::EndGame | Command::Pause | Command::OpenInventory
+        )
+    }
+
+    pub fn is_wizard(&self) -> bool {
+        matches!(self, Command::Wizard(_))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct CommandEvent {
     pub command: Command,
     pub entity: Entity,
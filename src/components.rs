@@ -1,14 +1,14 @@
 use crate::bodyparts::*;
 use crate::command::Command;
+use crate::config::Config;
 use crate::systems::ai::types::AiType;
-use crate::CONFIG;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use std::collections::HashMap;
 
 pub mod flags;
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct PrintDebug;
 
@@ -30,11 +30,20 @@ impl Target {
     }
 
     pub fn decrement_timer(&mut self) {
-        self.give_up_timer = self.give_up_timer - 1
+        self.give_up_timer -= 1
     }
 }
 
-#[derive(Component, Debug, PartialEq, Deserialize, Clone)]
+// the blueprint an entity was built from, so it can be found again when the blueprint is
+// reloaded. `prefab` is the prefab and member number for entities built as part of one
+#[derive(Component, Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[storage(DenseVecStorage)]
+pub struct FromBlueprint {
+    pub blueprint: String,
+    pub prefab: Option<(String, usize)>,
+}
+
+#[derive(Component, Debug, PartialEq, Deserialize, Serialize, Clone)]
 #[storage(VecStorage)]
 pub struct Name {
     #[serde(default)]
@@ -49,7 +58,7 @@ impl Name {
     }
 }
 
-#[derive(Component, PartialEq, Clone, Deserialize, Debug)]
+#[derive(Component, PartialEq, Clone, Deserialize, Serialize, Debug)]
 #[storage(DenseVecStorage)]
 pub struct Mobile {
     #[serde(default)]
@@ -59,36 +68,27 @@ pub struct Mobile {
 impl Default for Mobile {
     fn default() -> Self {
         Mobile {
-            quickness: CONFIG.base_turn_time,
+            quickness: Config::default().base_turn_time,
         }
     }
 }
 
 impl Mobile {
-    pub fn modify_quickness(&mut self, modifier: i32) {
-        if modifier < CONFIG.base_turn_time as i32 {
-            self.quickness = (CONFIG.base_turn_time as i32 - modifier) as u32;
+    pub fn modify_quickness(&mut self, modifier: i32, config: &Config) {
+        if modifier < config.base_turn_time as i32 {
+            self.quickness = (config.base_turn_time as i32 - modifier) as u32;
         } else {
-            self.quickness = CONFIG.min_turn_time
+            self.quickness = config.min_turn_time
         }
     }
 }
 
-#[derive(Component, Clone, PartialEq, Debug)]
+#[derive(Component, Clone, PartialEq, Debug, Default)]
 pub struct CommandSequence {
     pub commands: Vec<Command>,
 }
 
-impl Default for CommandSequence {
-    fn default() -> Self {
-        CommandSequence {
-            commands: Vec::new(),
-        }
-    }
-}
-
-#[derive(Component, Clone, PartialEq, Debug, Deserialize)]
-#[storage(DenseVecStorage)]
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
 pub struct Actor {
     #[serde(default)]
     pub next_turn: u64,
@@ -97,7 +97,14 @@ pub struct Actor {
     pub stats: Stats,
 }
 
-#[derive(PartialEq, Copy, Clone, Debug, Deserialize)]
+// `Position`, `Actor`, `BlockMovement` and `BlockSight` are flagged, so the collision map only
+// has to look at the entities that changed, see `CollisionMapUpdater`
+impl Component for Actor {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Stats {
     pub strength: u32,
     pub agility: u32,
@@ -144,16 +151,7 @@ impl Actor {
     }
 }
 
-impl Default for Actor {
-    fn default() -> Self {
-        Actor {
-            next_turn: 0,
-            stats: Stats::default(),
-        }
-    }
-}
-
-#[derive(Component, Clone, Deserialize, Debug)]
+#[derive(Component, Clone, Deserialize, Serialize, Debug)]
 #[storage(VecStorage)]
 // something that is destructible and physically exists
 pub struct Corporeal {
@@ -162,27 +160,30 @@ pub struct Corporeal {
     pub base_damage: i32,
 }
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct MyTurn;
 
-#[derive(Component, Default, Deserialize, Clone, Debug)]
+#[derive(Component, Default, Deserialize, Serialize, Clone, Debug)]
 #[storage(NullStorage)]
 pub struct PlayerControl;
 
-#[derive(Component, Debug, Clone, Deserialize)]
+#[derive(Component, Debug, Clone, Deserialize, Serialize)]
 #[storage(DenseVecStorage)]
 pub struct AiControl {
     pub ai_type: AiType,
 }
 
-#[derive(Component, Clone, Deserialize, Copy, Debug, PartialEq)]
-#[storage(VecStorage)]
+#[derive(Clone, Deserialize, Serialize, Copy, Debug, PartialEq)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
 
+impl Component for Position {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
 impl Position {
     pub fn new(x: i32, y: i32) -> Self {
         Position { x, y }
@@ -191,8 +192,7 @@ impl Position {
 
 // TODO: separate elevation into its own component type
 
-#[derive(Component, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Deserialize)]
-#[storage(VecStorage)]
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Deserialize, Serialize)]
 pub enum Elevation {
     Floor,
     OnFloor,
@@ -200,7 +200,24 @@ pub enum Elevation {
     _InAir,
 }
 
-#[derive(Component, Clone, Deserialize, Debug)]
+// flagged for the `SpatialIndex`, which sorts entities by it
+impl Component for Elevation {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+// what something is made of, for terrain as much as for entities
+#[derive(Component, Debug, Default, Eq, PartialEq, Copy, Clone, Deserialize, Serialize)]
+#[storage(DenseVecStorage)]
+pub enum Material {
+    #[default]
+    Stone,
+    Earth,
+    Wood,
+    Metal,
+    Flesh,
+}
+
+#[derive(Component, Clone, Deserialize, Serialize, Debug)]
 #[storage(VecStorage)]
 pub struct Renderable {
     pub glyph: char,
@@ -218,7 +235,7 @@ impl Renderable {
     }
 }
 
-#[derive(Component, Clone, Deserialize, Debug)]
+#[derive(Component, Clone, Deserialize, Serialize, Debug)]
 #[storage(VecStorage)]
 pub struct RandomRenderable {
     pub glyphs: String,
@@ -226,30 +243,23 @@ pub struct RandomRenderable {
     pub bg_colors: Option<Vec<(u8, u8, u8)>>,
 }
 
-#[derive(Component, Debug)]
-#[storage(HashMapStorage)]
-pub struct Defending {
-    pub damage_source: Entity,
-    pub damage_amount: i32,
-}
-
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct Floor;
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct OnFloor;
 
-#[derive(Component, Default, Deserialize, Clone, Debug)]
+#[derive(Component, Default, Deserialize, Serialize, Clone, Debug)]
 #[storage(NullStorage)]
 pub struct Camera;
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct InView;
 
-#[derive(Component, Clone, Debug, Deserialize)]
+#[derive(Component, Clone, Debug, Deserialize, Serialize)]
 #[storage(VecStorage)]
 pub struct Seeing {
     #[serde(default)]
@@ -277,37 +287,53 @@ impl Seeing {
     }
 }
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct Death;
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct Corpse;
 
-#[derive(Component, Default, Debug, Clone, Deserialize)]
-#[storage(NullStorage)]
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct BlockSight;
 
-#[derive(Component, Default, Debug, Clone, Deserialize)]
+impl Component for BlockSight {
+    type Storage = FlaggedStorage<Self, NullStorage<Self>>;
+}
+
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct Invulnerable;
 
-#[derive(Component, Default, Debug, Clone, Deserialize)]
-#[storage(NullStorage)]
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct BlockMovement;
 
-#[derive(Component, PartialEq, Default, Debug)]
+impl Component for BlockMovement {
+    type Storage = FlaggedStorage<Self, NullStorage<Self>>;
+}
+
+#[derive(Component, PartialEq, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct CanSeeTarget;
 
-#[derive(Component, PartialEq, Default, Debug)]
+#[derive(Component, PartialEq, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(NullStorage)]
 pub struct Carryable;
 
-#[derive(Component, Debug)]
+// takes whoever stands on it a level down or back up, `stairs: Some(Down)`
+#[derive(Component, Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[storage(DenseVecStorage)]
+pub enum Stairs {
+    Up,
+    Down,
+}
+
+// blueprints can only give out empty inventories, `inventory: Some(())`
+#[derive(Component, Debug, Default, Deserialize)]
 #[storage(VecStorage)]
 pub struct Inventory {
+    #[serde(skip)]
     pub items: Vec<Entity>,
 }
 
@@ -317,6 +343,12 @@ pub struct Body {
     core: BodyPart,
 }
 
+impl Default for Body {
+    fn default() -> Self {
+        Body::new()
+    }
+}
+
 impl Body {
     pub fn new() -> Self {
         let core = BodyPart {
@@ -330,104 +362,12 @@ impl Body {
         Body { core }
     }
 
-    // TODO: use something like JSON to store bodypart templates
-    pub fn make_humanoid() -> Self {
-        let mut core = BodyPart {
-            name: String::from("Core"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Core],
-            armor_tags: vec![ArmorTag::Core],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut head = BodyPart {
-            name: String::from("Head"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::ThoughtCenter, BodyPartTag::Limb],
-            armor_tags: vec![ArmorTag::Head, ArmorTag::Jewelry(3)],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut left_arm = BodyPart {
-            name: String::from("Left Arm"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Limb],
-            armor_tags: vec![ArmorTag::Arm],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut right_arm = BodyPart {
-            name: String::from("Right Arm"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Limb],
-            armor_tags: vec![ArmorTag::Arm],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut left_hand = BodyPart {
-            name: String::from("Left Hand"),
-            children: Vec::new(),
-            //MY GAME MY RULES
-            tags: vec![
-                BodyPartTag::Limb,
-                BodyPartTag::Grasping,
-                BodyPartTag::Dominant,
-            ],
-            armor_tags: vec![ArmorTag::Hand, ArmorTag::Jewelry(5)],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut right_hand = BodyPart {
-            name: String::from("Right Hand"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Limb, BodyPartTag::Grasping],
-            armor_tags: vec![ArmorTag::Head, ArmorTag::Jewelry(5)],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut left_leg = BodyPart {
-            name: String::from("Left Leg"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Limb, BodyPartTag::Mobility],
-            armor_tags: vec![ArmorTag::Head],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut right_leg = BodyPart {
-            name: String::from("Right Leg"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Limb, BodyPartTag::Mobility],
-            armor_tags: vec![ArmorTag::Head],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut left_foot = BodyPart {
-            name: String::from("Left Foot"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Limb],
-            armor_tags: vec![ArmorTag::Head],
-            equipped_armor: Vec::new(),
-        };
-
-        let mut right_foot = BodyPart {
-            name: String::from("Right Foot"),
-            children: Vec::new(),
-            tags: vec![BodyPartTag::Limb],
-            armor_tags: vec![ArmorTag::Head],
-            equipped_armor: Vec::new(),
-        };
-
-        left_arm.add_child(left_hand);
-        left_leg.add_child(left_foot);
-        right_arm.add_child(right_hand);
-        right_leg.add_child(right_foot);
-
-        core.add_child(head);
-        core.add_child(left_arm);
-        core.add_child(left_leg);
-        core.add_child(right_arm);
-        core.add_child(right_leg);
+    // a fresh body shaped like the plan, see `blueprints/bodies/`
+    pub fn from_plan(plan: &BodyPart) -> Self {
+        Body { core: plan.clone() }
+    }
 
-        Body { core }
+    pub fn core(&self) -> &BodyPart {
+        &self.core
     }
 }
@@ -1,7 +1,8 @@
 pub mod requests;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Deserialize, Serialize)]
 pub struct ActionResult {
     pub cost: u32,
 }
@@ -1,7 +1,8 @@
 use crate::systems::movement::Dir;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
 #[storage(VecStorage)]
 pub struct MoveRequest {
     pub dx: i32,
@@ -14,7 +15,7 @@ impl MoveRequest {
     }
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Deserialize, Serialize)]
 #[storage(HashMapStorage)]
 pub struct AttackRequest {
     pub dir: Dir,
@@ -25,3 +26,7 @@ impl AttackRequest {
         AttackRequest { dir }
     }
 }
+
+#[derive(Component, Default, Debug, Clone, Deserialize, Serialize)]
+#[storage(NullStorage)]
+pub struct PickupRequest;
@@ -1,9 +1,21 @@
 use serde::Deserialize;
-use std::fs::File;
-use std::io::prelude::*;
-use toml::{de::Error, Value};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize)]
+// where the game looks for its config when it isn't given one
+pub const DEFAULT_PATH: &str = "CONFIG.toml";
+
+// settings can also come from environment variables, `RUST_RL_MAP_WIDTH=120` sets `map_width`
+pub const ENV_PREFIX: &str = "RUST_RL_";
+
+// the game's settings, a resource. every field has a default, so a config file only needs the
+// ones it changes. they're layered: the defaults, then the file, then the environment, then
+// the command line
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub screen_width: i32,
     pub screen_height: i32,
@@ -23,16 +35,302 @@ pub struct Config {
     pub log_turn_start: bool,
 
     pub debug_vision: bool,
+
+    // the seed for every random thing in the game, a random one when left out. `--seed` on the
+    // command line overrides it
+    pub seed: Option<u64>,
+
+    // look for changed blueprint files while the game runs and load them
+    pub hot_reload_blueprints: bool,
+    // also push the changes onto entities already built from the changed blueprints
+    pub reapply_blueprints: bool,
+
+    // time every system and show the averages over the map, see `profiler`
+    pub profile: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            screen_width: 80,
+            screen_height: 33,
+            viewport_width: 80,
+            viewport_height: 25,
+            viewport_x: 0,
+            viewport_y: 0,
+            map_width: 80,
+            map_height: 25,
+            base_turn_time: 1000,
+            min_turn_time: 1,
+            log_turn_start: true,
+            debug_vision: false,
+            seed: None,
+            hot_reload_blueprints: false,
+            reapply_blueprints: false,
+            profile: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    UnknownSetting(String),
+    BadValue(String, String),
+    // settings that parse but can't be played with, like a viewport bigger than the screen
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read {}: {}", path, err),
+            ConfigError::Parse(path, err) => write!(f, "could not parse {}: {}", path, err),
+            ConfigError::UnknownSetting(key) => write!(f, "there's no setting called `{}`", key),
+            ConfigError::BadValue(key, value) => {
+                write!(f, "`{}` isn't a valid value for `{}`", value, key)
+            }
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
 }
 
 impl Config {
-    pub fn open() -> Self {
-        let mut file = File::open("CONFIG.toml").expect("Config file not found!");
-        let mut contents = String::new();
-        file.read_to_string(&mut contents);
-        println!("{}", contents);
+    // the settings in the toml file at `path`, with defaults for the ones it leaves out. not
+    // validated, the layers on top might fix what it gets wrong
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let contents =
+            fs::read_to_string(path).map_err(|err| ConfigError::Io(name.clone(), err))?;
+        toml::from_str(&contents).map_err(|err| ConfigError::Parse(name, err))
+    }
+
+    // like `open`, but the defaults if there's no file there at all
+    pub fn open_or_default<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        if path.as_ref().exists() {
+            Self::open(path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    // sets a field by name from text, the way it'd be written on the command line. `seed` can
+    // be set to `none` to go back to a random one
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+            value
+                .parse()
+                .map_err(|_| ConfigError::BadValue(String::from(key), String::from(value)))
+        }
+        // a width or height, at least 1
+        fn size(key: &str, value: &str) -> Result<i32, ConfigError> {
+            let size = parse(key, value)?;
+            check_size(key, size)?;
+            Ok(size)
+        }
+
+        match key {
+            "screen_width" => self.screen_width = size(key, value)?,
+            "screen_height" => self.screen_height = size(key, value)?,
+            "viewport_width" => self.viewport_width = size(key, value)?,
+            "viewport_height" => self.viewport_height = size(key, value)?,
+            "viewport_x" => self.viewport_x = parse(key, value)?,
+            "viewport_y" => self.viewport_y = parse(key, value)?,
+            "map_width" => self.map_width = size(key, value)?,
+            "map_height" => self.map_height = size(key, value)?,
+            "base_turn_time" => self.base_turn_time = parse(key, value)?,
+            "min_turn_time" => self.min_turn_time = parse(key, value)?,
+            "log_turn_start" => self.log_turn_start = parse(key, value)?,
+            "debug_vision" => self.debug_vision = parse(key, value)?,
+            "seed" if value == "none" => self.seed = None,
+            "seed" => self.seed = Some(parse(key, value)?),
+            "hot_reload_blueprints" => self.hot_reload_blueprints = parse(key, value)?,
+            "reapply_blueprints" => self.reapply_blueprints = parse(key, value)?,
+            "profile" => self.profile = parse(key, value)?,
+            _ => return Err(ConfigError::UnknownSetting(String::from(key))),
+        }
+        Ok(())
+    }
+
+    // `--map-width` and `map-width` are both `map_width`
+    pub fn setting_from_flag(flag: &str) -> String {
+        flag.trim_start_matches("--").replace('-', "_")
+    }
+
+    // overrides from every `RUST_RL_*` variable in the environment
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_vars(env::vars())
+    }
+
+    // `apply_env` with the variables given. other programs might use the prefix too, so a
+    // variable that isn't a setting is only warned about
+    fn apply_vars<I: IntoIterator<Item = (String, String)>>(
+        &mut self,
+        vars: I,
+    ) -> Result<(), ConfigError> {
+        for (var, value) in vars {
+            if let Some(key) = var.strip_prefix(ENV_PREFIX) {
+                match self.set(&key.to_lowercase(), &value) {
+                    Err(ConfigError::UnknownSetting(_)) => {
+                        warn!("ignoring {}, there's no setting called `{}`", var, key)
+                    }
+                    result => result?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // every size is at least 1 and the viewport fits on the screen. `set` only checks the one
+    // setting, the viewport and the screen can be changed in any order, so this goes after
+    // every layer is applied
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (key, size) in &[
+            ("screen_width", self.screen_width),
+            ("screen_height", self.screen_height),
+            ("viewport_width", self.viewport_width),
+            ("viewport_height", self.viewport_height),
+            ("map_width", self.map_width),
+            ("map_height", self.map_height),
+        ] {
+            check_size(key, *size)?;
+        }
+
+        if self.viewport_x < 0
+            || self.viewport_y < 0
+            || self.viewport_x + self.viewport_width > self.screen_width
+            || self.viewport_y + self.viewport_height > self.screen_height
+        {
+            return Err(ConfigError::Invalid(format!(
+                "the {}x{} viewport at {},{} doesn't fit on the {}x{} screen",
+                self.viewport_width,
+                self.viewport_height,
+                self.viewport_x,
+                self.viewport_y,
+                self.screen_width,
+                self.screen_height
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn check_size(key: &str, size: i32) -> Result<(), ConfigError> {
+    if size < 1 {
+        return Err(ConfigError::Invalid(format!(
+            "`{}` has to be at least 1, not {}",
+            key, size
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(var, value)| (String::from(*var), String::from(*value)))
+            .collect()
+    }
+
+    #[test]
+    fn the_defaults_are_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn sizes_have_to_be_positive() {
+        let mut config = Config::default();
+        for key in &["map_width", "map_height", "screen_width", "viewport_height"] {
+            for value in &["0", "-3"] {
+                match config.set(key, value) {
+                    Err(ConfigError::Invalid(_)) => (),
+                    other => panic!("{} = {} gave {:?}", key, value, other),
+                }
+            }
+        }
+        config.set("map_width", "120").unwrap();
+        assert_eq!(config.map_width, 120);
+        assert!(matches!(
+            config.set("map_width", "wide"),
+            Err(ConfigError::BadValue(..))
+        ));
+    }
+
+    #[test]
+    fn the_viewport_has_to_fit_on_the_screen() {
+        let mut config = Config::default();
+        config.set("viewport_width", "100").unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.set("screen_width", "100").unwrap();
+        config.validate().unwrap();
+
+        config.set("viewport_y", "10").unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn reads_settings_from_the_environment() {
+        let mut config = Config::default();
+        config
+            .apply_vars(vars(&[
+                ("RUST_RL_MAP_WIDTH", "120"),
+                ("RUST_RL_DEBUG_VISION", "true"),
+                ("RUST_RL_SEED", "42"),
+                // not ours
+                ("MAP_HEIGHT", "7"),
+                ("RUST_RL_NOT_A_SETTING", "1"),
+            ]))
+            .unwrap();
+        assert_eq!(config.map_width, 120);
+        assert_eq!(config.map_height, Config::default().map_height);
+        assert!(config.debug_vision);
+        assert_eq!(config.seed, Some(42));
 
-        let config: Config = toml::from_str(&contents).expect("Problem reading config");
         config
+            .apply_vars(vars(&[("RUST_RL_SEED", "none")]))
+            .unwrap();
+        assert_eq!(config.seed, None);
+        assert!(matches!(
+            config.apply_vars(vars(&[("RUST_RL_DEBUG_VISION", "maybe")])),
+            Err(ConfigError::BadValue(..))
+        ));
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let path = env::temp_dir().join(format!("rust_rl_config_{}.toml", process::id()));
+        // too big for the default screen on its own
+        fs::write(&path, "map_width = 100\nviewport_width = 100\nseed = 1\n").unwrap();
+        let config = Config::open(&path);
+        fs::remove_file(&path).unwrap();
+        let mut config = config.unwrap();
+        assert_eq!((config.map_width, config.seed), (100, Some(1)));
+        assert_eq!(config.map_height, Config::default().map_height);
+        assert!(config.validate().is_err());
+
+        config
+            .apply_vars(vars(&[
+                ("RUST_RL_MAP_WIDTH", "110"),
+                ("RUST_RL_SCREEN_WIDTH", "100"),
+            ]))
+            .unwrap();
+        config.set("seed", "2").unwrap();
+        config.set("map_width", "120").unwrap();
+        assert_eq!((config.map_width, config.seed), (120, Some(2)));
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn a_missing_file_is_only_fine_by_default() {
+        let path = env::temp_dir().join("rust_rl_no_such_config.toml");
+        assert!(matches!(Config::open(&path), Err(ConfigError::Io(..))));
+        let config = Config::open_or_default(&path).unwrap();
+        assert_eq!(config.map_width, Config::default().map_width);
     }
 }
@@ -0,0 +1,251 @@
+use crate::components::{Actor, Body, Inventory, MyTurn, PlayerControl, Position, Stairs};
+use crate::ecs::{GameState, MessageLog, State, StateHooks};
+use crate::entity_factory::value::Value;
+use crate::save::SaveRegistry;
+use crate::systems::render::{SeenTiles, Tile};
+use crate::terrain::Terrain;
+use crate::time::TurnQueue;
+use specs::prelude::*;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+// the level the player is on, and every level they've left, kept as they were left so going
+// back restores them
+pub struct Dungeon {
+    pub depth: u32,
+    // where the player is headed, set when they take the stairs
+    pub destination: Option<u32>,
+    // throw the level away and make a new one in its place, see `WizardCommand::Regenerate`
+    pub regenerate: bool,
+    levels: BTreeMap<u32, StoredLevel>,
+}
+
+// a level nobody is on. time stands still on it until the player comes back
+pub struct StoredLevel {
+    // the tick the player left at
+    pub left_at: u64,
+    // what the player remembers of it
+    pub seen: Vec<Tile>,
+    pub terrain: Terrain,
+    // everything on it, see `SaveRegistry::stash`
+    pub entities: Value,
+}
+
+impl Default for Dungeon {
+    fn default() -> Self {
+        Dungeon::new()
+    }
+}
+
+impl Dungeon {
+    pub fn new() -> Self {
+        Dungeon::with_levels(1, Vec::new())
+    }
+
+    pub fn with_levels(depth: u32, levels: Vec<(u32, StoredLevel)>) -> Self {
+        Dungeon {
+            depth,
+            destination: None,
+            regenerate: false,
+            levels: levels.into_iter().collect(),
+        }
+    }
+
+    pub fn levels(&self) -> impl Iterator<Item = (&u32, &StoredLevel)> {
+        self.levels.iter()
+    }
+}
+
+// the player and what they carry, everything that goes with them to another level
+fn carried(world: &World) -> HashSet<Entity> {
+    let entities = world.entities();
+    let players = world.read_storage::<PlayerControl>();
+    let inventories = world.read_storage::<Inventory>();
+    let bodies = world.read_storage::<Body>();
+
+    let mut carried = HashSet::new();
+    for (ent, _player) in (&entities, &players).join() {
+        carried.insert(ent);
+        if let Some(inventory) = inventories.get(ent) {
+            carried.extend(inventory.items.iter().cloned());
+        }
+        if let Some(body) = bodies.get(ent) {
+            body.core()
+                .walk(&mut |part| carried.extend(part.equipped_armor.iter().cloned()));
+        }
+    }
+    carried
+}
+
+// everything else on the level
+fn left_behind(world: &World, carried: &HashSet<Entity>) -> Vec<Entity> {
+    world
+        .entities()
+        .join()
+        .filter(|ent| !carried.contains(ent))
+        .collect()
+}
+
+// enter hook of `State::ChangeLevel`. stores the level the player is leaving and brings back the
+// one they're going to, or has `MapGen` make it if they've never been there. a level being
+// regenerated is thrown away instead
+pub struct LevelChange {
+    saves: Arc<SaveRegistry>,
+}
+
+impl LevelChange {
+    pub fn new(saves: Arc<SaveRegistry>) -> Self {
+        LevelChange { saves }
+    }
+
+    fn leave(&self, world: &mut World) {
+        let carried = carried(world);
+        let left = left_behind(world, &carried);
+
+        match self.saves.stash(world, left.clone()) {
+            Ok(entities) => {
+                let level = StoredLevel {
+                    left_at: world.read_resource::<GameState>().world_time.tick,
+                    seen: world
+                        .read_resource::<SeenTiles>()
+                        .tiles
+                        .items
+                        .iter()
+                        .filter_map(|tile| *tile)
+                        .collect(),
+                    terrain: world.write_resource::<Terrain>().take(),
+                    entities,
+                };
+                let mut dungeon = world.write_resource::<Dungeon>();
+                let depth = dungeon.depth;
+                dungeon.levels.insert(depth, level);
+            }
+            // the level is lost, but the player can carry on
+            Err(err) => error!("Could not store the level: {}", err),
+        }
+
+        Self::clear(world, &carried, &left);
+    }
+
+    // removes the level the player is on without storing it
+    fn discard(&self, world: &mut World) {
+        let carried = carried(world);
+        let left = left_behind(world, &carried);
+        Self::clear(world, &carried, &left);
+    }
+
+    // everything but what the player carries, `left`, goes
+    fn clear(world: &mut World, carried: &HashSet<Entity>, left: &[Entity]) {
+        world.delete_entities(left).ok();
+        world.write_resource::<SeenTiles>().tiles.reset_map();
+        world.write_resource::<Terrain>().clear();
+        {
+            let mut queue = world.write_resource::<TurnQueue>();
+            let turns: Vec<_> = queue
+                .drain()
+                .filter(|turn| carried.contains(&turn.entity))
+                .collect();
+            queue.extend(turns);
+        }
+        world.maintain();
+    }
+
+    // false if the level has to be generated
+    fn restore(&self, world: &mut World, depth: u32, arrived_by: Stairs) -> bool {
+        let level = match world.write_resource::<Dungeon>().levels.remove(&depth) {
+            Some(level) => level,
+            None => return false,
+        };
+        let entities = match self.saves.unstash(world, &level.entities) {
+            Ok(entities) => entities,
+            Err(err) => {
+                error!(
+                    "Could not restore level {}, making a new one: {}",
+                    depth, err
+                );
+                return false;
+            }
+        };
+
+        let now = world.read_resource::<GameState>().world_time.tick;
+        {
+            let mut actors = world.write_storage::<Actor>();
+            let mut my_turns = world.write_storage::<MyTurn>();
+            for ent in &entities {
+                if let Some(actor) = actors.get_mut(*ent) {
+                    actor.next_turn += now.saturating_sub(level.left_at);
+                }
+                my_turns.remove(*ent);
+            }
+        }
+        world.write_resource::<Terrain>().replace(level.terrain);
+        {
+            let mut seen_tiles = world.write_resource::<SeenTiles>();
+            for tile in level.seen {
+                seen_tiles
+                    .tiles
+                    .set_point(tile.position.x, tile.position.y, Some(tile));
+            }
+        }
+
+        // back where they left, on the other end of the stairs they took
+        let arrival = {
+            let stairs = world.read_storage::<Stairs>();
+            let positions = world.read_storage::<Position>();
+            entities.iter().find_map(|ent| match stairs.get(*ent) {
+                Some(kind) if *kind == arrived_by => positions.get(*ent).cloned(),
+                _ => None,
+            })
+        };
+        if let Some(arrival) = arrival {
+            let players = world.read_storage::<PlayerControl>();
+            let mut positions = world.write_storage::<Position>();
+            for (pos, _player) in (&mut positions, &players).join() {
+                *pos = arrival;
+            }
+        }
+        world.maintain();
+        true
+    }
+}
+
+impl StateHooks for LevelChange {
+    fn enter(&mut self, world: &mut World) {
+        let (from, to, regenerate) = {
+            let mut dungeon = world.write_resource::<Dungeon>();
+            let regenerate = std::mem::replace(&mut dungeon.regenerate, false);
+            (dungeon.depth, dungeon.destination.take(), regenerate)
+        };
+        if regenerate {
+            self.discard(world);
+            world
+                .write_resource::<GameState>()
+                .transition(State::MapGen);
+            return;
+        }
+
+        let to = match to {
+            Some(to) if to != from => to,
+            _ => {
+                world
+                    .write_resource::<GameState>()
+                    .transition(State::TurnProcess);
+                return;
+            }
+        };
+
+        self.leave(world);
+        world.write_resource::<Dungeon>().depth = to;
+        let arrived_by = if to > from { Stairs::Up } else { Stairs::Down };
+        let next = match self.restore(world, to, arrived_by) {
+            true => State::TurnProcess,
+            false => State::MapGen,
+        };
+        world.write_resource::<GameState>().transition(next);
+
+        let verb = if to > from { "descend" } else { "climb" };
+        world
+            .write_resource::<MessageLog>()
+            .log(format!("You {} to depth {}.", verb, to));
+    }
+}
@@ -1,56 +1,175 @@
 
-use crate::components::{Position, Renderable};
-use crate::entity_factory::{EntityBlueprint, EntityFactory, EntityLoadQueue};
+use crate::components::Position;
+use crate::backend::{Keyboard, Screen, TcodBackend};
+use crate::config::Config;
+use crate::dungeon::{Dungeon, LevelChange};
+use crate::entity_factory::{BlueprintError, EntityFactory, EntityLoadQueue};
+use crate::events;
 use crate::map;
+use crate::profiler::{Profiler, ProfiledBuilder, DISPATCH, PROFILE_PATH};
+use crate::replay::{Recorder, Replay, Settings, RECORDING_PATH};
+use crate::rng::GameRng;
+use crate::save::{SaveRegistry, AUTOSAVE_PATH};
 use crate::systems;
-use crate::systems::render::Tile;
+use crate::systems::menu::{Menu, OpenMenu};
+use crate::terrain::{Terrain, TerrainKinds};
 use crate::time;
-use crate::CONFIG;
-use rltk::RandomNumberGenerator;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use std::sync::{Arc, Mutex};
-use systems::render::LayeredTileMap;
-use tcod::console::*;
+use systems::render::{LayeredTileMap, SeenTiles};
 use tcod::map::Map as TcodMap;
 use vecmap::*;
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
 use shrev::EventChannel;
 use crate::command::CommandEvent;
-use tcod::input::Key;
+use crate::backend::Key;
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
 pub enum State {
     MapGen,
     TurnProcess,
     PlayerTurn,
+    // passed through on the way to another level, see `dungeon::LevelChange`
+    ChangeLevel,
+    MainMenu,
+    Paused,
+    Inventory,
+    Targeting,
+    // the wizard console, see `systems::wizard`
+    Console,
+    GameOver,
 }
 
+impl State {
+    // drawn over the states beneath it, which are kept as they were until it's popped
+    pub fn is_modal(self) -> bool {
+        matches!(
+            self,
+            State::MainMenu
+                | State::Paused
+                | State::Inventory
+                | State::Targeting
+                | State::Console
+                | State::GameOver
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Transition {
+    Push(State),
+    Pop,
+    Switch(State),
+}
+
+// the states are a stack, only the one on top runs. changes are queued and made by `Ecs`
+// between frames, so every system in a frame sees the same state and the hooks get the world
 pub struct GameState {
-    current_state: State,
+    stack: Vec<State>,
+    pending: Vec<Transition>,
     pub game_end: bool,
     pub world_time: time::WorldTime,
 }
 
 impl GameState {
+    pub fn new(state: State) -> Self {
+        GameState {
+            stack: vec![state],
+            pending: Vec::new(),
+            game_end: false,
+            world_time: time::WorldTime::new(),
+        }
+    }
+
+    // replaces the game state, under any menus that are open over it, so turns can carry on
+    // changing it in the frame a menu is opened
     pub fn transition(&mut self, state: State) {
 //        println!("{:?}", state);
-        self.current_state = state;
+        self.pending.push(Transition::Switch(state));
+    }
+
+    pub fn push(&mut self, state: State) {
+        self.pending.push(Transition::Push(state));
+    }
+
+    pub fn pop(&mut self) {
+        self.pending.push(Transition::Pop);
     }
 
     pub fn current(&self) -> State {
-        self.current_state
+        *self.stack.last().expect("the state stack is empty")
+    }
+
+    pub fn stack(&self) -> &[State] {
+        &self.stack
+    }
+
+    // the game underneath any menus, what gets saved
+    pub fn resumable(&self) -> State {
+        self.stack
+            .iter()
+            .rev()
+            .find(|state| !state.is_modal())
+            .cloned()
+            .unwrap_or(State::MapGen)
     }
+
+    // throws away the stack and anything queued, without running any hooks
+    pub fn reset(&mut self, state: State) {
+        self.stack = vec![state];
+        self.pending.clear();
+    }
+
+    fn take_pending(&mut self) -> Vec<Transition> {
+        std::mem::take(&mut self.pending)
+    }
+
+    // the states that were left and entered
+    fn apply(&mut self, transition: Transition) -> (Option<State>, Option<State>) {
+        match transition {
+            Transition::Push(state) => {
+                self.stack.push(state);
+                (None, Some(state))
+            }
+            Transition::Pop => {
+                if self.stack.len() < 2 {
+                    error!("Tried to pop the last state, {:?}", self.current());
+                    return (None, None);
+                }
+                (self.stack.pop(), None)
+            }
+            Transition::Switch(state) => {
+                let i = self
+                    .stack
+                    .iter()
+                    .rposition(|state| !state.is_modal())
+                    .unwrap_or(self.stack.len() - 1);
+                let exited = std::mem::replace(&mut self.stack[i], state);
+                (Some(exited), Some(state))
+            }
+        }
+    }
+}
+
+// run when a state is pushed or switched to, and when it's popped or switched away from. a
+// state covered by another one is neither exited nor entered again
+pub trait StateHooks: Send {
+    fn enter(&mut self, _world: &mut World) {}
+    fn exit(&mut self, _world: &mut World) {}
 }
 
+#[derive(Default)]
 pub struct MessageLog {
     pub messages: Vec<String>,
 }
 
 impl MessageLog {
     pub fn new() -> Self {
-        MessageLog {
-            messages: Vec::new(),
-        }
+        MessageLog::default()
     }
 
     pub fn log(&mut self, string: String) {
@@ -65,46 +184,189 @@ impl MessageLog {
     }
 }
 
+// how often to look for edited blueprint files when hot reloading
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct Ecs {
     world: World,
     dispatchers: HashMap<State, Dispatcher<'static, 'static>>,
+    hooks: HashMap<State, Box<dyn StateHooks>>,
+    factory: EntityFactory,
+    saves: Arc<SaveRegistry>,
+    last_reload_check: Instant,
+    // only with `profile = true`
+    profiler: Option<Arc<Profiler>>,
 }
 
 impl Ecs {
     pub fn main_loop(&mut self) {
-        let mut factory = EntityFactory::new("blueprints");
-        let mut current_state = None;
-        loop {
-            self.world.maintain();
-            let mut blueprints: Vec<EntityBlueprint> = Vec::new();
-            self.build_blueprints(&mut blueprints, &mut factory);
-            {
-                let game_state = self.world.read_resource::<GameState>();
-                let mut root = self.world.write_resource::<Root>();
-
+        // pick up where the last game left off
+        let autosave = Path::new(AUTOSAVE_PATH);
+        let mut from_save = false;
+        if autosave.exists() {
+            match self.saves.load(&mut self.world, autosave) {
+                Ok(()) => {
+                    println!("Loaded {}", autosave.display());
+                    from_save = true;
+                }
+                Err(err) => eprintln!("{}", err),
+            }
+        }
 
-                root.flush();
+        let seed = self.world.read_resource::<GameRng>().seed();
+        let settings = Settings::from_config(&self.world.read_resource::<Config>());
+        match Recorder::create(Path::new(RECORDING_PATH), seed, from_save, settings) {
+            Ok(recorder) => self.world.insert(recorder),
+            Err(err) => eprintln!("{}: {}", RECORDING_PATH, err),
+        }
 
-                if root.window_closed() || game_state.game_end {
-                    break;
+        self.world
+            .write_resource::<GameState>()
+            .push(State::MainMenu);
+        while self.step() {}
+        self.write_profile();
+
+        // a finished game isn't carried on from
+        if self.world.read_resource::<GameState>().current() == State::GameOver {
+            if autosave.exists() {
+                if let Err(err) = fs::remove_file(autosave) {
+                    eprintln!("{}: {}", autosave.display(), err);
                 }
+            }
+            return;
+        }
 
-                current_state = Some(game_state.current_state);
+        match self.saves.save(&self.world, autosave) {
+            Ok(()) => println!("Saved {}", autosave.display()),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    // plays a recording back from the start of a game set up with its seed, then hands control
+    // back to the player. the autosave is left alone, and nothing is recorded
+    pub fn replay(&mut self, replay: Replay) {
+        self.world.insert(replay);
+        while self.step() {}
+        self.write_profile();
+    }
+
+    // runs one frame of the current state's systems. false once the window is closed or the
+    // game has ended, in which case nothing was run
+    pub fn step(&mut self) -> bool {
+        self.world.maintain();
+        if self.world.read_resource::<Config>().hot_reload_blueprints
+            && self.last_reload_check.elapsed() >= RELOAD_CHECK_INTERVAL
+        {
+            self.reload_blueprints();
+            self.last_reload_check = Instant::now();
+        }
+        self.build_blueprints();
+        self.apply_transitions();
+        let current_state = {
+            let game_state = self.world.read_resource::<GameState>();
+            let mut screen = self.world.write_resource::<Screen>();
+
+            screen.flush();
+
+            if screen.is_closed() || game_state.game_end {
+                return false;
             }
-            let mut dispatcher = self.dispatchers
-                .get_mut(&current_state.unwrap())
-                .expect("Could not get dispatcher for state");
 
-            dispatcher.dispatch(&mut self.world);
+            game_state.current()
+        };
+        let dispatcher = self
+            .dispatchers
+            .get_mut(&current_state)
+            .expect("Could not get dispatcher for state");
+
+        let start = Instant::now();
+        dispatcher.dispatch(&self.world);
+        if let Some(profiler) = &self.profiler {
+            profiler.record(&format!("{:?}", current_state), DISPATCH, start.elapsed());
+        }
+        true
+    }
+
+    // the timings so far, to `PROFILE_PATH`. nothing without a profiler
+    pub fn write_profile(&self) {
+        if let Some(profiler) = &self.profiler {
+            match profiler.write_summary(PROFILE_PATH) {
+                Ok(()) => println!("Wrote {}", PROFILE_PATH),
+                Err(err) => eprintln!("{}: {}", PROFILE_PATH, err),
+            }
+        }
+    }
+
+    // makes the state changes queued since the last frame, running their hooks. a hook can
+    // queue more, which are made right after
+    pub fn apply_transitions(&mut self) {
+        loop {
+            let pending = self.world.write_resource::<GameState>().take_pending();
+            if pending.is_empty() {
+                return;
+            }
+            for transition in pending {
+                let (exited, entered) = self.world.write_resource::<GameState>().apply(transition);
+                let hooks = &mut self.hooks;
+                if let Some(hooks) = exited.and_then(|state| hooks.get_mut(&state)) {
+                    hooks.exit(&mut self.world);
+                }
+                if let Some(hooks) = entered.and_then(|state| hooks.get_mut(&state)) {
+                    hooks.enter(&mut self.world);
+                }
+            }
+        }
+    }
+
+    // builds a blueprint or prefab right away instead of through the `EntityLoadQueue`
+    pub fn spawn(&mut self, name: &str, pos: Option<Position>) -> Vec<Entity> {
+        self.factory.spawn(String::from(name), &mut self.world, pos)
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn reload_blueprints(&mut self) {
+        let factory = &mut self.factory;
+        if !factory.changed_on_disk() {
+            return;
+        }
+
+        match factory.reload() {
+            Ok(changes) => {
+                self.world.insert(factory.index().clone());
+                let terrain_kinds = TerrainKinds::from_factory(factory);
+                let mut message = format!("Reloaded blueprints, {} changed", changes.len());
+                if self.world.read_resource::<Config>().reapply_blueprints {
+                    let updated = factory.reapply(&mut self.world, &changes);
+                    let terrain = self
+                        .world
+                        .write_resource::<Terrain>()
+                        .reapply(&terrain_kinds);
+                    message = format!(
+                        "{}, {} entities and {} kinds of terrain updated",
+                        message, updated, terrain
+                    );
+                }
+                self.world.insert(terrain_kinds);
+                println!("{}", message);
+                self.world.write_resource::<MessageLog>().log(message);
+            }
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", err);
+                }
+            }
         }
     }
 
-    pub fn build_blueprints(
-        &mut self,
-        blueprints: &mut Vec<EntityBlueprint>,
-        factory: &mut EntityFactory,
-    ) {
-        let mut blueprint_queue = &mut self.world.write_resource::<EntityLoadQueue>().clone();
+    pub fn build_blueprints(&mut self) {
+        let blueprint_queue = &mut self.world.write_resource::<EntityLoadQueue>().clone();
 
         if blueprint_queue.is_empty() {
             return;
@@ -112,38 +374,76 @@ impl Ecs {
         println!("# of blueprints to build: {}", blueprint_queue.len());
         for _ in 0..blueprint_queue.len() {
             let blueprint = blueprint_queue.pop().unwrap();
-            factory.build(blueprint.0, &mut self.world, blueprint.1);
+            self.factory.spawn(blueprint.0, &mut self.world, blueprint.1);
         }
 
         self.world.write_resource::<EntityLoadQueue>().clear();
     }
 }
 
-//pub struct
-pub fn world_setup<'a, 'b>() -> Ecs {
-    //    println!("{:?}", CONFIG);
+// the game in a tcod window. without a seed in the config, a random one is used
+pub fn world_setup(config: Config) -> Result<Ecs, Vec<BlueprintError>> {
+    let seed = config.seed.unwrap_or_else(GameRng::random_seed);
+    println!("Seed: {}", seed);
+    let backend = TcodBackend::open(config.screen_width, config.screen_height);
+    world_setup_with(
+        Screen(Box::new(backend.clone())),
+        Keyboard(Box::new(backend)),
+        seed,
+        config,
+    )
+}
+
+// the game drawing to and reading keys from any backend, e.g. `Headless` to run it without a
+// window. the same seed and the same keys play out the same game
+pub fn world_setup_with(
+    screen: Screen,
+    keyboard: Keyboard,
+    seed: u64,
+    config: Config,
+) -> Result<Ecs, Vec<BlueprintError>> {
+    let (map_width, map_height) = (config.map_width, config.map_height);
+    let profiler = match config.profile {
+        true => Some(Arc::new(Profiler::new())),
+        false => None,
+    };
+    let profiler = profiler.as_ref();
     let mut world = World::new();
+    // before the systems are set up, some of them size things by it
+    world.insert(config);
 
     let mut dispatchers = HashMap::new();
 
-    let map_gen_dispatcher = DispatcherBuilder::new()
+    let map_gen_dispatcher = ProfiledBuilder::new(State::MapGen, profiler)
         .with(systems::mapgen::MapGen::new(), "map_gen_sys", &[])
         .build();
 
-    let player_turn_dispatcher = DispatcherBuilder::new()
+    let player_turn_dispatcher = ProfiledBuilder::new(State::PlayerTurn, profiler)
         .with(systems::render::RandomRender, "random_render_sys", &[])
         .with(systems::input::InputListener, "input_listener_sys", &[])
+        .with(systems::spatial::SpatialIndexer::new(), "spatial_index_sys", &[])
         .with(
             systems::input::Input::new(),
             "input_sys",
-            &["input_listener_sys"],
+            &["input_listener_sys", "spatial_index_sys"],
         )
         .with(
             systems::render::RenderViewport::new(),
             "render_viewport_sys",
-            &[],
+            &["spatial_index_sys"],
+        )
+        .with(
+            systems::replay::ReplayInput::new(),
+            "replay_input_sys",
+            &["render_viewport_sys"],
         )
         .with(systems::render::RenderUi, "render_ui_sys", &[])
+        .with(
+            systems::inspector::RenderInspector,
+            "render_inspector_sys",
+            &["render_ui_sys"],
+        )
+        .with_overlay(&["render_inspector_sys"])
         .with(systems::naming::Naming, "naming_sys", &[])
         .with(systems::actor_setup::ActorSetup, "actor_setup_sys", &[])
         .with(
@@ -158,10 +458,11 @@ pub fn world_setup<'a, 'b>() -> Ecs {
             "action_sys",
             &["ai_sys"],
         )
+        .with(systems::wizard::Wizard::new(), "wizard_sys", &[])
         .with(systems::time::EndTurn, "end_turn_sys", &[])
         .build();
 
-    let turn_process_dispatcher = DispatcherBuilder::new()
+    let turn_process_dispatcher = ProfiledBuilder::new(State::TurnProcess, profiler)
         .with(systems::render::RandomRender, "random_render_sys", &[])
         .with(systems::input::InputListener, "input_listener_sys", &[])
         .with(
@@ -174,7 +475,18 @@ pub fn world_setup<'a, 'b>() -> Ecs {
             "render_viewport_sys",
             &[],
         )
+        .with(
+            systems::replay::ReplayInput::new(),
+            "replay_input_sys",
+            &["render_viewport_sys"],
+        )
         .with(systems::render::RenderUi, "render_ui_sys", &[])
+        .with(
+            systems::inspector::RenderInspector,
+            "render_inspector_sys",
+            &["render_ui_sys"],
+        )
+        .with_overlay(&["render_inspector_sys"])
         .with(systems::naming::Naming, "naming_sys", &[])
         .with(systems::actor_setup::ActorSetup, "actor_setup_sys", &[])
         .with(
@@ -196,13 +508,39 @@ pub fn world_setup<'a, 'b>() -> Ecs {
             "movement_sys",
             &["action_sys", "collision_map_updater_sys"],
         )
+        .with(
+            systems::spatial::SpatialIndexer::new(),
+            "spatial_index_sys",
+            &["movement_sys"],
+        )
         .with(systems::combat::DeathSystem, "death_sys", &[])
         .with(
             systems::combat::Attack,
             "attack_sys",
-            &["death_sys", "movement_sys", "action_sys"],
+            &["death_sys", "movement_sys", "action_sys", "spatial_index_sys"],
+        )
+        .with(systems::combat::Defend::new(), "defend_sys", &["attack_sys"])
+        .with(
+            systems::items::Pickup,
+            "pickup_sys",
+            &["movement_sys", "action_sys", "spatial_index_sys"],
+        )
+        .with(
+            systems::ai::perception::Perception::new(),
+            "perception_sys",
+            &["movement_sys", "attack_sys"],
+        )
+        .with(
+            systems::event_log::EventLog::new(),
+            "event_log_sys",
+            &["defend_sys", "pickup_sys"],
+        )
+        .with(
+            systems::statistics::StatisticsKeeper::new(),
+            "statistics_sys",
+            &["defend_sys", "pickup_sys"],
         )
-        .with(systems::combat::Defend, "defend_sys", &["attack_sys"])
+        .with(systems::effects::EffectsSystem::new(), "effects_sys", &["defend_sys"])
         //        .with_barrier()
         .with(systems::time::EndTurn, "end_turn_sys", &[])
         .build();
@@ -210,56 +548,103 @@ pub fn world_setup<'a, 'b>() -> Ecs {
     dispatchers.insert(State::MapGen, map_gen_dispatcher);
     dispatchers.insert(State::TurnProcess, turn_process_dispatcher);
     dispatchers.insert(State::PlayerTurn, player_turn_dispatcher);
+    dispatchers.insert(
+        State::MainMenu,
+        modal_dispatcher(
+            State::MainMenu,
+            profiler,
+            systems::menu::MenuInput,
+            systems::menu::RenderMenu,
+        ),
+    );
+    dispatchers.insert(
+        State::Paused,
+        modal_dispatcher(
+            State::Paused,
+            profiler,
+            systems::menu::MenuInput,
+            systems::menu::RenderMenu,
+        ),
+    );
+    dispatchers.insert(
+        State::Inventory,
+        modal_dispatcher(
+            State::Inventory,
+            profiler,
+            systems::menu::MenuInput,
+            systems::menu::RenderMenu,
+        ),
+    );
+    dispatchers.insert(
+        State::GameOver,
+        modal_dispatcher(
+            State::GameOver,
+            profiler,
+            systems::menu::MenuInput,
+            systems::menu::RenderMenu,
+        ),
+    );
+    dispatchers.insert(
+        State::Targeting,
+        modal_dispatcher(
+            State::Targeting,
+            profiler,
+            systems::targeting::TargetingInput,
+            systems::targeting::RenderTargeting,
+        ),
+    );
+    dispatchers.insert(
+        State::Console,
+        modal_dispatcher(
+            State::Console,
+            profiler,
+            systems::wizard::ConsoleInput,
+            systems::wizard::RenderConsole,
+        ),
+    );
+
+    let mut hooks: HashMap<State, Box<dyn StateHooks>> = HashMap::new();
+    hooks.insert(State::MainMenu, Box::new(OpenMenu(Menu::main)));
+    hooks.insert(State::Paused, Box::new(OpenMenu(Menu::paused)));
+    hooks.insert(State::Inventory, Box::new(OpenMenu(Menu::inventory)));
+    hooks.insert(State::GameOver, Box::new(OpenMenu(Menu::game_over)));
+    hooks.insert(State::Targeting, Box::new(systems::targeting::TargetingHooks));
+    hooks.insert(State::Console, Box::new(systems::wizard::ConsoleHooks));
+    let saves = Arc::new(SaveRegistry::default());
+    hooks.insert(State::ChangeLevel, Box::new(LevelChange::new(saves.clone())));
 
 
     for dispatcher in dispatchers.values_mut() {
         dispatcher.setup(&mut world);
     }
 
-    let world_time = time::WorldTime::new();
-    let game_state = GameState {
-        current_state: State::MapGen,
-        game_end: false,
-        world_time,
-    };
+    let game_state = GameState::new(State::MapGen);
 
     let view = map::View {
-        map: Arc::new(Mutex::new(TcodMap::new(
-            CONFIG.map_width,
-            CONFIG.map_height,
-        ))),
-        block_map: VecMap::filled_with(
-            map::BlockTile::default(),
-            CONFIG.map_width,
-            CONFIG.map_height,
-        ),
+        map: Arc::new(Mutex::new(TcodMap::new(map_width, map_height))),
+        block_map: VecMap::filled_with(map::BlockTile::default(), map_width, map_height),
     };
-    let map = map::EntityMap::new(CONFIG.map_width as usize, CONFIG.map_height as usize);
+    let map = map::EntityMap::new(map_width as usize, map_height as usize);
     let message_log = MessageLog::new();
-    let root = Root::initializer()
-        .size(CONFIG.screen_width, CONFIG.screen_height)
-        .font("term.png", FontLayout::AsciiInRow)
-        .init();
 
     world.insert(game_state);
     world.insert(map);
     world.insert(view);
     world.insert(message_log);
     world.insert(time::TurnQueue::new());
-    world.insert(LayeredTileMap::new(CONFIG.map_width, CONFIG.map_height));
-    world.insert(RandomNumberGenerator::new());
-    world.insert(root);
+    world.insert(LayeredTileMap::new(map_width, map_height));
+    world.insert(Terrain::new(map_width, map_height));
+    world.insert(map::SpatialIndex::new(map_width, map_height));
+    world.insert(SeenTiles::new(map_width, map_height));
+    world.insert(GameRng::new(seed));
+    world.insert(screen);
+    world.insert(keyboard);
     world.insert(EntityLoadQueue::new());
+    world.insert(Dungeon::new());
 
-    // insert event channels
-    let command_event_channel: EventChannel<CommandEvent> = EventChannel::new();
-
-    world.insert(command_event_channel);
-
-    // insert readers
+    // insert readers. the command channel is already there, `Wizard` reads it too
     let key_reader = world.fetch_mut::<EventChannel<Key>>().register_reader();
-    let command_event_reader = world.fetch_mut::<EventChannel<CommandEvent>>()
-        .register_reader();
+    let command_event_reader = events::reader::<CommandEvent>(&mut world);
 
     world.insert(command_event_reader);
     world.insert(key_reader);
@@ -270,6 +655,131 @@ pub fn world_setup<'a, 'b>() -> Ecs {
 //
 //    dispatcher.dispatch(&mut world);
 
-    Ecs { world, dispatchers }
+    let factory = EntityFactory::new("blueprints")?;
+    factory.setup(&mut world);
+    world.insert(factory.index().clone());
+    world.insert(TerrainKinds::from_factory(&factory));
+
+    saves.setup(&mut world);
+
+    Ok(Ecs {
+        world,
+        dispatchers,
+        hooks,
+        factory,
+        saves,
+        last_reload_check: Instant::now(),
+        profiler: profiler.cloned(),
+    })
 }
 
+// a state drawn over the map. it shows the map and messages as they were, takes the keys for
+// itself, and draws its own overlay on top
+fn modal_dispatcher<I, O>(
+    state: State,
+    profiler: Option<&Arc<Profiler>>,
+    input: I,
+    overlay: O,
+) -> Dispatcher<'static, 'static>
+where
+    I: for<'a> System<'a> + Send + 'static,
+    O: for<'a> System<'a> + Send + 'static,
+{
+    ProfiledBuilder::new(state, profiler)
+        .with(systems::input::InputListener, "input_listener_sys", &[])
+        .with(systems::spatial::SpatialIndexer::new(), "spatial_index_sys", &[])
+        .with(input, "input_sys", &["input_listener_sys"])
+        .with(
+            systems::render::RenderViewport::new(),
+            "render_viewport_sys",
+            &["spatial_index_sys"],
+        )
+        .with(systems::render::RenderUi, "render_ui_sys", &["render_viewport_sys"])
+        .with(
+            systems::inspector::RenderInspector,
+            "render_inspector_sys",
+            &["render_ui_sys"],
+        )
+        .with_overlay(&["render_inspector_sys"])
+        .with(overlay, "render_overlay_sys", &["render_inspector_sys"])
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::headless::Headless;
+    use std::sync::{Arc, Mutex};
+
+    // writes down every time its state is entered or exited
+    struct LogHooks(State, Arc<Mutex<Vec<String>>>);
+
+    impl StateHooks for LogHooks {
+        fn enter(&mut self, _world: &mut World) {
+            self.1.lock().unwrap().push(format!("enter {:?}", self.0));
+        }
+
+        fn exit(&mut self, _world: &mut World) {
+            self.1.lock().unwrap().push(format!("exit {:?}", self.0));
+        }
+    }
+
+    #[test]
+    fn runs_hooks_in_order() {
+        let config = Config::default();
+        let headless = Headless::new(config.screen_width, config.screen_height);
+        let mut ecs = world_setup_with(headless.screen(), headless.keyboard(), 1, config).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let states = [
+            State::TurnProcess,
+            State::PlayerTurn,
+            State::Paused,
+            State::Inventory,
+        ];
+        for &state in &states {
+            ecs.hooks
+                .insert(state, Box::new(LogHooks(state, log.clone())));
+        }
+        ecs.world
+            .write_resource::<GameState>()
+            .reset(State::PlayerTurn);
+
+        {
+            let mut game_state = ecs.world.write_resource::<GameState>();
+            game_state.push(State::Paused);
+            game_state.push(State::Inventory);
+            // the game carries on under the menus
+            game_state.transition(State::TurnProcess);
+            // nothing happens until the transitions are applied
+            assert_eq!(game_state.current(), State::PlayerTurn);
+        }
+        ecs.apply_transitions();
+        assert_eq!(
+            ecs.world.read_resource::<GameState>().stack(),
+            [State::TurnProcess, State::Paused, State::Inventory]
+        );
+
+        {
+            let mut game_state = ecs.world.write_resource::<GameState>();
+            game_state.pop();
+            game_state.pop();
+            // the last state can't be popped
+            game_state.pop();
+        }
+        ecs.apply_transitions();
+        assert_eq!(
+            ecs.world.read_resource::<GameState>().stack(),
+            [State::TurnProcess]
+        );
+
+        let expected = [
+            "enter Paused",
+            "enter Inventory",
+            "exit PlayerTurn",
+            "enter TurnProcess",
+            "exit Inventory",
+            "exit Paused",
+        ];
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+}
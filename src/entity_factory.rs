@@ -1,37 +1,351 @@
+// blueprints are ron files under `blueprints/`, named by their path ("creatures/human.ron"
+// is "creatures.human"). a blueprint can name a parent with `extends: Some("base_object")`,
+// or several parents and mixins with `extends: ["creatures.base_monster", "traits.undead"]`.
+//
+// parents are applied left to right, each one right after its own ancestors, and the blueprint
+// itself goes last, so later parents override earlier ones. an ancestor shared by several
+// parents (a diamond) is only applied once, at the first place it shows up. a blueprint marked
+// `mixin: true` is only meant to be extended: it doesn't need to be a complete entity and
+// can't be built on its own. `extends` and `mixin` aren't inherited.
+//
+// each blueprint is merged on top of what came before it field by field:
+//
+// - a component the child doesn't mention is inherited as is
+// - `component: Some((field: value))` only changes the fields written out, so
+//   `corporeal: Some((hp: 5))` keeps the parent's `max_hp` and `base_damage`
+// - `component: None` removes a component the parent had, e.g. `ai_control: None`
+// - lists, tuples and plain values (colors, glyphs, numbers) are replaced whole
+//
+// a file with a `members` list is a prefab, a group of blueprints spawned together (see
+// `prefab.rs`). a file with a `core` body part tree is a body plan (see `body_plan.rs`).
+//
+// blueprints and prefabs can also carry tags and numbers for spawning code to query instead of
+// naming blueprints itself, see `query.rs`.
+//
+// each key other than `extends`, `mixin` and those names a component registered in the factory's
+// `ComponentRegistry` (see `registry.rs`).
+//
+// the factory can load the tree again while the game runs (`changed_on_disk`, `reload`) and
+// push what changed onto live entities (`reapply`). every entity it builds gets a
+// `FromBlueprint` so it can be found again.
+//
+// number fields can also be rolled fresh for every entity built, with dice (`hp: "2d6+3"`) or
+// an inclusive range (`strength: (8, 12)`). see `dice.rs`.
+
+use crate::bodyparts::BodyPart;
 use crate::components::*;
-use ron::de::from_reader;
+use crate::events::SpawnEvent;
+use crate::rng::{GameRng, Stream};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use shrev::EventChannel;
 use specs::prelude::*;
-use specs::Builder;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs;
-use std::fs::FileType;
-use std::fs::{read_dir, File};
-use std::io::prelude::*;
-use std::io::Read;
+use std::mem;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::time::SystemTime;
+
+pub mod body_plan;
+pub mod dice;
+pub mod error;
+pub mod export;
+pub mod prefab;
+pub mod query;
+pub mod registry;
+pub mod value;
+
+use body_plan::BodyPlans;
+use dice::Roll;
+pub use error::BlueprintError;
+pub use export::{ResolvedBlueprint, ResolvedComponent};
+pub use prefab::{Prefab, PrefabMember};
+pub use query::{BlueprintIndex, BlueprintMeta, BlueprintQuery};
+pub use registry::ComponentRegistry;
+use value::Value;
 
 pub type EntityLoadQueue = Vec<(String, Option<Position>)>;
 
 #[derive(Debug, Clone)]
 struct BlueprintStorage {
+    // the blueprint with all of its parents applied
     pub blueprint: Option<EntityBlueprint>,
+    // the blueprint exactly as written in its file
+    pub raw: Option<Value>,
     pub path: PathBuf,
 }
 
 pub struct EntityFactory {
     blueprints: HashMap<String, BlueprintStorage>,
+    prefabs: HashMap<String, Prefab>,
+    index: BlueprintIndex,
+    body_plans: BodyPlans,
+    registry: ComponentRegistry,
+    // the blueprint directory, and when each file in it was last written when it was loaded
+    path: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+// the components of one blueprint (or prefab member) that differ after a reload
+#[derive(Clone, Debug)]
+pub struct BlueprintChange {
+    pub source: FromBlueprint,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
 }
 
 impl EntityFactory {
-    pub fn new(path: &str) -> Self {
+    pub fn new(path: &str) -> Result<Self, Vec<BlueprintError>> {
+        Self::with_registry(path, ComponentRegistry::default())
+    }
+
+    pub fn with_registry(
+        path: &str,
+        registry: ComponentRegistry,
+    ) -> Result<Self, Vec<BlueprintError>> {
+        let (factory, errors) = Self::load_tree(PathBuf::from(path), registry);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(factory)
+    }
+
+    // the factory comes back even when there were errors, so `reload` can take its registry
+    // back out of it
+    fn load_tree(path: PathBuf, mut registry: ComponentRegistry) -> (Self, Vec<BlueprintError>) {
+        let body_plans = BodyPlans::default();
+        register_body(&mut registry, &body_plans);
+
         let mut factory = EntityFactory {
             blueprints: HashMap::new(),
+            prefabs: HashMap::new(),
+            index: BlueprintIndex::default(),
+            body_plans,
+            registry,
+            modified: modified_times(&path),
+            path: path.clone(),
         };
-        let path = PathBuf::from(path);
-        factory.build_map(&path);
-        factory
+        let errors = factory.build_map(&path);
+        (factory, errors)
+    }
+
+    // whether a file under the blueprint directory was written, added or removed since the
+    // last load
+    pub fn changed_on_disk(&self) -> bool {
+        modified_times(&self.path) != self.modified
+    }
+
+    // loads the whole blueprint directory again and returns what changed. nothing is replaced
+    // unless everything loads, so a half saved file can't break a running game
+    pub fn reload(&mut self) -> Result<Vec<BlueprintChange>, Vec<BlueprintError>> {
+        let registry = mem::replace(&mut self.registry, ComponentRegistry::new());
+        let (fresh, errors) = Self::load_tree(self.path.clone(), registry);
+        if !errors.is_empty() {
+            self.registry = fresh.registry;
+            register_body(&mut self.registry, &self.body_plans);
+            // don't try again until the next save
+            self.modified = fresh.modified;
+            return Err(errors);
+        }
+
+        let mut changes = Vec::new();
+        for (name, storage) in &fresh.blueprints {
+            let source = FromBlueprint {
+                blueprint: name.clone(),
+                prefab: None,
+            };
+            if let (Some(old), Some(new)) = (self.resolved(&source), &storage.blueprint) {
+                changes.extend(old.diff(new, source));
+            }
+        }
+        for (name, prefab) in &fresh.prefabs {
+            for (i, member) in prefab.members.iter().enumerate() {
+                let source = FromBlueprint {
+                    blueprint: member.blueprint.clone(),
+                    prefab: Some((name.clone(), i)),
+                };
+                if let Some(old) = self.resolved(&source) {
+                    changes.extend(old.diff(&member.resolved, source));
+                }
+            }
+        }
+
+        *self = fresh;
+        Ok(changes)
+    }
+
+    // puts changed components back onto every live entity built from a changed blueprint.
+    // changed components are built again from scratch, rolls included. positions are left
+    // alone since things move. returns how many entities were updated
+    pub fn reapply(&self, world: &mut World, changes: &[BlueprintChange]) -> usize {
+        let targets: Vec<(Entity, &BlueprintChange)> = {
+            let entities = world.entities();
+            let sources = world.read_storage::<FromBlueprint>();
+            (&entities, &sources)
+                .join()
+                .filter_map(|(entity, source)| {
+                    let change = changes.iter().find(|change| change.source == *source)?;
+                    Some((entity, change))
+                })
+                .collect()
+        };
+
+        for (entity, change) in &targets {
+            let blueprint = match self.resolved(&change.source) {
+                Some(blueprint) => blueprint,
+                None => continue,
+            };
+            let mut rng = world.write_resource::<GameRng>();
+            let rng = RefCell::new(rng.stream(Stream::Rolls));
+            let roller = |roll: &Roll| roll.roll(&mut **rng.borrow_mut());
+
+            for key in change.changed.iter().filter(|key| *key != "position") {
+                let value = match blueprint.value(key) {
+                    Some(value) => value.clone(),
+                    None => continue,
+                };
+                if let Err(err) = self.registry.insert(key, value, &roller, *entity, world) {
+                    error!("could not reapply `{}` to {:?}: {}", key, entity, err);
+                }
+            }
+            for key in change.removed.iter().filter(|key| *key != "position") {
+                self.registry.remove(key, *entity, world);
+            }
+        }
+        targets.len()
+    }
+
+    // the blueprint an entity was built from, overrides included for prefab members
+    pub fn resolved(&self, source: &FromBlueprint) -> Option<&EntityBlueprint> {
+        match &source.prefab {
+            Some((prefab, i)) => Some(&self.prefabs.get(prefab)?.members.get(*i)?.resolved),
+            None => self.blueprints.get(&source.blueprint)?.blueprint.as_ref(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blueprints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blueprints.is_empty()
+    }
+
+    pub fn blueprint(&self, name: &str) -> Option<&EntityBlueprint> {
+        self.blueprints.get(name)?.blueprint.as_ref()
+    }
+
+    // every blueprint that can be built, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .blueprints
+            .iter()
+            .filter(|(_, storage)| storage.blueprint.is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    // the blueprint with all of its parents applied, along with the files each of its
+    // components came from
+    pub fn resolve(&self, name: &str) -> Result<ResolvedBlueprint, BlueprintError> {
+        let mut order = Vec::new();
+        self.linearize(name, &mut Vec::new(), &mut order)?;
+
+        let mut sources: Vec<(String, Vec<PathBuf>)> = Vec::new();
+        for ancestor in &order {
+            let storage = &self.blueprints[ancestor];
+            let fields = match &storage.raw {
+                Some(Value::Struct(_, fields)) => fields,
+                _ => continue,
+            };
+            for (key, value) in fields {
+                if is_blueprint_key(key) {
+                    continue;
+                }
+                let index = match sources.iter().position(|(k, _)| k == key) {
+                    Some(index) => index,
+                    None => {
+                        sources.push((key.clone(), Vec::new()));
+                        sources.len() - 1
+                    }
+                };
+                let paths = &mut sources[index].1;
+                // mirrors `Value::merge`, only structs and maps keep what came before them
+                let value = match value {
+                    Value::Option(Some(value)) => value.as_ref(),
+                    value => value,
+                };
+                match value {
+                    Value::Struct(..) | Value::Map(..) | Value::Unit => (),
+                    _ => paths.clear(),
+                }
+                paths.push(storage.path.clone());
+            }
+        }
+
+        let path = self.blueprints[name].path.clone();
+        let value = self.load(name)?;
+        let meta = query::META_KEYS
+            .iter()
+            .filter_map(|key| Some((String::from(*key), value.field(key)?.clone())))
+            .collect();
+        let blueprint = EntityBlueprint::from_value(value, &self.registry).map_err(|message| {
+            BlueprintError::Parse {
+                path: path.clone(),
+                position: None,
+                message,
+            }
+        })?;
+        let components = blueprint
+            .components
+            .into_iter()
+            .map(|(key, value)| ResolvedComponent {
+                sources: sources
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, paths)| paths.clone())
+                    .unwrap_or_default(),
+                key,
+                value,
+            })
+            .collect();
+
+        Ok(ResolvedBlueprint {
+            name: String::from(name),
+            path,
+            meta,
+            components,
+        })
+    }
+
+    // tags and numbers of everything that can be spawned, blueprints and prefabs both
+    pub fn index(&self) -> &BlueprintIndex {
+        &self.index
+    }
+
+    pub fn query(&self) -> BlueprintQuery<'_> {
+        self.index.query()
+    }
+
+    pub fn body_plans(&self) -> &BodyPlans {
+        &self.body_plans
+    }
+
+    pub fn prefab(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn registry(&self) -> &ComponentRegistry {
+        &self.registry
+    }
+
+    // has to run once before anything is built
+    pub fn setup(&self, world: &mut World) {
+        self.registry.setup(world);
+        world.register::<FromBlueprint>();
     }
 
     pub fn build(
@@ -41,164 +355,731 @@ impl EntityFactory {
         pos: Option<Position>,
     ) -> Option<Entity> {
         if !self.blueprints.contains_key(&name) {
-            //            println!("Could not build blueprint: {}", &name);
             return None;
         }
 
-        let mut blueprint_entry = self.blueprints.get_mut(&name);
-        if let Some(mut blueprint_storage) = blueprint_entry {
-            if let Some(blueprint) = &blueprint_storage.blueprint {
-                let mut blueprint = blueprint.clone();
-                blueprint.position = pos;
-                //                println!{"BUILDING: {:?}", &name}
-                let entity = blueprint.build(world);
-                return Some(entity);
-            }
+        let blueprint = self.blueprints.get(&name)?.blueprint.as_ref()?;
+        let source = FromBlueprint {
+            blueprint: name,
+            prefab: None,
+        };
+        self.build_blueprint(source, blueprint, world, pos)
+    }
+
+    // builds every member of the prefab, offset from `anchor`. members that fail to build are
+    // left out
+    pub fn build_prefab(&mut self, name: &str, world: &mut World, anchor: Position) -> Vec<Entity> {
+        let prefab = match self.prefabs.get(name) {
+            Some(prefab) => prefab,
+            None => return Vec::new(),
+        };
+
+        prefab
+            .members
+            .iter()
+            .enumerate()
+            .filter_map(|(i, member)| {
+                let pos = member.position(anchor);
+                let source = FromBlueprint {
+                    blueprint: member.blueprint.clone(),
+                    prefab: Some((String::from(name), i)),
+                };
+                self.build_blueprint(source, &member.resolved, world, Some(pos))
+            })
+            .collect()
+    }
+
+    // builds a blueprint or a whole prefab, whichever `name` is. a prefab without a position
+    // is built around (0, 0)
+    pub fn spawn(&mut self, name: String, world: &mut World, pos: Option<Position>) -> Vec<Entity> {
+        if self.prefabs.contains_key(&name) {
+            let anchor = pos.unwrap_or_else(|| Position::new(0, 0));
+            return self.build_prefab(&name, world, anchor);
         }
-        None
+        self.build(name, world, pos).into_iter().collect()
     }
 
-    fn build_map(&mut self, path: &PathBuf) {
-        let file_paths = get_blueprint_paths(path);
+    fn build_blueprint(
+        &self,
+        source: FromBlueprint,
+        blueprint: &EntityBlueprint,
+        world: &mut World,
+        pos: Option<Position>,
+    ) -> Option<Entity> {
+        let entity = world.create_entity().build();
+        let built = {
+            let mut rng = world.write_resource::<GameRng>();
+            let rng = RefCell::new(rng.stream(Stream::Rolls));
+            let roller = |roll: &Roll| roll.roll(&mut **rng.borrow_mut());
+            blueprint.insert(&self.registry, &roller, entity, world)
+        };
+
+        if let Err(err) = built {
+            error!("could not build blueprint {}: {}", source.blueprint, err);
+            world.delete_entity(entity).ok();
+            return None;
+        }
+        if let Some(pos) = pos {
+            world.write_storage::<Position>().insert(entity, pos).ok();
+        }
+        // the world of a tool like `validate-blueprints` has nobody listening
+        if let Some(mut spawns) = world.try_fetch_mut::<EventChannel<SpawnEvent>>() {
+            spawns.single_write(SpawnEvent {
+                entity,
+                blueprint: source.blueprint.clone(),
+            });
+        }
+        world
+            .write_storage::<FromBlueprint>()
+            .insert(entity, source)
+            .ok();
+        Some(entity)
+    }
+
+    // reads every file under `path` and resolves its `extends` chain. returns every problem
+    // found instead of stopping at the first one, so a single run reports the whole tree.
+    fn build_map(&mut self, path: &Path) -> Vec<BlueprintError> {
+        let mut errors = Vec::new();
+        let file_paths = match get_blueprint_paths(path) {
+            Ok(file_paths) => file_paths,
+            Err(err) => return vec![err],
+        };
         let mut names = Vec::new();
+        let mut prefab_files = Vec::new();
 
-        // make empty map of blueprints
+        // make map of unresolved blueprints
         for ref entry in file_paths {
-            let name = format_path_name(entry).split_off((path.to_string_lossy().len() + 1)); // remove topmost parent name from formatted name (e.g. "blueprints.")
+            let relative_path = entry.strip_prefix(path).unwrap_or(entry).to_path_buf(); // remove topmost parent from name (e.g. "blueprints.")
+            let name = format_path_name(&relative_path);
+            let raw = match parse_blueprint(entry, &self.registry) {
+                Ok(blueprint) => Some(blueprint),
+                Err(mut file_errors) => {
+                    errors.append(&mut file_errors);
+                    None
+                }
+            };
+            if let Some(raw) = raw.as_ref().filter(|raw| raw.field("members").is_some()) {
+                prefab_files.push((name, raw.clone(), entry.clone()));
+                continue;
+            }
+            if let Some(raw) = raw.as_ref().filter(|raw| raw.field("core").is_some()) {
+                match load_body_plan(raw, entry) {
+                    Ok(core) => self.body_plans.insert(name, core),
+                    Err(err) => errors.push(err),
+                }
+                continue;
+            }
             names.push(name.clone());
             let storage = BlueprintStorage {
                 blueprint: None,
+                raw,
                 path: entry.clone(),
             };
 
             self.blueprints.insert(name, storage);
         }
 
+        names.sort();
         for name in names {
-            let mut storage = self.blueprints.get_mut(&name);
-            if let Some(storage) = storage {
-                let mut storage = storage.clone();
-                let blueprint = self.load(name.clone());
-                storage.blueprint = Some(blueprint.clone());
-                self.blueprints.insert(name, storage);
+            // files that failed to parse have already been reported
+            if self.blueprints[&name].raw.is_none() {
+                continue;
+            }
+
+            let resolved = self.load(&name).and_then(|value| {
+                if value.field("mixin") == Some(&Value::Bool(true)) {
+                    return Ok(None);
+                }
+                EntityBlueprint::from_value(value, &self.registry)
+                    .map(Some)
+                    .map_err(|err| BlueprintError::Parse {
+                        path: self.blueprints[&name].path.clone(),
+                        position: None,
+                        message: err.to_string(),
+                    })
+            });
+
+            match resolved {
+                Ok(blueprint) => {
+                    if let Some(storage) = self.blueprints.get_mut(&name) {
+                        storage.blueprint = blueprint;
+                    }
+                }
+                Err(err) => {
+                    // a broken ancestor fails every descendant the same way, only report it once
+                    let message = err.to_string();
+                    if !errors.iter().any(|e| e.to_string() == message) {
+                        errors.push(err);
+                    }
+                }
+            }
+        }
+
+        // prefabs go last, their members have to be resolved first
+        let prefab_names: Vec<String> =
+            prefab_files.iter().map(|(name, ..)| name.clone()).collect();
+        for (name, raw, path) in prefab_files {
+            match self.load_prefab(&raw, &path, &prefab_names) {
+                Ok(prefab) => {
+                    self.prefabs.insert(name, prefab);
+                }
+                Err(mut prefab_errors) => errors.append(&mut prefab_errors),
             }
         }
+
+        for (name, storage) in &self.blueprints {
+            if let Some(blueprint) = &storage.blueprint {
+                self.index.insert(name.clone(), blueprint.meta.clone());
+            }
+        }
+        for (name, prefab) in &self.prefabs {
+            self.index.insert(name.clone(), prefab.meta.clone());
+        }
+        errors
     }
+
+    fn load_prefab(
+        &self,
+        raw: &Value,
+        path: &Path,
+        prefab_names: &[String],
+    ) -> Result<Prefab, Vec<BlueprintError>> {
+        let error = |message: String| BlueprintError::Parse {
+            path: path.to_path_buf(),
+            position: None,
+            message,
+        };
+
+        if let Value::Struct(_, fields) = raw {
+            let not_allowed =
+                |key: &String| key != "members" && !query::META_KEYS.contains(&key.as_str());
+            if let Some((key, _)) = fields.iter().find(|(key, _)| not_allowed(key)) {
+                return Err(vec![error(format!(
+                    "prefabs can only have `members`, tags and properties, found `{}`",
+                    key
+                ))]);
+            }
+        }
+        let members = match raw.field("members") {
+            Some(Value::List(members)) => members,
+            _ => return Err(vec![error(String::from("`members` must be a list"))]),
+        };
+
+        let meta = BlueprintMeta::from_value(raw)
+            .map_err(|err| vec![error(format!("bad tags or properties: {}", err))])?;
+
+        let mut errors = Vec::new();
+        let mut prefab = Prefab {
+            members: Vec::new(),
+            meta,
+        };
+        for (i, member) in members.iter().enumerate() {
+            match self.load_prefab_member(member, prefab_names) {
+                Ok(member) => prefab.members.push(member),
+                Err(message) => errors.push(error(format!("member {}: {}", i + 1, message))),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(prefab)
+    }
+
+    fn load_prefab_member(
+        &self,
+        member: &Value,
+        prefab_names: &[String],
+    ) -> Result<PrefabMember, String> {
+        if let Value::Struct(_, fields) = member {
+            for (key, _) in fields {
+                if !prefab::MEMBER_KEYS.contains(&key.as_str()) {
+                    return Err(format!("unknown prefab member key `{}`", key));
+                }
+            }
+        }
+
+        let blueprint: String = member
+            .field("blueprint")
+            .ok_or_else(|| String::from("missing `blueprint`"))?
+            .clone()
+            .into_rust()
+            .map_err(|err| format!("`blueprint`: {}", err))?;
+        let offset: (i32, i32) = match member.field("offset") {
+            Some(offset) => offset
+                .clone()
+                .into_rust()
+                .map_err(|err| format!("`offset`: {}", err))?,
+            None => (0, 0),
+        };
+
+        if prefab_names.contains(&blueprint) {
+            return Err(format!(
+                "`{}` is a prefab, members have to be blueprints",
+                blueprint
+            ));
+        }
+        match self.blueprints.get(&blueprint) {
+            None => return Err(format!("no blueprint named `{}`", blueprint)),
+            Some(storage) if storage.blueprint.is_none() => {
+                return Err(format!("blueprint `{}` can't be built", blueprint))
+            }
+            _ => (),
+        }
+
+        let mut value = self.load(&blueprint).map_err(|err| err.to_string())?;
+        if let Some(overrides) = member.field("overrides") {
+            value = value.merge(overrides.clone());
+        }
+        let resolved = EntityBlueprint::from_value(value, &self.registry)?;
+
+        Ok(PrefabMember {
+            blueprint,
+            offset,
+            resolved,
+        })
+    }
+
+    // merges the blueprint on top of all of its ancestors
+    fn load(&self, name: &str) -> Result<Value, BlueprintError> {
+        let mut order = Vec::new();
+        self.linearize(name, &mut Vec::new(), &mut order)?;
+
+        let mut merged = Value::Struct(None, Vec::new());
+        // tags add up instead of replacing each other
+        let mut tags = Vec::new();
+        for ancestor in order {
+            let mut blueprint = match &self.blueprints[&ancestor].raw {
+                Some(blueprint) => blueprint.clone(),
+                None => continue,
+            };
+            if ancestor != name {
+                for key in NOT_INHERITED {
+                    blueprint.remove_field(key);
+                }
+            }
+            if let Some(Value::List(ancestor_tags)) = blueprint.field("tags") {
+                for tag in ancestor_tags {
+                    if !tags.contains(tag) {
+                        tags.push(tag.clone());
+                    }
+                }
+            }
+            merged = merged.merge(blueprint);
+        }
+        if !tags.is_empty() {
+            merged.remove_field("tags");
+            merged = merged.merge(Value::Struct(
+                None,
+                vec![(String::from("tags"), Value::List(tags))],
+            ));
+        }
+        Ok(merged)
+    }
+
+    // pushes the blueprint's ancestors onto `order` in the order they get applied, followed
+    // by the blueprint itself. ancestors already in `order` are skipped, which is what keeps
+    // a diamond from applying its shared ancestor twice.
+    // `chain` holds the names already visited on the way here, for cycle detection
+    fn linearize(
+        &self,
+        name: &str,
+        chain: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), BlueprintError> {
+        if let Some(start) = chain.iter().position(|visited| visited == name) {
+            return Err(BlueprintError::cycle(&chain[start..]));
+        }
+
+        if order.iter().any(|applied| applied == name) {
+            return Ok(());
+        }
+
+        let storage = self
+            .blueprints
+            .get(name)
+            .ok_or_else(|| BlueprintError::NotFound {
+                name: String::from(name),
+            })?;
+        let blueprint = match &storage.raw {
+            Some(blueprint) => blueprint,
+            None => {
+                return Err(BlueprintError::NotFound {
+                    name: String::from(name),
+                })
+            }
+        };
+        debug!("LOADING: {:?}", storage.path);
+
+        chain.push(String::from(name));
+        for parent_name in parent_names(blueprint, &storage.path)? {
+            debug!("^ EXTENDS: {:?}", parent_name);
+            match self.blueprints.get(&parent_name) {
+                None => {
+                    return Err(BlueprintError::MissingParent {
+                        path: storage.path.clone(),
+                        parent: parent_name,
+                    })
+                }
+                Some(parent) if parent.raw.is_none() => {
+                    return Err(BlueprintError::BrokenParent {
+                        path: storage.path.clone(),
+                        parent: parent_name,
+                    })
+                }
+                _ => (),
+            }
+            self.linearize(&parent_name, chain, order)?;
+        }
+        chain.pop();
+
+        order.push(String::from(name));
+        Ok(())
+    }
+}
+
+// `extends` and `mixin` describe the file they're written in, not the blueprints extending it
+const NOT_INHERITED: &[&str] = &["extends", "mixin"];
+
+// top level keys that aren't components, along with `query::META_KEYS`
+const BLUEPRINT_KEYS: &[&str] = &["extends", "mixin", "members", "core"];
+
+fn is_blueprint_key(key: &str) -> bool {
+    BLUEPRINT_KEYS.contains(&key) || query::META_KEYS.contains(&key)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Extends {
+    Single(String),
+    List(Vec<String>),
+}
+
+impl Extends {
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            Extends::Single(name) => vec![name.clone()],
+            Extends::List(names) => names.clone(),
+        }
+    }
+}
+
+fn parent_names(blueprint: &Value, path: &Path) -> Result<Vec<String>, BlueprintError> {
+    let extends = match blueprint.field("extends") {
+        Some(extends) => extends.clone(),
+        None => return Ok(Vec::new()),
+    };
+
+    let extends: Option<Extends> = extends.into_rust().map_err(|err| BlueprintError::Parse {
+        path: path.to_path_buf(),
+        position: None,
+        message: format!(
+            "`extends` must be a blueprint name or a list of them: {}",
+            err
+        ),
+    })?;
+
+    Ok(extends.map(|extends| extends.names()).unwrap_or_default())
+}
+
+fn register_body(registry: &mut ComponentRegistry, body_plans: &BodyPlans) {
+    let plans = body_plans.clone();
+    registry.register_with("body", move |name: String| plans.build(&name));
+}
+
+fn modified_times(path: &Path) -> HashMap<PathBuf, SystemTime> {
+    get_blueprint_paths(path)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+            Some((path, modified.ok()?))
+        })
+        .collect()
 }
 
-fn get_blueprint_paths(path_buf: &PathBuf) -> Vec<PathBuf> {
-    //    println!("{:?}", path);
+fn load_body_plan(raw: &Value, path: &Path) -> Result<BodyPart, BlueprintError> {
+    let error = |message: String| BlueprintError::Parse {
+        path: path.to_path_buf(),
+        position: None,
+        message,
+    };
+
+    if let Value::Struct(_, fields) = raw {
+        if let Some((key, _)) = fields.iter().find(|(key, _)| key != "core") {
+            return Err(error(format!(
+                "body plans can only have `core`, found `{}`",
+                key
+            )));
+        }
+    }
+    raw.field("core")
+        .cloned()
+        .unwrap_or(Value::Unit)
+        .into_rust()
+        .map_err(|err| error(format!("`core`: {}", err)))
+}
+
+fn get_blueprint_paths(path_buf: &Path) -> Result<Vec<PathBuf>, BlueprintError> {
     let mut paths = Vec::new();
-    for entry in fs::read_dir(path_buf).expect(&format!("Problem reading path: {:?}", path_buf)) {
-        if let Ok(entry) = entry {
-            let ref path = entry.path();
-            let metadata = entry
-                .metadata()
-                .expect(&format!("Problem reading file metadata: {:?}", path));
+    let entries = fs::read_dir(path_buf).map_err(|error| BlueprintError::Io {
+        path: path_buf.to_path_buf(),
+        error,
+    })?;
 
-            if metadata.is_dir() {
-                paths.extend(get_blueprint_paths(path))
-            } else if metadata.is_file() {
-                //                    println!("{}", &formatted_path_name);
-                paths.push(path.clone());
-            }
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let metadata = entry.metadata().map_err(|error| BlueprintError::Io {
+            path: path.clone(),
+            error,
+        })?;
+
+        if metadata.is_dir() {
+            paths.extend(get_blueprint_paths(&path)?)
+        } else if metadata.is_file() {
+            paths.push(path);
         }
     }
-    paths
+    Ok(paths)
 }
 
-fn format_path_name(path: &PathBuf) -> String {
+fn format_path_name(path: &Path) -> String {
     let mut path_name = String::new();
     for ancestor in path.ancestors() {
-        match ancestor.file_stem() {
-            Some(ancestor_name) => {
-                if path_name.is_empty() {
-                    path_name = String::from(ancestor_name.to_string_lossy());
-                } else if ancestor.parent() != None {
-                    path_name = format!("{}.{}", ancestor_name.to_string_lossy(), path_name);
-                }
+        if let Some(ancestor_name) = ancestor.file_stem() {
+            if path_name.is_empty() {
+                path_name = String::from(ancestor_name.to_string_lossy());
+            } else if ancestor.parent().is_some() {
+                path_name = format!("{}.{}", ancestor_name.to_string_lossy(), path_name);
             }
-            None => (),
         }
     }
-    println!("REGISTERING NAME: {:?}", path_name);
+    debug!("REGISTERING NAME: {:?}", path_name);
     path_name
 }
 
-#[macro_export]
-macro_rules! entity_blueprint_template {
-    {
-        $($compname:ident: $comptype:ty,)+
-    } => {
-        #[derive(Clone, Debug, Deserialize, Default)]
-        pub struct EntityBlueprint {
-            pub extends: Option<String>,
-            $(
-                #[serde(default)]
-                pub $compname: Option<$comptype>
-            ),+
+fn parse_blueprint(
+    path: &Path,
+    registry: &ComponentRegistry,
+) -> Result<Value, Vec<BlueprintError>> {
+    let source = fs::read_to_string(path).map_err(|error| {
+        vec![BlueprintError::Io {
+            path: path.to_path_buf(),
+            error,
+        }]
+    })?;
+
+    let blueprint = match value::from_str(&source) {
+        Ok(Value::Unit) => Value::Struct(None, Vec::new()),
+        Ok(blueprint @ Value::Struct(..)) => blueprint,
+        Ok(_) => {
+            return Err(vec![BlueprintError::Parse {
+                path: path.to_path_buf(),
+                position: None,
+                message: String::from("expected a blueprint struct"),
+            }])
+        }
+        Err(err) => {
+            return Err(vec![BlueprintError::Parse {
+                path: path.to_path_buf(),
+                position: err.position,
+                message: err.message,
+            }])
         }
+    };
 
-        impl EntityBlueprint {
-            pub fn load(path: &PathBuf) -> Self {
-                let mut file = File::open(path)
-                    .expect(&format!("blueprint file not found: {:?}", path));
+    // serde would silently skip a misspelled component
+    let mut unknown_keys = Vec::new();
+    if let Value::Struct(_, fields) = &blueprint {
+        for (key, _) in fields {
+            if !is_blueprint_key(key) && !registry.contains(key) {
+                unknown_keys.push(BlueprintError::UnknownComponent {
+                    path: path.to_path_buf(),
+                    key: key.clone(),
+                    position: find_key(&source, key),
+                });
+            }
+        }
+    }
 
-                let mut blueprint: Self = from_reader(file).expect(&format!("could not create blueprint: {:?}", path));
+    if !unknown_keys.is_empty() {
+        return Err(unknown_keys);
+    }
+    Ok(blueprint)
+}
 
-                // recursively apply parent blueprints
-                blueprint
+// blueprint values don't keep positions, so look for the first `key:` in the source instead
+fn find_key(source: &str, key: &str) -> Option<(usize, usize)> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    for (line, text) in source.lines().enumerate() {
+        for (col, _) in text.match_indices(key) {
+            let before = text[..col].chars().last();
+            let after = text[col + key.len()..].trim_start();
+            if !before.is_some_and(is_ident) && after.starts_with(':') {
+                return Some((line + 1, col + 1));
             }
+        }
+    }
+    None
+}
 
-            pub fn build(&self, world: &mut World) -> Entity {
-                let mut builder = world.create_entity();
-                $(
-                    if let Some(c) = self.$compname.clone() {
-                        builder = builder.with(c);
-                    }
-                )+
-                builder.build()
-            }
-        }
-
-        impl EntityFactory {
-            fn load(&mut self, name: String) -> EntityBlueprint {
-                let ref path = self.blueprints.get(&name).expect("Could not load blueprint").path;
-                let mut file = File::open(path)
-                    .expect(&format!("blueprint file not found: {:?}", path));
-                let mut blueprint: EntityBlueprint = from_reader(file).expect(&format!("could not create blueprint: {:?}", path));
-                println!("LOADING: {:?}", path);
-                // recursively apply child blueprints on top of parent
-                if let Some(name) = blueprint.extends {
-                    println!("^ EXTENDS: {:?}", name);
-                    let mut parent =  self.load(name);
-                    $(
-                        if let Some(c) = blueprint.$compname {
-                        parent.$compname = Some(c);
-                    };
-                    )+
-                    return parent
-                }
-                blueprint
+// a fully resolved blueprint, every component it builds with all of its parents applied.
+// dice and ranges are left unrolled until the blueprint is built
+#[derive(Clone, Debug)]
+pub struct EntityBlueprint {
+    pub components: Vec<(String, Value)>,
+    pub meta: BlueprintMeta,
+}
+
+impl EntityBlueprint {
+    // checks every component against the registry, so mistakes show up when loading instead
+    // of the first time something gets built
+    fn from_value(value: Value, registry: &ComponentRegistry) -> Result<Self, String> {
+        let meta = BlueprintMeta::from_value(&value)
+            .map_err(|err| format!("bad tags or properties: {}", err))?;
+        let fields = match value {
+            Value::Struct(_, fields) => fields,
+            _ => return Err(String::from("expected a blueprint struct")),
+        };
+
+        let mut components = Vec::new();
+        for (key, value) in fields {
+            if is_blueprint_key(&key) {
+                continue;
             }
+            let value = match value {
+                Value::Option(None) => continue,
+                Value::Option(Some(value)) => *value,
+                value => value,
+            };
+            registry
+                .check(&key, value.clone())
+                .map_err(|err| format!("`{}`: {}", key, err))?;
+            components.push((key, value));
+        }
+        Ok(EntityBlueprint { components, meta })
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.components.iter().any(|(name, _)| name == key)
+    }
+
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        let (_, value) = self.components.iter().find(|(name, _)| name == key)?;
+        Some(value)
+    }
+
+    // the components that differ in `new`, or `None` if it's all the same
+    fn diff(&self, new: &EntityBlueprint, source: FromBlueprint) -> Option<BlueprintChange> {
+        let changed: Vec<String> = new
+            .components
+            .iter()
+            .filter(|(key, value)| self.value(key) != Some(value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let removed: Vec<String> = self
+            .components
+            .iter()
+            .filter(|(key, _)| !new.has(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        if changed.is_empty() && removed.is_empty() {
+            return None;
         }
+        Some(BlueprintChange {
+            source,
+            changed,
+            removed,
+        })
+    }
+
+    // the component under `key`, with every roll at its lowest
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.value(key)?.clone().into_rust().ok()
+    }
+
+    fn insert(
+        &self,
+        registry: &ComponentRegistry,
+        roller: &dyn Fn(&Roll) -> i64,
+        entity: Entity,
+        world: &World,
+    ) -> Result<(), String> {
+        for (key, value) in &self.components {
+            registry
+                .insert(key, value.clone(), roller, entity, world)
+                .map_err(|err| format!("`{}`: {}", key, err))?;
+        }
+        Ok(())
     }
 }
 
-entity_blueprint_template! {
-    name: Name,
-    actor: Actor,
-    player: PlayerControl,
-    camera: Camera,
-    renderable: Renderable,
-    random_renderable: RandomRenderable,
-    corporeal: Corporeal,
-    seeing: Seeing,
-    ai_control: AiControl,
-    mobile: Mobile,
-    position: Position,
-    invulnerable: Invulnerable,
-    blocks_movement: BlockMovement,
-    blocks_sight: BlockSight,
-    elevation: Elevation,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // keeps whatever loaded, so broken fixtures can still be inspected
+    fn factory(path: &str) -> EntityFactory {
+        let registry = ComponentRegistry::default();
+        let (factory, _) = EntityFactory::load_tree(PathBuf::from(path), registry);
+        factory
+    }
+
+    fn linearize(factory: &EntityFactory, name: &str) -> Result<Vec<String>, BlueprintError> {
+        let mut order = Vec::new();
+        factory.linearize(name, &mut Vec::new(), &mut order)?;
+        Ok(order)
+    }
+
+    #[test]
+    fn applies_a_shared_ancestor_once() {
+        let factory = factory("tests/fixtures/diamond");
+        let order = linearize(&factory, "bottom").unwrap();
+        assert_eq!(order, ["base", "left", "right", "bottom"]);
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let factory = factory("tests/fixtures/cycle");
+        match linearize(&factory, "egg") {
+            Err(BlueprintError::Cycle { chain }) => {
+                assert_eq!(chain, ["chicken", "egg", "chicken"])
+            }
+            result => panic!("expected a cycle, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn later_parents_override_earlier_ones() {
+        let factory = factory("tests/fixtures/diamond");
+        let bottom = factory.blueprint("bottom").unwrap();
+        // `right` is a mixin listed after the parent `left`
+        assert_eq!(bottom.get::<Name>("name").unwrap().name, "Right");
+        let left = factory.blueprint("left").unwrap();
+        assert_eq!(left.get::<Name>("name").unwrap().name, "Left");
+    }
+
+    #[test]
+    fn extends_and_mixin_are_not_inherited() {
+        let factory = factory("tests/fixtures/diamond");
+        assert!(factory.blueprint("right").is_none());
+        assert!(factory.blueprint("bottom").is_some());
+
+        // `right` is a mixin and extends `base`, neither of which ends up in the merged value
+        let merged = factory.load("bottom").unwrap();
+        assert_eq!(merged.field("mixin"), None);
+        let path = Path::new("bottom.ron");
+        assert_eq!(parent_names(&merged, path).unwrap(), ["left", "right"]);
+    }
+
+    #[test]
+    fn body_plans_only_have_a_core() {
+        let path = Path::new("bodies/blob.ron");
+        let plan = value::from_str(r#"(core: (name: "Blob"))"#).unwrap();
+        assert_eq!(load_body_plan(&plan, path).unwrap().name, "Blob");
+
+        let plan = value::from_str(r#"(core: (name: "Blob"), legs: 4)"#).unwrap();
+        assert!(load_body_plan(&plan, path).is_err());
+    }
 }
@@ -0,0 +1,47 @@
+use crate::bodyparts::BodyPart;
+use crate::components::Body;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// body plans are files under `blueprints/` with a single `core` key holding the tree of body
+// parts, and are named by their path like blueprints ("bodies/humanoid.ron" is
+// "bodies.humanoid"):
+//
+//     (
+//         core: (
+//             name: "Core",
+//             tags: [Core],
+//             armor_tags: [Core, Back],
+//             children: [
+//                 (name: "Head", tags: [ThoughtCenter, Limb], armor_tags: [Head, Jewelry(3)]),
+//             ],
+//         ),
+//     )
+//
+// blueprints give an entity a body with `body: Some("bodies.humanoid")`.
+//
+// the factory fills this in while loading and the `body` component loader reads from it, so
+// both hold on to the same plans.
+#[derive(Clone, Debug, Default)]
+pub struct BodyPlans {
+    plans: Arc<RwLock<HashMap<String, BodyPart>>>,
+}
+
+impl BodyPlans {
+    pub fn insert(&self, name: String, core: BodyPart) {
+        self.plans.write().unwrap().insert(name, core);
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.plans.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn build(&self, name: &str) -> Result<Body, String> {
+        match self.plans.read().unwrap().get(name) {
+            Some(core) => Ok(Body::from_plan(core)),
+            None => Err(format!("no body plan named `{}`", name)),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use rand::distributions::Uniform;
+use rand::Rng;
+
+// the most dice, and sides on a die, a blueprint can ask for
+const MAX_COUNT: i64 = 1000;
+const MAX_SIDES: i64 = 10000;
+
+// a number in a blueprint that's rolled again every time the blueprint is built. written as
+// dice, `hp: "2d6+3"`, `"d20"`, `"3d4-2"`, or as a range with both ends included,
+// `strength: (8, 12)`. only works in number fields.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Roll {
+    Dice {
+        count: i64,
+        sides: i64,
+        modifier: i64,
+    },
+    Range(i64, i64),
+}
+
+impl Roll {
+    pub fn parse(text: &str) -> Option<Roll> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let d = text.find(['d', 'D'])?;
+        let (count, rest) = (&text[..d], &text[d + 1..]);
+        let count: i64 = match count {
+            "" => 1,
+            count => count.parse().ok()?,
+        };
+
+        let (sides, modifier) = match rest.find(['+', '-']) {
+            Some(sign) => (
+                &rest[..sign],
+                rest[sign..].trim_start_matches('+').parse().ok()?,
+            ),
+            None => (rest, 0),
+        };
+        let sides: i64 = sides.parse().ok()?;
+
+        if !(1..=MAX_COUNT).contains(&count) || !(1..=MAX_SIDES).contains(&sides) {
+            return None;
+        }
+        // the highest and lowest rolls both have to fit, with the modifier
+        (count * sides).checked_add(modifier)?;
+        count.checked_add(modifier)?;
+        Some(Roll::Dice {
+            count,
+            sides,
+            modifier,
+        })
+    }
+
+    pub fn min(&self) -> i64 {
+        match *self {
+            Roll::Dice {
+                count, modifier, ..
+            } => count.saturating_add(modifier),
+            Roll::Range(min, _) => min,
+        }
+    }
+
+    pub fn roll<R: Rng + ?Sized>(&self, rng: &mut R) -> i64 {
+        match *self {
+            Roll::Dice {
+                count,
+                sides,
+                modifier,
+            } => {
+                let die = Uniform::new_inclusive(1, sides);
+                (0..count).map(|_| rng.sample(die)).sum::<i64>() + modifier
+            }
+            Roll::Range(min, max) => rng.sample(Uniform::new_inclusive(min, max)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dice() {
+        let dice = |count, sides, modifier| {
+            Some(Roll::Dice {
+                count,
+                sides,
+                modifier,
+            })
+        };
+        assert_eq!(Roll::parse("2d6+3"), dice(2, 6, 3));
+        assert_eq!(Roll::parse("d20"), dice(1, 20, 0));
+        assert_eq!(Roll::parse(" 3D4 - 2 "), dice(3, 4, -2));
+        assert_eq!(Roll::parse("1000d10000"), dice(1000, 10000, 0));
+    }
+
+    #[test]
+    fn rejects_too_many_dice_or_sides() {
+        for text in &[
+            "0d6",
+            "d0",
+            "1001d6",
+            "1d10001",
+            "99999999999d6",
+            "xd6",
+            "2d",
+        ] {
+            assert_eq!(Roll::parse(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_modifiers_that_overflow() {
+        assert_eq!(Roll::parse(&format!("2d6+{}", i64::MAX)), None);
+        assert_eq!(Roll::parse(&format!("1000d10000+{}", i64::MAX - 5)), None);
+    }
+
+    #[test]
+    fn min_saturates() {
+        let roll = Roll::Dice {
+            count: 2,
+            sides: 6,
+            modifier: i64::MAX,
+        };
+        assert_eq!(roll.min(), i64::MAX);
+    }
+}
@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// everything that can go wrong while reading and resolving the blueprint tree.
+// positions are 1-based (line, column) pairs, the same as ron reports them.
+#[derive(Debug)]
+pub enum BlueprintError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+        message: String,
+    },
+    UnknownComponent {
+        path: PathBuf,
+        key: String,
+        position: Option<(usize, usize)>,
+    },
+    MissingParent {
+        path: PathBuf,
+        parent: String,
+    },
+    BrokenParent {
+        path: PathBuf,
+        parent: String,
+    },
+    Cycle {
+        chain: Vec<String>,
+    },
+    NotFound {
+        name: String,
+    },
+}
+
+impl BlueprintError {
+    // rotates the cycle so it starts at its alphabetically first member. that way the same
+    // cycle reached from different blueprints always prints the same.
+    pub fn cycle(members: &[String]) -> Self {
+        let start = (0..members.len()).min_by_key(|&i| &members[i]).unwrap_or(0);
+        let mut chain: Vec<String> = members[start..]
+            .iter()
+            .chain(members[..start].iter())
+            .cloned()
+            .collect();
+        if let Some(first) = chain.first().cloned() {
+            chain.push(first);
+        }
+        BlueprintError::Cycle { chain }
+    }
+}
+
+fn format_location(path: &Path, position: &Option<(usize, usize)>) -> String {
+    match position {
+        Some((line, col)) => format!("{}:{}:{}", path.display(), line, col),
+        None => format!("{}", path.display()),
+    }
+}
+
+impl fmt::Display for BlueprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlueprintError::Io { path, error } => {
+                write!(f, "{}: could not read blueprint: {}", path.display(), error)
+            }
+            BlueprintError::Parse {
+                path,
+                position,
+                message,
+            } => write!(f, "{}: {}", format_location(path, position), message),
+            BlueprintError::UnknownComponent {
+                path,
+                key,
+                position,
+            } => write!(
+                f,
+                "{}: unknown component key `{}`",
+                format_location(path, position),
+                key
+            ),
+            BlueprintError::MissingParent { path, parent } => write!(
+                f,
+                "{}: extends missing blueprint `{}`",
+                path.display(),
+                parent
+            ),
+            BlueprintError::BrokenParent { path, parent } => write!(
+                f,
+                "{}: extends blueprint `{}` which failed to load",
+                path.display(),
+                parent
+            ),
+            BlueprintError::Cycle { chain } => {
+                write!(f, "blueprint inheritance cycle: {}", chain.join(" -> "))
+            }
+            BlueprintError::NotFound { name } => write!(f, "no blueprint named `{}`", name),
+        }
+    }
+}
+
+impl Error for BlueprintError {}
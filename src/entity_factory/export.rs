@@ -0,0 +1,235 @@
+use super::value::Value;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+// a blueprint with everything it inherits written out, for looking at what an entity actually
+// gets without reading through its whole `extends` chain
+#[derive(Clone, Debug)]
+pub struct ResolvedBlueprint {
+    pub name: String,
+    pub path: PathBuf,
+    // tags and properties, as written in `query::META_KEYS`
+    pub meta: Vec<(String, Value)>,
+    pub components: Vec<ResolvedComponent>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedComponent {
+    pub key: String,
+    pub value: Value,
+    // every file that changed the component, in the order they were applied. a component
+    // replaced whole only lists the file that replaced it
+    pub sources: Vec<PathBuf>,
+}
+
+impl ResolvedBlueprint {
+    // a blueprint file that loads into the same entity, with the sources as comments
+    pub fn to_ron(&self) -> String {
+        let mut out = format!("// {} ({})\n(\n", self.name, self.path.display());
+        for (key, value) in &self.meta {
+            write!(out, "    {}: ", key).unwrap();
+            write_ron(&mut out, value, 1);
+            out.push_str(",\n");
+        }
+        for component in &self.components {
+            let sources: Vec<String> = component
+                .sources
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            writeln!(out, "    // from {}", sources.join(", ")).unwrap();
+            write!(out, "    {}: Some(", component.key).unwrap();
+            write_ron(&mut out, &component.value, 1);
+            out.push_str("),\n");
+        }
+        out.push_str(")\n");
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\n");
+        writeln!(out, "  \"name\": {},", json_string(&self.name)).unwrap();
+        writeln!(
+            out,
+            "  \"path\": {},",
+            json_string(&self.path.display().to_string())
+        )
+        .unwrap();
+        for (key, value) in &self.meta {
+            write!(out, "  {}: ", json_string(key)).unwrap();
+            write_json(&mut out, value);
+            out.push_str(",\n");
+        }
+        out.push_str("  \"components\": {");
+        for (i, component) in self.components.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            writeln!(out, "    {}: {{", json_string(&component.key)).unwrap();
+            let sources: Vec<String> = component
+                .sources
+                .iter()
+                .map(|path| json_string(&path.display().to_string()))
+                .collect();
+            writeln!(out, "      \"sources\": [{}],", sources.join(", ")).unwrap();
+            out.push_str("      \"value\": ");
+            write_json(&mut out, &component.value);
+            out.push_str("\n    }");
+        }
+        out.push_str("\n  }\n}\n");
+        out
+    }
+}
+
+// structs are spread over several lines, everything else stays on one
+fn write_ron(out: &mut String, value: &Value, depth: usize) {
+    match value {
+        Value::Unit => out.push_str("()"),
+        Value::Bool(b) => write!(out, "{}", b).unwrap(),
+        Value::Int(i) => write!(out, "{}", i).unwrap(),
+        Value::Float(f) => write!(out, "{:?}", f).unwrap(),
+        Value::Char(c) => write!(out, "{:?}", c).unwrap(),
+        Value::String(s) => write!(out, "{:?}", s).unwrap(),
+        Value::Ident(ident) => out.push_str(ident),
+        Value::Option(None) => out.push_str("None"),
+        Value::Option(Some(value)) => {
+            out.push_str("Some(");
+            write_ron(out, value, depth);
+            out.push(')');
+        }
+        Value::List(items) => {
+            out.push('[');
+            write_ron_items(out, items, depth);
+            out.push(']');
+        }
+        Value::Tuple(name, items) => {
+            out.push_str(name.as_ref().map_or("", |name| name.as_str()));
+            out.push('(');
+            write_ron_items(out, items, depth);
+            out.push(')');
+        }
+        Value::Map(entries) => {
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_ron(out, key, depth);
+                out.push_str(": ");
+                write_ron(out, value, depth);
+            }
+            out.push('}');
+        }
+        Value::Struct(name, fields) => {
+            out.push_str(name.as_ref().map_or("", |name| name.as_str()));
+            out.push('(');
+            if fields.is_empty() {
+                out.push(')');
+                return;
+            }
+            let indent = "    ".repeat(depth + 1);
+            for (key, value) in fields {
+                write!(out, "\n{}{}: ", indent, key).unwrap();
+                write_ron(out, value, depth + 1);
+                out.push(',');
+            }
+            write!(out, "\n{})", "    ".repeat(depth)).unwrap();
+        }
+    }
+}
+
+fn write_ron_items(out: &mut String, items: &[Value], depth: usize) {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_ron(out, item, depth);
+    }
+}
+
+// json has no enums, named tuples and structs become `{"Variant": ...}` the way serde_json
+// writes them
+fn write_json(out: &mut String, value: &Value) {
+    match value {
+        Value::Unit | Value::Option(None) => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{}", b).unwrap(),
+        Value::Int(i) => write!(out, "{}", i).unwrap(),
+        Value::Float(f) if f.is_finite() => write!(out, "{:?}", f).unwrap(),
+        Value::Float(_) => out.push_str("null"),
+        Value::Char(c) => out.push_str(&json_string(&c.to_string())),
+        Value::String(s) | Value::Ident(s) => out.push_str(&json_string(s)),
+        Value::Option(Some(value)) => write_json(out, value),
+        Value::List(items) | Value::Tuple(None, items) => write_json_items(out, items),
+        Value::Tuple(Some(name), items) => {
+            write!(out, "{{{}: ", json_string(name)).unwrap();
+            match items.as_slice() {
+                [item] => write_json(out, item),
+                items => write_json_items(out, items),
+            }
+            out.push('}');
+        }
+        Value::Map(entries) => {
+            // keys that aren't strings are written out as ron
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                let key = match key {
+                    Value::String(s) | Value::Ident(s) => s.clone(),
+                    key => {
+                        let mut text = String::new();
+                        write_ron(&mut text, key, 0);
+                        text
+                    }
+                };
+                write!(out, "{}: ", json_string(&key)).unwrap();
+                write_json(out, value);
+            }
+            out.push('}');
+        }
+        Value::Struct(name, fields) => {
+            if let Some(name) = name {
+                write!(out, "{{{}: ", json_string(name)).unwrap();
+            }
+            out.push('{');
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write!(out, "{}: ", json_string(key)).unwrap();
+                write_json(out, value);
+            }
+            out.push('}');
+            if name.is_some() {
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_json_items(out: &mut String, items: &[Value]) {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_json(out, item);
+    }
+    out.push(']');
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
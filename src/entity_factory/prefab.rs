@@ -0,0 +1,40 @@
+use super::{BlueprintMeta, EntityBlueprint};
+use crate::components::Position;
+
+// a group of blueprints spawned together around one anchor position. a prefab is a file under
+// `blueprints/` with a `members` list:
+//
+//     (
+//         members: [
+//             (blueprint: "creatures.zombie", offset: (-1, 0)),
+//             (blueprint: "creatures.zombie", offset: (1, 1), overrides: (
+//                 name: Some((name: "Zombie Chief")),
+//             )),
+//         ],
+//     )
+//
+// `offset` defaults to (0, 0). `overrides` is merged over the member's blueprint the same way a
+// child blueprint would be, so it can change single fields or remove components with `None`.
+// members have to be blueprints, prefabs can't contain other prefabs. prefabs can have tags
+// and properties like blueprints (see `query.rs`), but don't inherit any.
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    pub members: Vec<PrefabMember>,
+    pub meta: BlueprintMeta,
+}
+
+#[derive(Clone, Debug)]
+pub struct PrefabMember {
+    pub blueprint: String,
+    pub offset: (i32, i32),
+    // the member's blueprint with its overrides applied
+    pub resolved: EntityBlueprint,
+}
+
+impl PrefabMember {
+    pub fn position(&self, anchor: Position) -> Position {
+        Position::new(anchor.x + self.offset.0, anchor.y + self.offset.1)
+    }
+}
+
+pub const MEMBER_KEYS: &[&str] = &["blueprint", "offset", "overrides"];
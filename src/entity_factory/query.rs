@@ -0,0 +1,192 @@
+use super::value::{Value, ValueError};
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// the keys a blueprint or prefab can use to describe itself to spawning code. none of them
+// build anything:
+//
+// - `tags: ["undead", "cave"]`, added to the tags of every parent instead of replacing them
+// - `min_depth: 3` and `max_depth: 8`, the dungeon depths it can show up at
+// - `rarity: 10`, it turns up a tenth as often as something with the default rarity of 1
+// - `props: {"danger": 4}`, any other numbers spawning code wants to look at
+pub const META_KEYS: &[&str] = &["tags", "min_depth", "max_depth", "rarity", "props"];
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BlueprintMeta {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub min_depth: Option<u32>,
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    #[serde(default)]
+    pub rarity: Option<u32>,
+    #[serde(default)]
+    pub props: BTreeMap<String, f64>,
+}
+
+impl BlueprintMeta {
+    // picks the meta keys out of a whole blueprint
+    pub fn from_value(value: &Value) -> Result<Self, ValueError> {
+        let fields = META_KEYS
+            .iter()
+            .filter_map(|key| Some((String::from(*key), value.field(key)?.clone())))
+            .collect();
+        Value::Struct(None, fields).into_rust()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn valid_at_depth(&self, depth: u32) -> bool {
+        self.min_depth.is_none_or(|min| depth >= min)
+            && self.max_depth.is_none_or(|max| depth <= max)
+    }
+
+    pub fn prop(&self, key: &str) -> Option<f64> {
+        self.props.get(key).cloned()
+    }
+
+    fn weight(&self) -> f64 {
+        1.0 / f64::from(self.rarity.unwrap_or(1).max(1))
+    }
+}
+
+// the meta of every blueprint and prefab that can be spawned. spawning code queries this
+// instead of naming blueprints itself. it's also inserted into the world as a resource, so
+// systems can get at it.
+#[derive(Clone, Debug, Default)]
+pub struct BlueprintIndex {
+    entries: BTreeMap<String, BlueprintMeta>,
+}
+
+impl BlueprintIndex {
+    pub fn insert(&mut self, name: String, meta: BlueprintMeta) {
+        self.entries.insert(name, meta);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BlueprintMeta> {
+        self.entries.get(name)
+    }
+
+    // everything, until narrowed down with `tagged` and `at_depth`
+    pub fn query(&self) -> BlueprintQuery<'_> {
+        BlueprintQuery {
+            index: self,
+            tags: Vec::new(),
+            depth: None,
+        }
+    }
+}
+
+// e.g. everything tagged `monster` that can show up at depth 4:
+//
+//     index.query().tagged("monster").at_depth(4).pick(&mut rng)
+pub struct BlueprintQuery<'a> {
+    index: &'a BlueprintIndex,
+    tags: Vec<String>,
+    depth: Option<u32>,
+}
+
+impl<'a> BlueprintQuery<'a> {
+    // can be called more than once, matches have to have every tag
+    pub fn tagged(mut self, tag: &str) -> Self {
+        self.tags.push(String::from(tag));
+        self
+    }
+
+    pub fn at_depth(mut self, depth: u32) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn matches(&self) -> impl Iterator<Item = (&'a str, &'a BlueprintMeta)> + '_ {
+        self.index
+            .entries
+            .iter()
+            .filter(move |(_, meta)| {
+                self.tags.iter().all(|tag| meta.has_tag(tag))
+                    && self.depth.is_none_or(|depth| meta.valid_at_depth(depth))
+            })
+            .map(|(name, meta)| (name.as_str(), meta))
+    }
+
+    // sorted by name
+    pub fn names(&self) -> Vec<String> {
+        self.matches().map(|(name, _)| String::from(name)).collect()
+    }
+
+    // a random match, rarer ones less likely
+    pub fn pick<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<String> {
+        let total: f64 = self.matches().map(|(_, meta)| meta.weight()).sum();
+        let mut roll = rng.gen::<f64>() * total;
+        let mut picked = None;
+        for (name, meta) in self.matches() {
+            picked = Some(String::from(name));
+            roll -= meta.weight();
+            if roll < 0.0 {
+                break;
+            }
+        }
+        picked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn meta(tags: &[&str], min_depth: Option<u32>, max_depth: Option<u32>) -> BlueprintMeta {
+        BlueprintMeta {
+            tags: tags.iter().map(|tag| String::from(*tag)).collect(),
+            min_depth,
+            max_depth,
+            ..BlueprintMeta::default()
+        }
+    }
+
+    fn index() -> BlueprintIndex {
+        let mut index = BlueprintIndex::default();
+        index.insert(String::from("rat"), meta(&["monster"], None, Some(3)));
+        let undead_monster = meta(&["monster", "undead"], Some(2), None);
+        index.insert(String::from("ghost"), undead_monster);
+        index.insert(String::from("bones"), meta(&["undead"], None, None));
+        index
+    }
+
+    #[test]
+    fn matches_every_tag() {
+        let index = index();
+        assert_eq!(index.query().tagged("monster").names(), ["ghost", "rat"]);
+        assert_eq!(
+            index.query().tagged("monster").tagged("undead").names(),
+            ["ghost"]
+        );
+        assert!(index.query().tagged("dragon").names().is_empty());
+    }
+
+    #[test]
+    fn matches_depth_bounds_inclusively() {
+        let index = index();
+        let at = |depth| index.query().tagged("monster").at_depth(depth).names();
+        assert_eq!(at(1), ["rat"]);
+        assert_eq!(at(2), ["ghost", "rat"]);
+        assert_eq!(at(3), ["ghost", "rat"]);
+        assert_eq!(at(4), ["ghost"]);
+    }
+
+    #[test]
+    fn only_picks_matches() {
+        let index = index();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let picked = index.query().tagged("undead").at_depth(1).pick(&mut rng);
+            assert_eq!(picked.as_deref(), Some("bones"));
+        }
+        assert_eq!(index.query().tagged("dragon").pick(&mut rng), None);
+    }
+}
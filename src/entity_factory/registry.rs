@@ -0,0 +1,181 @@
+use super::dice::Roll;
+use super::value::{Value, ValueError};
+use crate::components::*;
+use serde::de::{self, DeserializeOwned};
+use specs::prelude::*;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+// maps blueprint keys (`corporeal: Some(...)`) to the component they build. anything that can
+// be deserialized can be registered, including components from outside this crate:
+//
+//     let mut registry = ComponentRegistry::default();
+//     registry.register::<Glowing>("glowing");
+//     let factory = EntityFactory::with_registry("blueprints", registry);
+pub struct ComponentRegistry {
+    components: BTreeMap<String, Box<dyn ComponentLoader>>,
+}
+
+impl ComponentRegistry {
+    // a registry with no components at all. `default()` has all of the game's components
+    pub fn new() -> Self {
+        ComponentRegistry {
+            components: BTreeMap::new(),
+        }
+    }
+
+    // registering a key again replaces the component it builds
+    pub fn register<T>(&mut self, key: &str) -> &mut Self
+    where
+        T: Component + DeserializeOwned + Send + Sync,
+        T::Storage: Default,
+    {
+        self.register_with(key, |component: T| Ok(component))
+    }
+
+    // for components that aren't written out in full in blueprints. the value under `key` is
+    // deserialized into a `D` and handed to `build`, e.g. a body plan name that gets looked up
+    pub fn register_with<T, D, F>(&mut self, key: &str, build: F) -> &mut Self
+    where
+        T: Component + Send + Sync,
+        T::Storage: Default,
+        D: DeserializeOwned + 'static,
+        F: Fn(D) -> Result<T, String> + Send + Sync + 'static,
+    {
+        let loader = Loader {
+            build,
+            marker: PhantomData,
+        };
+        self.components.insert(String::from(key), Box::new(loader));
+        self
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.components.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.components.keys().map(|key| key.as_str())
+    }
+
+    // registers the storage of every component with the world, so building a blueprint never
+    // runs into a component that no system happened to ask for
+    pub fn setup(&self, world: &mut World) {
+        for loader in self.components.values() {
+            loader.setup(world);
+        }
+    }
+
+    // makes sure `value` deserializes into the component, with every roll at its lowest
+    pub fn check(&self, key: &str, value: Value) -> Result<(), ValueError> {
+        self.loader(key)?.check(value)
+    }
+
+    pub fn insert(
+        &self,
+        key: &str,
+        value: Value,
+        roller: &dyn Fn(&Roll) -> i64,
+        entity: Entity,
+        world: &World,
+    ) -> Result<(), ValueError> {
+        self.loader(key)?.insert(value, roller, entity, world)
+    }
+
+    // takes the component off the entity, if it had one
+    pub fn remove(&self, key: &str, entity: Entity, world: &World) {
+        if let Ok(loader) = self.loader(key) {
+            loader.remove(entity, world);
+        }
+    }
+
+    fn loader(&self, key: &str) -> Result<&dyn ComponentLoader, ValueError> {
+        self.components
+            .get(key)
+            .map(|loader| loader.as_ref())
+            .ok_or_else(|| de::Error::custom(format!("unknown component key `{}`", key)))
+    }
+}
+
+impl Default for ComponentRegistry {
+    fn default() -> Self {
+        let mut registry = ComponentRegistry::new();
+        registry
+            .register::<Name>("name")
+            .register::<Actor>("actor")
+            .register::<PlayerControl>("player")
+            .register::<Camera>("camera")
+            .register::<Renderable>("renderable")
+            .register::<RandomRenderable>("random_renderable")
+            .register::<Corporeal>("corporeal")
+            .register::<Seeing>("seeing")
+            .register::<AiControl>("ai_control")
+            .register::<Mobile>("mobile")
+            .register::<Position>("position")
+            .register::<Invulnerable>("invulnerable")
+            .register::<BlockMovement>("blocks_movement")
+            .register::<BlockSight>("blocks_sight")
+            .register::<Elevation>("elevation")
+            .register::<Material>("material")
+            .register::<Carryable>("carryable")
+            .register::<Stairs>("stairs")
+            .register::<Inventory>("inventory")
+            .register::<PrintDebug>("print_debug");
+        registry
+    }
+}
+
+trait ComponentLoader: Send + Sync {
+    fn setup(&self, world: &mut World);
+    fn check(&self, value: Value) -> Result<(), ValueError>;
+    fn insert(
+        &self,
+        value: Value,
+        roller: &dyn Fn(&Roll) -> i64,
+        entity: Entity,
+        world: &World,
+    ) -> Result<(), ValueError>;
+    fn remove(&self, entity: Entity, world: &World);
+}
+
+struct Loader<T, D, F> {
+    build: F,
+    marker: PhantomData<fn(D) -> T>,
+}
+
+impl<T, D, F> ComponentLoader for Loader<T, D, F>
+where
+    T: Component + Send + Sync,
+    T::Storage: Default,
+    D: DeserializeOwned,
+    F: Fn(D) -> Result<T, String> + Send + Sync,
+{
+    fn setup(&self, world: &mut World) {
+        world.register::<T>();
+    }
+
+    fn check(&self, value: Value) -> Result<(), ValueError> {
+        (self.build)(value.into_rust()?)
+            .map(|_| ())
+            .map_err(de::Error::custom)
+    }
+
+    fn insert(
+        &self,
+        value: Value,
+        roller: &dyn Fn(&Roll) -> i64,
+        entity: Entity,
+        world: &World,
+    ) -> Result<(), ValueError> {
+        let component = (self.build)(value.into_rust_rolled(roller)?).map_err(de::Error::custom)?;
+        world
+            .write_storage::<T>()
+            .insert(entity, component)
+            .map(|_| ())
+            .map_err(de::Error::custom)
+    }
+
+    fn remove(&self, entity: Entity, world: &World) {
+        world.write_storage::<T>().remove(entity);
+    }
+}
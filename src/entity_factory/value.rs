@@ -0,0 +1,1031 @@
+use super::dice::Roll;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer,
+    Unexpected, VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+// a parsed ron value. ron's own `Value` forgets enum variant and struct names, which makes it
+// useless for anything with an `Elevation` or `AiType` in it. this one keeps them, so
+// blueprints can be merged as values and deserialized into components afterwards.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Char(char),
+    String(String),
+    Option(Option<Box<Value>>),
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    // `(a, b)` or `Name(a, b)`
+    Tuple(Option<String>, Vec<Value>),
+    // `(field: a)` or `Name(field: a)`, fields stay in the order they were written
+    Struct(Option<String>, Vec<(String, Value)>),
+    // a bare identifier, i.e. a unit variant like `Upright`
+    Ident(String),
+}
+
+impl Value {
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(_, fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn remove_field(&mut self, key: &str) {
+        if let Value::Struct(_, fields) = self {
+            fields.retain(|(k, _)| k != key);
+        }
+    }
+
+    // applies `child` on top of `self`. structs, maps and options are merged field by field,
+    // everything else (numbers, strings, lists, tuples...) is replaced by the child's value.
+    pub fn merge(self, child: Value) -> Value {
+        match (self, child) {
+            (Value::Struct(name, mut fields), Value::Struct(child_name, child_fields)) => {
+                for (key, value) in child_fields {
+                    match fields.iter().position(|(k, _)| *k == key) {
+                        Some(i) => {
+                            let parent = mem::replace(&mut fields[i].1, Value::Unit);
+                            fields[i].1 = parent.merge(value);
+                        }
+                        None => fields.push((key, value)),
+                    }
+                }
+                Value::Struct(child_name.or(name), fields)
+            }
+
+            (Value::Map(mut entries), Value::Map(child_entries)) => {
+                for (key, value) in child_entries {
+                    match entries.iter().position(|(k, _)| *k == key) {
+                        Some(i) => {
+                            let parent = mem::replace(&mut entries[i].1, Value::Unit);
+                            entries[i].1 = parent.merge(value);
+                        }
+                        None => entries.push((key, value)),
+                    }
+                }
+                Value::Map(entries)
+            }
+
+            (Value::Option(Some(parent)), Value::Option(Some(child))) => {
+                Value::Option(Some(Box::new(parent.merge(*child))))
+            }
+
+            // `()` is a struct with no fields set, so it keeps everything the parent had
+            (parent @ Value::Struct(..), Value::Unit) => parent,
+
+            (_, child) => child,
+        }
+    }
+
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Value::Unit => Unexpected::Unit,
+            Value::Bool(b) => Unexpected::Bool(*b),
+            Value::Int(i) => Unexpected::Signed(*i),
+            Value::Float(f) => Unexpected::Float(*f),
+            Value::Char(c) => Unexpected::Char(*c),
+            Value::String(s) => Unexpected::Str(s),
+            Value::Option(_) => Unexpected::Option,
+            Value::List(_) | Value::Tuple(..) => Unexpected::Seq,
+            Value::Map(_) | Value::Struct(..) => Unexpected::Map,
+            Value::Ident(_) => Unexpected::Other("identifier"),
+        }
+    }
+}
+
+// writes the value back out as ron that `from_str` reads the same, on one line
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T>(
+            f: &mut fmt::Formatter<'_>,
+            items: &[T],
+            write: impl Fn(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+        ) -> fmt::Result {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write(f, item)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Option(None) => write!(f, "None"),
+            Value::Option(Some(value)) => write!(f, "Some({})", value),
+            Value::List(items) => {
+                write!(f, "[")?;
+                list(f, items, |f, item| write!(f, "{}", item))?;
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                list(f, entries, |f, (key, value)| {
+                    write!(f, "{}: {}", key, value)
+                })?;
+                write!(f, "}}")
+            }
+            Value::Tuple(name, items) => {
+                write!(f, "{}(", name.as_ref().map_or("", |name| name.as_str()))?;
+                list(f, items, |f, item| write!(f, "{}", item))?;
+                write!(f, ")")
+            }
+            Value::Struct(name, fields) => {
+                write!(f, "{}(", name.as_ref().map_or("", |name| name.as_str()))?;
+                list(f, fields, |f, (key, value)| write!(f, "{}: {}", key, value))?;
+                write!(f, ")")
+            }
+            Value::Ident(ident) => write!(f, "{}", ident),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueError {
+    pub message: String,
+    // (line, column), only known for syntax errors
+    pub position: Option<(usize, usize)>,
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ValueError {}
+
+impl de::Error for ValueError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ValueError {
+            message: msg.to_string(),
+            position: None,
+        }
+    }
+}
+
+pub fn from_str(source: &str) -> Result<Value, ValueError> {
+    let mut parser = Parser { source, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace()?;
+    if parser.pos < source.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> ValueError {
+        let before = &self.source[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let col = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        ValueError {
+            message: String::from(message),
+            position: Some((line, col)),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ValueError> {
+        self.skip_whitespace()?;
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected `{}`", c)));
+        }
+        self.bump();
+        Ok(())
+    }
+
+    // whitespace and comments
+    fn skip_whitespace(&mut self) -> Result<(), ValueError> {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(end) => self.pos += end + 4,
+                    None => return Err(self.error("unclosed block comment")),
+                }
+            } else if let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+                self.pos += c.len_utf8();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        let rest = self.rest();
+        let mut end = 0;
+        for (i, c) in rest.char_indices() {
+            if c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()) {
+                end = i + c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        if end == 0 {
+            return None;
+        }
+        self.pos += end;
+        Some(&rest[..end])
+    }
+
+    fn value(&mut self) -> Result<Value, ValueError> {
+        self.skip_whitespace()?;
+        let rest = self.rest();
+        match self.peek() {
+            None => Err(self.error("unexpected end of file")),
+            Some('(') => self.parens(None),
+            Some('[') => {
+                self.bump();
+                Ok(Value::List(self.sequence(']')?))
+            }
+            Some('{') => self.map(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('\'') => self.character(),
+            Some('r') if rest.starts_with("r\"") || rest.starts_with("r#") => {
+                Ok(Value::String(self.raw_string()?))
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => self.number(),
+            Some(c) => match self.ident() {
+                Some("true") => Ok(Value::Bool(true)),
+                Some("false") => Ok(Value::Bool(false)),
+                Some("None") => Ok(Value::Option(None)),
+                Some("Some") => {
+                    self.expect('(')?;
+                    let value = self.value()?;
+                    self.expect(')')?;
+                    Ok(Value::Option(Some(Box::new(value))))
+                }
+                Some(ident) => {
+                    self.skip_whitespace()?;
+                    match self.peek() {
+                        Some('(') => self.parens(Some(String::from(ident))),
+                        _ => Ok(Value::Ident(String::from(ident))),
+                    }
+                }
+                None => Err(self.error(&format!("unexpected character `{}`", c))),
+            },
+        }
+    }
+
+    // the `(...)` part of a unit, tuple or struct, with an optional name in front
+    fn parens(&mut self, name: Option<String>) -> Result<Value, ValueError> {
+        self.expect('(')?;
+        self.skip_whitespace()?;
+
+        if self.peek() == Some(')') {
+            self.bump();
+            return Ok(match name {
+                Some(name) => Value::Tuple(Some(name), Vec::new()),
+                None => Value::Unit,
+            });
+        }
+
+        if !self.at_field() {
+            return Ok(Value::Tuple(name, self.sequence(')')?));
+        }
+
+        let mut fields = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.peek() == Some(')') {
+                break;
+            }
+            let field = match self.ident() {
+                Some(field) => String::from(field),
+                None => return Err(self.error("expected field name")),
+            };
+            self.expect(':')?;
+            fields.push((field, self.value()?));
+            if !self.comma()? {
+                break;
+            }
+        }
+        self.close(')')?;
+        Ok(Value::Struct(name, fields))
+    }
+
+    // true if the next tokens are `field:`
+    fn at_field(&mut self) -> bool {
+        let start = self.pos;
+        let at_field =
+            self.ident().is_some() && self.skip_whitespace().is_ok() && self.peek() == Some(':');
+        self.pos = start;
+        at_field
+    }
+
+    // comma separated values up to and including `end`
+    fn sequence(&mut self, end: char) -> Result<Vec<Value>, ValueError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.peek() == Some(end) {
+                break;
+            }
+            items.push(self.value()?);
+            if !self.comma()? {
+                break;
+            }
+        }
+        self.close(end)?;
+        Ok(items)
+    }
+
+    fn map(&mut self) -> Result<Value, ValueError> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.peek() == Some('}') {
+                break;
+            }
+            let key = self.value()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            if !self.comma()? {
+                break;
+            }
+        }
+        self.close('}')?;
+        Ok(Value::Map(entries))
+    }
+
+    // the closing bracket after the last item, where a missing comma is the likely mistake
+    fn close(&mut self, end: char) -> Result<(), ValueError> {
+        self.skip_whitespace()?;
+        if self.peek() != Some(end) {
+            return Err(self.error(&format!("expected `,` or `{}`", end)));
+        }
+        self.bump();
+        Ok(())
+    }
+
+    fn comma(&mut self) -> Result<bool, ValueError> {
+        self.skip_whitespace()?;
+        if self.peek() == Some(',') {
+            self.bump();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn number(&mut self) -> Result<Value, ValueError> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| {
+                !(c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+' || c == '_')
+            })
+            .unwrap_or(rest.len());
+        let text: String = rest[..end].chars().filter(|&c| c != '_').collect();
+
+        let value = if let Ok(int) = text.parse::<i64>() {
+            Value::Int(int)
+        } else if let Ok(float) = text.parse::<f64>() {
+            Value::Float(float)
+        } else {
+            return Err(self.error(&format!("invalid number `{}`", &rest[..end])));
+        };
+        self.pos += end;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, ValueError> {
+        self.bump();
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(self.escape()?),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    // r"..." and r#"..."#
+    fn raw_string(&mut self) -> Result<String, ValueError> {
+        self.bump();
+        let mut hashes = 0;
+        while self.peek() == Some('#') {
+            self.bump();
+            hashes += 1;
+        }
+        if self.bump() != Some('"') {
+            return Err(self.error("expected `\"`"));
+        }
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        match self.rest().find(&terminator) {
+            Some(end) => {
+                let string = String::from(&self.rest()[..end]);
+                self.pos += end + terminator.len();
+                Ok(string)
+            }
+            None => Err(self.error("unterminated string")),
+        }
+    }
+
+    fn character(&mut self) -> Result<Value, ValueError> {
+        self.bump();
+        let c = match self.bump() {
+            Some('\\') => self.escape()?,
+            Some(c) => c,
+            None => return Err(self.error("unterminated char")),
+        };
+        if self.bump() != Some('\'') {
+            return Err(self.error("expected `'`"));
+        }
+        Ok(Value::Char(c))
+    }
+
+    fn escape(&mut self) -> Result<char, ValueError> {
+        match self.bump() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some('\\') => Ok('\\'),
+            Some('"') => Ok('"'),
+            Some('\'') => Ok('\''),
+            Some('u') => {
+                let braced = self.peek() == Some('{');
+                if braced {
+                    self.bump();
+                }
+                // the hex digits, in bytes. they're all ascii, so `len` is on a char boundary
+                let rest = self.rest();
+                let len = rest
+                    .chars()
+                    .take_while(char::is_ascii_hexdigit)
+                    .take(if braced { 6 } else { 4 })
+                    .count();
+                if len == 0 || (braced && !rest[len..].starts_with('}')) {
+                    return Err(self.error("invalid unicode escape"));
+                }
+                let c = u32::from_str_radix(&rest[..len], 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| self.error("invalid unicode escape"))?;
+                self.pos += len + braced as usize;
+                Ok(c)
+            }
+            _ => Err(self.error("invalid escape")),
+        }
+    }
+}
+
+impl Value {
+    pub fn into_rust<T: DeserializeOwned>(self) -> Result<T, ValueError> {
+        T::deserialize(ValueDeserializer {
+            value: self,
+            roller: None,
+        })
+    }
+
+    // like `into_rust`, but dice and ranges in number fields are rolled with `roller`
+    pub fn into_rust_rolled<T: DeserializeOwned>(
+        self,
+        roller: &dyn Fn(&Roll) -> i64,
+    ) -> Result<T, ValueError> {
+        T::deserialize(ValueDeserializer {
+            value: self,
+            roller: Some(roller),
+        })
+    }
+}
+
+pub struct ValueDeserializer<'r> {
+    value: Value,
+    // without a roller, dice and ranges deserialize to their lowest roll
+    roller: Option<&'r dyn Fn(&Roll) -> i64>,
+}
+
+impl<'r> ValueDeserializer<'r> {
+    fn child(&self, value: Value) -> Self {
+        ValueDeserializer {
+            value,
+            roller: self.roller,
+        }
+    }
+
+    fn visit_seq<'de, V: Visitor<'de>>(
+        self,
+        items: Vec<Value>,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        let items: Vec<_> = items.into_iter().map(|item| self.child(item)).collect();
+        let mut seq = SeqDeserializer::new(items.into_iter());
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    fn visit_map<'de, V: Visitor<'de>>(
+        self,
+        entries: Vec<(Value, Value)>,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(key, value)| (self.child(key), self.child(value)))
+            .collect();
+        let mut map = MapDeserializer::new(entries.into_iter());
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(value)
+    }
+
+    fn roll(&self) -> Option<i64> {
+        let roll = match &self.value {
+            Value::String(text) => Roll::parse(text)?,
+            Value::Tuple(None, items) => match items.as_slice() {
+                [Value::Int(min), Value::Int(max)] if min <= max => Roll::Range(*min, *max),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        match self.roller {
+            Some(roller) => Some(roller(&roll)),
+            None => Some(roll.min()),
+        }
+    }
+}
+
+impl<'de, 'r> IntoDeserializer<'de, ValueError> for ValueDeserializer<'r> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn struct_entries(fields: Vec<(String, Value)>) -> Vec<(Value, Value)> {
+    fields
+        .into_iter()
+        .map(|(key, value)| (Value::Ident(key), value))
+        .collect()
+}
+
+// every integer type goes through here so dice and ranges get rolled
+macro_rules! deserialize_integer {
+    ($($method:ident)+) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+                match self.roll() {
+                    Some(rolled) => visitor.visit_i64(rolled),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )+
+    };
+}
+
+impl<'de, 'r> Deserializer<'de> for ValueDeserializer<'r> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        let roller = self.roller;
+        match self.value {
+            Value::Unit => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Int(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Char(c) => visitor.visit_char(c),
+            Value::String(s) | Value::Ident(s) => visitor.visit_string(s),
+            Value::Option(None) => visitor.visit_none(),
+            Value::Option(Some(value)) => visitor.visit_some(ValueDeserializer {
+                value: *value,
+                roller,
+            }),
+            Value::List(ref items) | Value::Tuple(_, ref items) => {
+                let items = items.clone();
+                self.visit_seq(items, visitor)
+            }
+            Value::Map(ref entries) => {
+                let entries = entries.clone();
+                self.visit_map(entries, visitor)
+            }
+            Value::Struct(_, ref fields) => {
+                let entries = struct_entries(fields.clone());
+                self.visit_map(entries, visitor)
+            }
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        let roller = self.roller;
+        match self.value {
+            Value::Option(None) => visitor.visit_none(),
+            Value::Option(Some(value)) => visitor.visit_some(ValueDeserializer {
+                value: *value,
+                roller,
+            }),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::Unit | Value::Ident(_) => visitor.visit_unit(),
+            Value::Tuple(_, ref items) if items.is_empty() => visitor.visit_unit(),
+            value => Err(de::Error::invalid_type(value.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::Tuple(_, ref items) if items.len() == 1 => {
+                let item = self.child(items[0].clone());
+                visitor.visit_newtype_struct(item)
+            }
+            _ => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::List(ref items) | Value::Tuple(_, ref items) => {
+                let items = items.clone();
+                self.visit_seq(items, visitor)
+            }
+            Value::Unit => self.visit_seq(Vec::new(), visitor),
+            value => Err(de::Error::invalid_type(value.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::Map(ref entries) => {
+                let entries = entries.clone();
+                self.visit_map(entries, visitor)
+            }
+            Value::Struct(_, ref fields) => {
+                let entries = struct_entries(fields.clone());
+                self.visit_map(entries, visitor)
+            }
+            Value::Unit => self.visit_map(Vec::new(), visitor),
+            value => Err(de::Error::invalid_type(value.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        let (variant, content) = match self.value.clone() {
+            Value::Ident(variant) | Value::String(variant) => (variant, Value::Unit),
+            Value::Tuple(Some(variant), items) => (variant, Value::Tuple(None, items)),
+            Value::Struct(Some(variant), fields) => (variant, Value::Struct(None, fields)),
+            value => return Err(de::Error::invalid_type(value.unexpected(), &visitor)),
+        };
+        let content = self.child(content);
+        visitor.visit_enum(EnumDeserializer { variant, content })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self.value {
+            Value::Ident(s) | Value::String(s) => visitor.visit_string(s),
+            value => Err(de::Error::invalid_type(value.unexpected(), &visitor)),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf ignored_any
+    }
+}
+
+struct EnumDeserializer<'r> {
+    variant: String,
+    content: ValueDeserializer<'r>,
+}
+
+impl<'de, 'r> EnumAccess<'de> for EnumDeserializer<'r> {
+    type Error = ValueError;
+    type Variant = ValueDeserializer<'r>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, ValueDeserializer<'r>), ValueError> {
+        let variant = seed.deserialize(self.content.child(Value::Ident(self.variant)))?;
+        Ok((variant, self.content))
+    }
+}
+
+// the variant's contents, `Unit`, an unnamed `Tuple` or an unnamed `Struct`
+impl<'de, 'r> VariantAccess<'de> for ValueDeserializer<'r> {
+    type Error = ValueError;
+
+    fn unit_variant(self) -> Result<(), ValueError> {
+        match self.value {
+            Value::Unit => Ok(()),
+            Value::Tuple(_, ref items) if items.is_empty() => Ok(()),
+            value => Err(de::Error::invalid_type(value.unexpected(), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, ValueError> {
+        match self.value {
+            Value::Tuple(_, ref items) if items.len() == 1 => {
+                let item = self.child(items[0].clone());
+                seed.deserialize(item)
+            }
+            _ => seed.deserialize(self),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        self.deserialize_map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Dot,
+        Circle(i32),
+        Rect(i32, i32),
+        Named { width: i32 },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Everything {
+        escaped: String,
+        raw: String,
+        c: char,
+        b: bool,
+        i: i64,
+        f: f64,
+        pair: (i32, String),
+        list: Vec<u8>,
+        map: BTreeMap<String, i32>,
+        some: Option<i32>,
+        none: Option<i32>,
+        shapes: Vec<Shape>,
+    }
+
+    const EVERYTHING: &str = r##"Everything(
+        escaped: "a\n\t\"\\é\u{1F600}",
+        raw: r#"no "escapes" \n here"#,
+        c: '\'',
+        b: true,
+        i: -12,
+        f: 1.5,
+        pair: (3, "three"),
+        list: [1, 2, 3,],
+        map: {"one": 1, "two": 2},
+        some: Some(4),
+        none: None,
+        shapes: [Dot, Circle(2), Rect(3, 4), Named(width: 5)],
+    )"##;
+
+    fn everything() -> Everything {
+        Everything {
+            escaped: String::from("a\n\t\"\\\u{e9}\u{1F600}"),
+            raw: String::from(r#"no "escapes" \n here"#),
+            c: '\'',
+            b: true,
+            i: -12,
+            f: 1.5,
+            pair: (3, String::from("three")),
+            list: vec![1, 2, 3],
+            map: vec![(String::from("one"), 1), (String::from("two"), 2)]
+                .into_iter()
+                .collect(),
+            some: Some(4),
+            none: None,
+            shapes: vec![
+                Shape::Dot,
+                Shape::Circle(2),
+                Shape::Rect(3, 4),
+                Shape::Named { width: 5 },
+            ],
+        }
+    }
+
+    #[test]
+    fn parses_and_deserializes_every_literal() {
+        let value = from_str(EVERYTHING).unwrap();
+        assert_eq!(
+            value.clone().into_rust::<Everything>().unwrap(),
+            everything()
+        );
+
+        // and what it's written back out as reads the same
+        let written = value.to_string();
+        assert_eq!(from_str(&written).unwrap(), value);
+    }
+
+    #[test]
+    fn keeps_names() {
+        assert_eq!(
+            from_str("Rect(3, 4)").unwrap(),
+            Value::Tuple(
+                Some(String::from("Rect")),
+                vec![Value::Int(3), Value::Int(4)]
+            )
+        );
+        assert_eq!(
+            from_str("(width: 5)").unwrap(),
+            Value::Struct(None, vec![(String::from("width"), Value::Int(5))])
+        );
+        assert_eq!(
+            from_str("Upright").unwrap(),
+            Value::Ident(String::from("Upright"))
+        );
+    }
+
+    #[test]
+    fn dice_deserialize_to_their_lowest_roll_without_a_roller() {
+        assert_eq!(
+            from_str(r#""2d6+3""#).unwrap().into_rust::<i32>().unwrap(),
+            5
+        );
+        assert_eq!(from_str("(4, 9)").unwrap().into_rust::<i32>().unwrap(), 4);
+        let rolled = from_str(r#""2d6+3""#)
+            .unwrap()
+            .into_rust_rolled::<i32>(&|_| 11)
+            .unwrap();
+        assert_eq!(rolled, 11);
+    }
+
+    #[test]
+    fn merges_field_by_field() {
+        let parent = from_str(
+            r#"(
+                name: Some((name: "zombie")),
+                corporeal: Some((hp: 10, max_hp: 10)),
+                glyphs: ['z', 'Z'],
+                tags: {"undead": true},
+                seeing: Some((fov: 8)),
+            )"#,
+        )
+        .unwrap();
+        let child = from_str(
+            r#"(
+                corporeal: Some((hp: 20)),
+                glyphs: ['g'],
+                tags: {"fast": true},
+                seeing: None,
+                mobile: Some(()),
+            )"#,
+        )
+        .unwrap();
+        let expected = from_str(
+            r#"(
+                name: Some((name: "zombie")),
+                corporeal: Some((hp: 20, max_hp: 10)),
+                glyphs: ['g'],
+                tags: {"undead": true, "fast": true},
+                seeing: None,
+                mobile: Some(()),
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(parent.merge(child), expected);
+    }
+
+    #[test]
+    fn unit_over_a_struct_keeps_the_parent() {
+        let parent = from_str("Some((hp: 10))").unwrap();
+        let merged = parent.clone().merge(from_str("Some(())").unwrap());
+        assert_eq!(merged, parent);
+    }
+
+    fn error_at(source: &str) -> (String, Option<(usize, usize)>) {
+        let err = from_str(source).unwrap_err();
+        (err.message, err.position)
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        assert_eq!(
+            error_at("(\n  a: 1\n  b: 2,\n)"),
+            (String::from("expected `,` or `)`"), Some((3, 3)))
+        );
+        assert_eq!(
+            error_at("(a: 1) x"),
+            (String::from("trailing characters"), Some((1, 8)))
+        );
+        assert_eq!(error_at(r#""\q""#).0, "invalid escape");
+        assert_eq!(error_at("\"open").0, "unterminated string");
+    }
+
+    #[test]
+    fn rejects_bad_unicode_escapes() {
+        for source in &[
+            r#""\u好好""#,
+            r#""\u""#,
+            r#""\u{}""#,
+            r#""\u{123""#,
+            r#""\u{110000}""#,
+        ] {
+            assert_eq!(error_at(source).0, "invalid unicode escape", "{}", source);
+        }
+        // only four digits are taken without braces
+        assert_eq!(
+            from_str(r#""\u00e9f""#).unwrap(),
+            Value::String(String::from("\u{e9}f"))
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use shrev::{EventChannel, ReaderId};
+use specs::prelude::*;
+
+// events are what happened, where commands only ask for something to happen. the systems that
+// act write them to an `EventChannel` and anything that reacts, like the message log, the ai
+// noticing things or the statistics, reads them instead of looking for components left behind.
+//
+// a channel is created by the first system that reads or writes it, and every system that reads
+// one gets its own `reader` in `setup`. so don't insert the channels by hand, that would throw
+// away the readers
+
+// only sees the events written after it was made
+pub fn reader<T: Send + Sync + 'static>(world: &mut World) -> ReaderId<T> {
+    world
+        .entry::<EventChannel<T>>()
+        .or_insert_with(EventChannel::new)
+        .register_reader()
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MoveEvent {
+    pub entity: Entity,
+    pub start_x: i32,
+    pub start_y: i32,
+    pub dest_x: i32,
+    pub dest_y: i32,
+}
+
+impl MoveEvent {
+    pub fn new(entity: Entity, start_x: i32, start_y: i32, dest_x: i32, dest_y: i32) -> Self {
+        MoveEvent {
+            entity,
+            start_x,
+            start_y,
+            dest_x,
+            dest_y,
+        }
+    }
+}
+
+// something tried to move and bumped into something else, `None` for a wall or the edge of the
+// map
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct CollisionEvent {
+    pub collider: Entity,
+    pub collidee: Option<Entity>,
+}
+
+impl CollisionEvent {
+    pub fn new(collider: Entity, collidee: Option<Entity>) -> Self {
+        CollisionEvent { collider, collidee }
+    }
+}
+
+// an attack landed on `target`, before anything it wears or is gets a say
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub damage: i32,
+}
+
+impl AttackEvent {
+    pub fn new(attacker: Entity, target: Entity, damage: i32) -> Self {
+        AttackEvent {
+            attacker,
+            target,
+            damage,
+        }
+    }
+}
+
+// `deflected` attacks did no damage at all
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DamageEvent {
+    pub entity: Entity,
+    pub source: Entity,
+    pub amount: i32,
+    pub deflected: bool,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+}
+
+// an entity built from a blueprint, with the name of the blueprint
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpawnEvent {
+    pub entity: Entity,
+    pub blueprint: String,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct PickupEvent {
+    pub entity: Entity,
+    pub item: Entity,
+}
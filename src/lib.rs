@@ -0,0 +1,38 @@
+extern crate specs;
+
+#[macro_use]
+extern crate specs_derive;
+extern crate shred;
+extern crate shred_derive;
+extern crate shrev;
+extern crate toml;
+
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+extern crate mapgen;
+extern crate vecmap;
+
+extern crate arrayref;
+extern crate sha2;
+
+pub mod backend;
+pub mod bodyparts;
+pub mod command;
+pub mod components;
+pub mod config;
+pub mod dungeon;
+pub mod ecs;
+pub mod entity_factory;
+pub mod events;
+pub mod map;
+pub mod profiler;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod scenario;
+pub mod systems;
+pub mod terrain;
+pub mod time;
+
+use ecs::*;
@@ -1,50 +1,122 @@
-extern crate specs;
+extern crate rust_rl;
 
-#[macro_use]
-extern crate specs_derive;
-extern crate shred;
-extern crate shred_derive;
-extern crate shrev;
-extern crate toml;
-
-#[macro_use]
-extern crate log;
-extern crate env_logger;
-extern crate mapgen;
-extern crate vecmap;
-
-#[macro_use]
-extern crate arrayref;
-extern crate sha2;
-
-use specs::prelude::*;
+use rust_rl::config::{self, Config};
+use rust_rl::ecs;
+use rust_rl::replay::{Recording, Replay};
 use std::env;
-use tcod::console::*;
+use std::path::PathBuf;
 
-mod bodyparts;
-mod command;
-mod components;
-mod config;
-mod ecs;
-mod entity_factory;
-mod map;
-mod systems;
-mod time;
+fn main() {
+    // `--seed 1234` plays the same game as the last time that seed was used.
+    // `--replay last_run.replay` plays a recorded game back, `--fast` as quickly as possible,
+    // and `--stop-at 5000` gives control back at that tick.
+    // `--config other.toml` reads the settings from somewhere other than CONFIG.toml, and any
+    // setting can be given as a flag, `--map-width 120` or `--debug-vision true`
+    let mut config_path = None;
+    let mut settings = Vec::new();
+    let mut replay_path = None;
+    let mut fast_forward = false;
+    let mut stop_at = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => match args.next() {
+                Some(path) => config_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--config needs a file");
+                    return;
+                }
+            },
+            "--replay" => match args.next() {
+                Some(path) => replay_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--replay needs a recording");
+                    return;
+                }
+            },
+            "--fast" => fast_forward = true,
+            "--stop-at" => match args.next().map(|tick| tick.parse::<u64>()) {
+                Some(Ok(tick)) => stop_at = Some(tick),
+                _ => {
+                    eprintln!("--stop-at needs a tick");
+                    return;
+                }
+            },
+            arg if arg.starts_with("--") => match args.next() {
+                Some(value) => settings.push((Config::setting_from_flag(arg), value)),
+                None => {
+                    eprintln!("{} needs a value", arg);
+                    return;
+                }
+            },
+            arg => {
+                eprintln!("unknown argument `{}`", arg);
+                return;
+            }
+        }
+    }
 
-use config::*;
-use ecs::*;
-use shrev::EventChannel;
-use tcod::input::*;
+    // a missing CONFIG.toml is fine, a missing file that was asked for isn't
+    let config = match &config_path {
+        Some(path) => Config::open(path),
+        None => Config::open_or_default(config::DEFAULT_PATH),
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    let overrides = config
+        .apply_env()
+        .and_then(|_| {
+            settings
+                .iter()
+                .try_for_each(|(key, value)| config.set(key, value))
+        })
+        .and_then(|_| config.validate());
+    if let Err(err) = overrides {
+        eprintln!("{}", err);
+        return;
+    }
 
-#[macro_use]
-extern crate lazy_static;
+    let recording = match replay_path {
+        Some(path) => match Recording::load(&path) {
+            Ok(recording) => {
+                if recording.from_save {
+                    eprintln!(
+                        "warning: {} was carried on from a save, the replay won't match it",
+                        path.display()
+                    );
+                }
+                if let Err(err) = recording.check_settings(&config) {
+                    eprintln!("{}: {}", path.display(), err);
+                    return;
+                }
+                config.seed = Some(recording.seed);
+                Some(recording)
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        },
+        None => None,
+    };
 
-lazy_static! {
-    #[derive(Debug)]
-    static ref CONFIG: Config = Config::open();
-}
+    let mut ecs = match ecs::world_setup(config) {
+        Ok(ecs) => ecs,
+        Err(errors) => {
+            for err in errors {
+                eprintln!("{}", err);
+            }
+            return;
+        }
+    };
 
-fn main() {
-    let mut ecs = ecs::world_setup();
-    ecs.main_loop();
+    match recording {
+        Some(recording) => ecs.replay(Replay::new(recording, fast_forward, stop_at)),
+        None => ecs.main_loop(),
+    }
 }
@@ -1,5 +1,7 @@
-use crate::CONFIG;
+use crate::components::{Elevation, Position};
 use specs::prelude::*;
+use specs::world::Index;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tcod::map::Map as TcodMap;
 use vecmap::*;
@@ -15,7 +17,7 @@ pub struct EntityMap {
 
 impl EntityMap {
     pub fn new(width: usize, height: usize) -> Self {
-        let actor_map = ActorMap::filled_with(None, CONFIG.map_width, CONFIG.map_height);
+        let actor_map = ActorMap::filled_with(None, width as i32, height as i32);
 
         EntityMap {
             actors: actor_map,
@@ -25,6 +27,124 @@ impl EntityMap {
     }
 }
 
+// every entity with a position, by tile and elevation, so finding what's on a tile doesn't take
+// a join over every `Position`. kept up to date by `SpatialIndexer`, so it's as current as the
+// last time that ran. entities without an `Elevation` are upright, like they're drawn
+pub struct SpatialIndex {
+    width: i32,
+    height: i32,
+    // `LAYERS` lists per tile, lowest elevation first
+    tiles: Vec<Vec<Entity>>,
+    // where each entity was put, to find it again when it moves or goes. by index, because a
+    // deleted entity's generation has moved on by the time it's taken out
+    placed: HashMap<Index, (Entity, Position, Elevation)>,
+}
+
+const LAYERS: usize = 4;
+
+fn layer(elevation: Elevation) -> usize {
+    match elevation {
+        Elevation::Floor => 0,
+        Elevation::OnFloor => 1,
+        Elevation::Upright => 2,
+        Elevation::_InAir => 3,
+    }
+}
+
+impl SpatialIndex {
+    pub fn new(width: i32, height: i32) -> Self {
+        SpatialIndex {
+            width,
+            height,
+            tiles: vec![Vec::new(); (width * height).max(0) as usize * LAYERS],
+            placed: HashMap::new(),
+        }
+    }
+
+    fn idx(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y * self.width + x) as usize * LAYERS)
+    }
+
+    // puts `entity` at `pos`, taking it from wherever it was. off the map it's only taken away
+    pub fn insert(&mut self, entity: Entity, pos: Position, elevation: Elevation) {
+        if self.placed.get(&entity.id()) == Some(&(entity, pos, elevation)) {
+            return;
+        }
+        self.remove(entity);
+        if let Some(idx) = self.idx(pos.x, pos.y) {
+            self.tiles[idx + layer(elevation)].push(entity);
+            self.placed.insert(entity.id(), (entity, pos, elevation));
+        }
+    }
+
+    // takes out whatever has the index of `entity`, alive or not
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some((placed, pos, elevation)) = self.placed.remove(&entity.id()) {
+            let idx = self.idx(pos.x, pos.y).unwrap() + layer(elevation);
+            self.tiles[idx].retain(|ent| *ent != placed);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for tile in self.tiles.iter_mut() {
+            tile.clear();
+        }
+        self.placed.clear();
+    }
+
+    pub fn position(&self, entity: Entity) -> Option<Position> {
+        match self.placed.get(&entity.id()) {
+            Some((placed, pos, _)) if *placed == entity => Some(*pos),
+            _ => None,
+        }
+    }
+
+    // everything on the tile, lowest elevation first
+    pub fn at(&self, pos: Position) -> impl Iterator<Item = Entity> + '_ {
+        let tiles: &[Vec<Entity>] = match self.idx(pos.x, pos.y) {
+            Some(idx) => &self.tiles[idx..idx + LAYERS],
+            None => &[],
+        };
+        tiles.iter().flat_map(|tile| tile.iter().cloned())
+    }
+
+    pub fn at_elevation(&self, pos: Position, elevation: Elevation) -> &[Entity] {
+        match self.idx(pos.x, pos.y) {
+            Some(idx) => &self.tiles[idx + layer(elevation)],
+            None => &[],
+        }
+    }
+
+    // everything in the rectangle with its top left corner at (x, y), row by row
+    pub fn in_rect(&self, x: i32, y: i32, width: i32, height: i32) -> Vec<Entity> {
+        let mut found = Vec::new();
+        for y in i32::max(y, 0)..i32::min(y + height, self.height) {
+            for x in i32::max(x, 0)..i32::min(x + width, self.width) {
+                found.extend(self.at(Position::new(x, y)));
+            }
+        }
+        found
+    }
+
+    // everything no further than `radius` tiles from `center`, as the crow flies
+    pub fn in_radius(&self, center: Position, radius: i32) -> Vec<Entity> {
+        let side = radius * 2 + 1;
+        self.in_rect(center.x - radius, center.y - radius, side, side)
+            .into_iter()
+            .filter(|ent| match self.placed.get(&ent.id()) {
+                Some((_, pos, _)) => {
+                    let (dx, dy) = (pos.x - center.x, pos.y - center.y);
+                    dx * dx + dy * dy <= radius * radius
+                }
+                None => false,
+            })
+            .collect()
+    }
+}
+
 // use for pathfinding, fov, etc. essentially just a wrapper for tcod's map
 // currently not actually used for anything...
 pub struct View {
@@ -32,17 +152,93 @@ pub struct View {
     pub block_map: VecMap<BlockTile>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct BlockTile {
     pub blocks_movement: bool,
     pub blocks_sight: bool,
 }
 
-impl Default for BlockTile {
-    fn default() -> Self {
-        BlockTile {
-            blocks_movement: false,
-            blocks_sight: false,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `count` entities, alive in `world` so their indices and generations are real
+    fn entities(world: &mut World, count: usize) -> Vec<Entity> {
+        (0..count).map(|_| world.create_entity().build()).collect()
+    }
+
+    #[test]
+    fn clips_rects_to_the_map() {
+        let mut world = World::new();
+        let ents = entities(&mut world, 3);
+        let mut index = SpatialIndex::new(4, 3);
+        index.insert(ents[0], Position::new(0, 0), Elevation::Upright);
+        index.insert(ents[1], Position::new(3, 2), Elevation::Floor);
+        // off the map, so never found
+        index.insert(ents[2], Position::new(4, 2), Elevation::Upright);
+
+        assert_eq!(index.in_rect(-5, -5, 6, 6), vec![ents[0]]);
+        assert_eq!(index.in_rect(3, 2, 10, 10), vec![ents[1]]);
+        assert_eq!(index.in_rect(-10, -10, 30, 30), vec![ents[0], ents[1]]);
+        assert!(index.in_rect(4, 0, 2, 3).is_empty());
+        assert_eq!(index.position(ents[2]), None);
+    }
+
+    #[test]
+    fn radius_includes_its_edge() {
+        let mut world = World::new();
+        let ents = entities(&mut world, 4);
+        let mut index = SpatialIndex::new(9, 9);
+        let center = Position::new(4, 4);
+        index.insert(ents[0], center, Elevation::Upright);
+        index.insert(ents[1], Position::new(7, 4), Elevation::Upright);
+        // in the square but outside the circle
+        index.insert(ents[2], Position::new(7, 5), Elevation::Upright);
+        index.insert(ents[3], Position::new(6, 6), Elevation::OnFloor);
+
+        assert_eq!(index.in_radius(center, 3), vec![ents[0], ents[1], ents[3]]);
+        assert_eq!(index.in_radius(center, 0), vec![ents[0]]);
+    }
+
+    #[test]
+    fn moves_entities_it_already_has() {
+        let mut world = World::new();
+        let ents = entities(&mut world, 1);
+        let mut index = SpatialIndex::new(3, 3);
+        let (from, to) = (Position::new(0, 0), Position::new(2, 1));
+
+        index.insert(ents[0], from, Elevation::Upright);
+        index.insert(ents[0], from, Elevation::Upright);
+        assert_eq!(index.at(from).count(), 1);
+
+        index.insert(ents[0], to, Elevation::OnFloor);
+        assert_eq!(index.at(from).count(), 0);
+        assert_eq!(index.at_elevation(to, Elevation::OnFloor), &[ents[0]]);
+        assert!(index.at_elevation(to, Elevation::Upright).is_empty());
+        assert_eq!(index.position(ents[0]), Some(to));
+    }
+
+    #[test]
+    fn removes_entities_whose_generation_moved_on() {
+        let mut world = World::new();
+        let old = entities(&mut world, 1)[0];
+        let mut index = SpatialIndex::new(3, 3);
+        let pos = Position::new(1, 1);
+        index.insert(old, pos, Elevation::Upright);
+
+        world.delete_entity(old).unwrap();
+        world.maintain();
+        let new = world.create_entity().build();
+        assert_eq!(new.id(), old.id());
+        assert_ne!(new, old);
+        assert_eq!(index.position(new), None);
+
+        // the index is all it goes by, so the dead entity's spot is cleared
+        index.remove(new);
+        assert_eq!(index.at(pos).count(), 0);
+        assert_eq!(index.position(old), None);
+
+        index.insert(new, pos, Elevation::Upright);
+        assert_eq!(index.at(pos).collect::<Vec<_>>(), vec![new]);
     }
 }
@@ -0,0 +1,254 @@
+use crate::ecs::State;
+use crate::systems::profiler::RenderProfiler;
+use shred::RunningTime;
+use specs::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// where the timings are written when the game closes, with `profile = true`
+pub const PROFILE_PATH: &str = "profile.csv";
+
+// how many of the latest runs the rolling averages are over
+const ROLLING_RUNS: usize = 60;
+
+// the timings of a whole dispatch go under this name, next to its systems
+pub const DISPATCH: &str = "dispatch";
+
+// how long one system, or one whole dispatch, took every time it ran
+#[derive(Debug)]
+pub struct Timing {
+    pub runs: u64,
+    pub total: Duration,
+    pub max: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl Timing {
+    fn new() -> Self {
+        Timing {
+            runs: 0,
+            total: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+            recent: VecDeque::with_capacity(ROLLING_RUNS),
+        }
+    }
+
+    fn add(&mut self, elapsed: Duration) {
+        self.runs += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        if self.recent.len() == ROLLING_RUNS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.runs {
+            0 => Duration::from_secs(0),
+            // `runs` can be more than a `u32` holds, which `Duration` divides by
+            runs => Duration::from_secs_f64(self.total.as_secs_f64() / runs as f64),
+        }
+    }
+
+    // over the last `ROLLING_RUNS` runs
+    pub fn rolling(&self) -> Duration {
+        match self.recent.len() {
+            0 => Duration::from_secs(0),
+            len => self.recent.iter().sum::<Duration>() / len as u32,
+        }
+    }
+}
+
+// the wall clock time every system took in every dispatcher, and every dispatch as a whole. it's
+// shared between the systems, which run on their own threads, like `SaveRegistry` is shared
+// between the hooks, so it's behind a lock rather than a resource the systems would all have to
+// wait on
+#[derive(Default)]
+pub struct Profiler {
+    // keyed by the dispatcher, named after its state, and the system
+    timings: Mutex<HashMap<(String, String), Timing>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    pub fn record(&self, dispatcher: &str, system: &str, elapsed: Duration) {
+        self.timings
+            .lock()
+            .unwrap()
+            .entry((String::from(dispatcher), String::from(system)))
+            .or_insert_with(Timing::new)
+            .add(elapsed);
+    }
+
+    // (dispatcher, system, rolling average), slowest first
+    pub fn rolling(&self) -> Vec<(String, String, Duration)> {
+        let mut rolling: Vec<_> = self
+            .timings
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((dispatcher, system), timing)| {
+                (dispatcher.clone(), system.clone(), timing.rolling())
+            })
+            .collect();
+        rolling.sort_by_key(|(_, _, elapsed)| Reverse(*elapsed));
+        rolling
+    }
+
+    // one line for every system in every dispatcher, the slowest on average first
+    pub fn summary(&self) -> String {
+        let timings = self.timings.lock().unwrap();
+        let mut rows: Vec<_> = timings.iter().collect();
+        rows.sort_by_key(|(_, timing)| Reverse(timing.mean()));
+
+        let mut out = String::from("dispatcher,system,runs,total_ms,mean_ms,max_ms,rolling_ms\n");
+        for ((dispatcher, system), timing) in rows {
+            writeln!(
+                out,
+                "{},{},{},{:.3},{:.3},{:.3},{:.3}",
+                dispatcher,
+                system,
+                timing.runs,
+                millis(timing.total),
+                millis(timing.mean()),
+                millis(timing.max),
+                millis(timing.rolling())
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    pub fn write_summary<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.summary())
+    }
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// runs `system` and tells the profiler how long it took
+pub struct Timed<S> {
+    system: S,
+    dispatcher: String,
+    name: String,
+    profiler: Arc<Profiler>,
+}
+
+impl<'a, S> System<'a> for Timed<S>
+where
+    S: System<'a>,
+{
+    type SystemData = S::SystemData;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let start = Instant::now();
+        self.system.run(data);
+        self.profiler
+            .record(&self.dispatcher, &self.name, start.elapsed());
+    }
+
+    fn running_time(&self) -> RunningTime {
+        self.system.running_time()
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        self.system.setup(world);
+    }
+}
+
+// a `DispatcherBuilder` that times every system it's given when there's a profiler, and builds
+// the same dispatcher as a plain one when there isn't
+pub struct ProfiledBuilder<'a, 'b> {
+    builder: DispatcherBuilder<'a, 'b>,
+    dispatcher: String,
+    profiler: Option<Arc<Profiler>>,
+}
+
+impl<'a, 'b> ProfiledBuilder<'a, 'b> {
+    // the dispatcher of `state`, which its timings are listed under
+    pub fn new(state: State, profiler: Option<&Arc<Profiler>>) -> Self {
+        ProfiledBuilder {
+            builder: DispatcherBuilder::new(),
+            dispatcher: format!("{:?}", state),
+            profiler: profiler.cloned(),
+        }
+    }
+
+    pub fn with<S>(mut self, system: S, name: &str, dep: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'a,
+    {
+        self.builder = match &self.profiler {
+            Some(profiler) => {
+                let timed = Timed {
+                    system,
+                    dispatcher: self.dispatcher.clone(),
+                    name: String::from(name),
+                    profiler: profiler.clone(),
+                };
+                self.builder.with(timed, name, dep)
+            }
+            None => self.builder.with(system, name, dep),
+        };
+        self
+    }
+
+    pub fn with_barrier(mut self) -> Self {
+        self.builder = self.builder.with_barrier();
+        self
+    }
+
+    // the overlay with the rolling averages, drawn after `dep`. left out without a profiler
+    pub fn with_overlay(self, dep: &[&str]) -> Self {
+        match self.profiler.clone() {
+            Some(profiler) => self.with(RenderProfiler::new(profiler), "render_profiler_sys", dep),
+            None => self,
+        }
+    }
+
+    pub fn build(self) -> Dispatcher<'a, 'b> {
+        self.builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_and_sorts_the_timings() {
+        let profiler = Profiler::new();
+        for ms in &[1, 3] {
+            profiler.record("PlayerTurn", "input_sys", Duration::from_millis(*ms));
+        }
+        profiler.record("PlayerTurn", "render_sys", Duration::from_millis(5));
+
+        let rolling = profiler.rolling();
+        assert_eq!(rolling[0].1, "render_sys");
+        assert_eq!(rolling[1].2, Duration::from_millis(2));
+
+        let summary = profiler.summary();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("PlayerTurn,render_sys,1,"));
+        assert_eq!(lines[2], "PlayerTurn,input_sys,2,4.000,2.000,3.000,2.000");
+    }
+
+    #[test]
+    fn no_runs_is_no_time() {
+        assert_eq!(Timing::new().mean(), Duration::from_secs(0));
+        assert_eq!(Timing::new().rolling(), Duration::from_secs(0));
+    }
+}
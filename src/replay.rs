@@ -0,0 +1,236 @@
+use crate::command::Command;
+use crate::config::Config;
+use crate::entity_factory::value;
+use crate::systems::movement::Dir;
+use crate::systems::wizard::WizardCommand;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+pub const RECORDING_VERSION: u32 = 1;
+
+// every game played normally is recorded here, replay it with `--replay last_run.replay`
+pub const RECORDING_PATH: &str = "last_run.replay";
+
+// a recording is a header line followed by one line per command the player gave, written as
+// they happen so a crash still leaves everything up to it:
+//
+//     (version: 1, seed: 1234, from_save: false, settings: (map_width: 80, ...))
+//     (tick: 0, command: Move(E))
+//     (tick: 1000, command: Attack(N))
+//     (tick: 2000, command: Wizard(Spawn("creatures.zombie", (x: 4, y: 7))))
+//
+// replaying a recording starts a new game with the same seed and feeds the commands back in
+// place of the keyboard. games carried on from a save can't be replayed, the rest of the game
+// wasn't recorded
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    seed: u64,
+    from_save: bool,
+    settings: Settings,
+}
+
+// the config values that change how a game plays out. the same commands under different ones
+// make a different game, so a recording is only replayed with the ones it was recorded with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub map_width: i32,
+    pub map_height: i32,
+    pub base_turn_time: u32,
+    pub min_turn_time: u32,
+    pub debug_vision: bool,
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Self {
+        Settings {
+            map_width: config.map_width,
+            map_height: config.map_height,
+            base_turn_time: config.base_turn_time,
+            min_turn_time: config.min_turn_time,
+            debug_vision: config.debug_vision,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCommand {
+    // the world tick the command was given on
+    pub tick: u64,
+    pub command: PlayerCommand,
+}
+
+// the commands the player can give, `Command` itself can hold entities
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PlayerCommand {
+    Move(Dir),
+    Attack(Dir),
+    Descend,
+    Ascend,
+    PickUp,
+    Wizard(WizardCommand),
+}
+
+impl PlayerCommand {
+    pub fn from_command(command: &Command) -> Option<Self> {
+        match *command {
+            Command::Move(dir) => Some(PlayerCommand::Move(dir)),
+            Command::Attack(dir) => Some(PlayerCommand::Attack(dir)),
+            Command::Descend => Some(PlayerCommand::Descend),
+            Command::Ascend => Some(PlayerCommand::Ascend),
+            Command::PickUp => Some(PlayerCommand::PickUp),
+            Command::Wizard(ref command) => Some(PlayerCommand::Wizard(command.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn command(self) -> Command {
+        match self {
+            PlayerCommand::Move(dir) => Command::Move(dir),
+            PlayerCommand::Attack(dir) => Command::Attack(dir),
+            PlayerCommand::Descend => Command::Descend,
+            PlayerCommand::Ascend => Command::Ascend,
+            PlayerCommand::PickUp => Command::PickUp,
+            PlayerCommand::Wizard(command) => Command::Wizard(command),
+        }
+    }
+}
+
+pub struct Recording {
+    pub seed: u64,
+    pub from_save: bool,
+    pub settings: Settings,
+    pub commands: Vec<RecordedCommand>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let header: Header = match lines.next() {
+            Some((_, line)) => parse_line(line),
+            None => Err(String::from("empty recording")),
+        }
+        .map_err(|err| format!("{}:1: {}", path.display(), err))?;
+        if header.version != RECORDING_VERSION {
+            return Err(format!(
+                "{}: recording is version {}, this game reads version {}",
+                path.display(),
+                header.version,
+                RECORDING_VERSION
+            ));
+        }
+
+        let commands = lines
+            .map(|(i, line)| {
+                parse_line(line).map_err(|err| format!("{}:{}: {}", path.display(), i + 1, err))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Recording {
+            seed: header.seed,
+            from_save: header.from_save,
+            settings: header.settings,
+            commands,
+        })
+    }
+
+    // the config can't change while the game runs, so a recording made under other settings
+    // can't be replayed
+    pub fn check_settings(&self, config: &Config) -> Result<(), String> {
+        let current = Settings::from_config(config);
+        if self.settings == current {
+            return Ok(());
+        }
+        Err(format!(
+            "recorded with other settings, change them back to replay it\n  recorded: {}\n  config:   {}",
+            to_ron(&self.settings),
+            to_ron(&current)
+        ))
+    }
+}
+
+fn parse_line<T: DeserializeOwned>(line: &str) -> Result<T, String> {
+    value::from_str(line)
+        .and_then(|value| value.into_rust())
+        .map_err(|err| err.to_string())
+}
+
+// writes the player's commands to a recording as they're given. the default one doesn't write
+// anything
+#[derive(Default)]
+pub struct Recorder {
+    file: Option<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, seed: u64, from_save: bool, settings: Settings) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        let header = Header {
+            version: RECORDING_VERSION,
+            seed,
+            from_save,
+            settings,
+        };
+        writeln!(file, "{}", to_ron(&header))?;
+        Ok(Recorder { file: Some(file) })
+    }
+
+    pub fn record(&mut self, tick: u64, command: &Command) {
+        let command = match PlayerCommand::from_command(command) {
+            Some(command) => command,
+            None => return,
+        };
+        if let Some(file) = &mut self.file {
+            if let Err(err) = writeln!(file, "{}", to_ron(&RecordedCommand { tick, command })) {
+                error!("Failed to record command, recording stopped: {}", err);
+                self.file = None;
+            }
+        }
+    }
+}
+
+fn to_ron<T: Serialize>(value: &T) -> String {
+    ron::ser::to_string(value).expect("recordings only hold plain data")
+}
+
+// the recording being played back, see `systems::replay`. the default one has nothing to play
+#[derive(Default)]
+pub struct Replay {
+    commands: VecDeque<RecordedCommand>,
+    // a command every frame instead of at watching speed, with no frame limit
+    pub fast_forward: bool,
+    // hands control back to the player once the world reaches this tick
+    pub stop_at: Option<u64>,
+}
+
+impl Replay {
+    pub fn new(recording: Recording, fast_forward: bool, stop_at: Option<u64>) -> Self {
+        Replay {
+            commands: recording.commands.into_iter().collect(),
+            fast_forward,
+            stop_at,
+        }
+    }
+
+    // while this is true the keyboard only works to quit
+    pub fn is_playing(&self) -> bool {
+        !self.commands.is_empty()
+    }
+
+    pub fn next_command(&mut self) -> Option<RecordedCommand> {
+        self.commands.pop_front()
+    }
+
+    pub fn stop(&mut self) {
+        self.commands.clear();
+    }
+}
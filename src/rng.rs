@@ -0,0 +1,63 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// every random decision the game makes comes from one of these. each subsystem draws from its
+// own stream, so rendering a few more frames or rolling one more stat doesn't change the map
+// a seed makes. streams are only reproducible on the same build, `StdRng` can change between
+// versions of `rand`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Stream {
+    // the layout of the map
+    MapLayout,
+    // what gets spawned where
+    Spawning,
+    // dice and ranges in blueprints
+    Rolls,
+    // things that only change how the game looks, like random glyphs
+    Cosmetic,
+}
+
+// the world's random number generator, inserted as a resource
+pub struct GameRng {
+    seed: u64,
+    streams: HashMap<Stream, StdRng>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    // a seed for when none was given, printed at startup so the game can be played again
+    pub fn random_seed() -> u64 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+        mix(nanos ^ rand::thread_rng().gen::<u64>())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, stream: Stream) -> &mut StdRng {
+        let seed = self.seed;
+        self.streams
+            .entry(stream)
+            .or_insert_with(|| StdRng::seed_from_u64(mix(seed ^ mix(stream as u64 + 1))))
+    }
+}
+
+// splitmix64, spreads seeds that are close together far apart
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,928 @@
+use crate::bodyparts::BodyPart;
+use crate::command::Command;
+use crate::components::flags::requests::*;
+use crate::components::flags::ActionResult;
+use crate::components::*;
+use crate::dungeon::{Dungeon, StoredLevel};
+use crate::ecs::{GameState, MessageLog, State};
+use crate::entity_factory::value::{self, Value};
+use crate::map::EntityMap;
+use crate::systems::movement::Dir;
+use crate::systems::render::{SeenTiles, Tile};
+use crate::systems::statistics::Statistics;
+use crate::terrain::Terrain;
+use crate::time::{Turn, TurnQueue, WorldTime};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+// bumped whenever the save format changes in a way old saves can't be read with
+pub const SAVE_VERSION: u32 = 4;
+
+// written when the game closes and loaded when it starts, delete it to start a new game
+pub const AUTOSAVE_PATH: &str = "autosave.ron";
+
+// a save is a ron file holding the world's resources and every component of every entity:
+//
+//     (
+//         version: 4,
+//         map_size: (80, 25),
+//         entities: 2,
+//         state: PlayerTurn,
+//         statistics: (steps: 1, attacks: 0, damage_dealt: 0, ...),
+//         depth: 1,
+//         levels: [(2, 5000, [...], (width: 80, ...), (entities: 40, components: (...)))],
+//         world_time: (tick: 2000, world_turns: 2, player_turns: 1),
+//         messages: ["[TURN START]"],
+//         turn_queue: [(3000, 1)],
+//         actors: [(4, 7, 1)],
+//         seen: [...],
+//         terrain: (width: 80, height: 50, kinds: [...], cells: [...]),
+//         components: (
+//             name: [
+//                 (0, (name: "Player")),
+//                 (1, (name: "Zombie")),
+//             ],
+//             target: [
+//                 (1, (entity: 0, give_up_timer: 15, position: (x: 4, y: 6))),
+//             ],
+//         ),
+//     )
+//
+// entities are written as their number in the save, starting at 0, and get fresh entities
+// when loaded. components that point at other entities (`Target`, `Inventory`...)
+// are registered with `register_with` and swap their entities for those numbers and back.
+// references to entities that were already deleted are dropped.
+//
+// the levels the player has left are stored the same way, `stash` writes a set of entities
+// out and `unstash` brings them back. they're kept in the save under `levels` as
+// `(depth, tick they were left at, seen tiles, terrain, entities)`.
+pub struct SaveRegistry {
+    components: BTreeMap<String, Box<dyn ComponentSaver>>,
+}
+
+impl SaveRegistry {
+    pub fn new() -> Self {
+        SaveRegistry {
+            components: BTreeMap::new(),
+        }
+    }
+
+    // for components without entities in them, which are saved as they are
+    pub fn register<T>(&mut self, key: &str) -> &mut Self
+    where
+        T: Component + Clone + Serialize + DeserializeOwned + Send + Sync,
+        T::Storage: Default,
+    {
+        self.register_with(
+            key,
+            |component: &T, _| Some(component.clone()),
+            |saved: T, _| Ok(saved),
+        )
+    }
+
+    // `save` turns the component into an `S` that is written out instead, or `None` to leave it
+    // out of the save. `load` turns it back
+    pub fn register_with<T, S, F, G>(&mut self, key: &str, save: F, load: G) -> &mut Self
+    where
+        T: Component + Send + Sync,
+        T::Storage: Default,
+        S: Serialize + DeserializeOwned + 'static,
+        F: Fn(&T, &EntityIds) -> Option<S> + Send + Sync + 'static,
+        G: Fn(S, &EntityIds) -> Result<T, String> + Send + Sync + 'static,
+    {
+        let saver = Saver {
+            save,
+            load,
+            marker: PhantomData,
+        };
+        self.components.insert(String::from(key), Box::new(saver));
+        self
+    }
+
+    pub fn setup(&self, world: &mut World) {
+        for saver in self.components.values() {
+            saver.setup(world);
+        }
+    }
+
+    pub fn save(&self, world: &World, path: &Path) -> Result<(), SaveError> {
+        let ids = EntityIds::new(world.entities().join().collect());
+        let game_state = world.read_resource::<GameState>();
+        let turn_queue: Vec<(u64, u32)> = world
+            .read_resource::<TurnQueue>()
+            .iter()
+            .filter_map(|turn| Some((turn.tick, ids.id(turn.entity)?)))
+            .collect();
+        let entity_map = world.read_resource::<EntityMap>();
+        let actors: Vec<(i32, i32, u32)> = entity_map
+            .actors
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, actor)| {
+                let (x, y) = entity_map.actors.idx_xy(i);
+                Some((x, y, ids.id((*actor)?)?))
+            })
+            .collect();
+        let seen: Vec<Tile> = world
+            .read_resource::<SeenTiles>()
+            .tiles
+            .items
+            .iter()
+            .filter_map(|tile| *tile)
+            .collect();
+
+        let mut out = String::from("(\n");
+        write_field(&mut out, "version", &SAVE_VERSION)?;
+        let map_size = (entity_map.width as i32, entity_map.height as i32);
+        write_field(&mut out, "map_size", &map_size)?;
+        write_field(&mut out, "entities", &ids.entities.len())?;
+        write_field(&mut out, "state", &game_state.resumable())?;
+        write_field(&mut out, "world_time", &game_state.world_time)?;
+        write_field(
+            &mut out,
+            "statistics",
+            &*world.read_resource::<Statistics>(),
+        )?;
+        write_field(
+            &mut out,
+            "messages",
+            &world.read_resource::<MessageLog>().messages,
+        )?;
+        write_field(&mut out, "turn_queue", &turn_queue)?;
+        write_field(&mut out, "actors", &actors)?;
+        write_field(&mut out, "seen", &seen)?;
+        write_field(&mut out, "terrain", &*world.read_resource::<Terrain>())?;
+
+        let dungeon = world.read_resource::<Dungeon>();
+        write_field(&mut out, "depth", &dungeon.depth)?;
+        out.push_str("    levels: [\n");
+        for (depth, level) in dungeon.levels() {
+            let error = |err: ron::ser::Error| SaveError::Serialize {
+                key: String::from("levels"),
+                message: err.to_string(),
+            };
+            let seen = ron::ser::to_string(&level.seen).map_err(error)?;
+            let terrain = ron::ser::to_string(&level.terrain).map_err(error)?;
+            writeln!(
+                out,
+                "        ({}, {}, {}, {}, {}),",
+                depth, level.left_at, seen, terrain, level.entities
+            )
+            .unwrap();
+        }
+        out.push_str("    ],\n");
+
+        out.push_str("    components: (\n");
+        self.write_components(world, &ids, &mut out, "        ")?;
+        out.push_str("    ),\n)\n");
+
+        // written next to the save first, so quitting halfway through never leaves a broken one
+        let partial = path.with_extension("partial");
+        fs::write(&partial, out)
+            .and_then(|_| fs::rename(&partial, path))
+            .map_err(|error| SaveError::Io {
+                path: path.to_path_buf(),
+                error,
+            })
+    }
+
+    // replaces every entity in the world with the saved ones. the save is read in full before
+    // anything is replaced, so a save that fails to load leaves the world as it was
+    pub fn load(&self, world: &mut World, path: &Path) -> Result<(), SaveError> {
+        let parse_error = |message: String| SaveError::Parse {
+            path: path.to_path_buf(),
+            message,
+        };
+        let text = fs::read_to_string(path).map_err(|error| SaveError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let save = value::from_str(&text).map_err(|err| parse_error(err.to_string()))?;
+
+        let version: u32 = read_field(&save, "version").map_err(parse_error)?;
+        if version != SAVE_VERSION {
+            return Err(SaveError::Version {
+                path: path.to_path_buf(),
+                found: version,
+            });
+        }
+        // positions and the seen tiles only make sense on a map of the same size
+        let map_size: (i32, i32) = read_field(&save, "map_size").map_err(parse_error)?;
+        let expected = {
+            let entity_map = world.read_resource::<EntityMap>();
+            (entity_map.width as i32, entity_map.height as i32)
+        };
+        if map_size != expected {
+            return Err(SaveError::MapSize {
+                path: path.to_path_buf(),
+                found: map_size,
+                expected,
+            });
+        }
+        let count: u32 = read_field(&save, "entities").map_err(parse_error)?;
+        let state: State = read_field(&save, "state").map_err(parse_error)?;
+        let world_time: WorldTime = read_field(&save, "world_time").map_err(parse_error)?;
+        let statistics: Statistics = read_field(&save, "statistics").map_err(parse_error)?;
+        let messages: Vec<String> = read_field(&save, "messages").map_err(parse_error)?;
+        let turn_queue: Vec<(u64, u32)> = read_field(&save, "turn_queue").map_err(parse_error)?;
+        let actors: Vec<(i32, i32, u32)> = read_field(&save, "actors").map_err(parse_error)?;
+        let seen: Vec<Tile> = read_field(&save, "seen").map_err(parse_error)?;
+        let terrain: Terrain = read_field(&save, "terrain").map_err(parse_error)?;
+        let depth: u32 = read_field(&save, "depth").map_err(parse_error)?;
+        let levels = read_levels(&save).map_err(parse_error)?;
+
+        let old: Vec<Entity> = world.entities().join().collect();
+        let ids = EntityIds::new((0..count).map(|_| world.create_entity().build()).collect());
+        let read = || -> Result<_, String> {
+            let inserts = self.read_components(&save, &ids)?;
+            let turns = turn_queue
+                .iter()
+                .map(|&(tick, id)| {
+                    Ok(Turn {
+                        tick,
+                        entity: ids.entity(id)?,
+                    })
+                })
+                .collect::<Result<Vec<Turn>, String>>()?;
+            let actors = actors
+                .iter()
+                .map(|&(x, y, id)| Ok((x, y, ids.entity(id)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok((inserts, turns, actors))
+        };
+        let (inserts, turns, actors) = match read() {
+            Ok(read) => read,
+            Err(message) => {
+                world.delete_entities(&ids.entities).ok();
+                return Err(parse_error(message));
+            }
+        };
+
+        world.delete_entities(&old).ok();
+        for insert in inserts {
+            insert(world);
+        }
+        {
+            let mut game_state = world.write_resource::<GameState>();
+            game_state.reset(state);
+            game_state.world_time = world_time;
+        }
+        world.write_resource::<MessageLog>().messages = messages;
+        world.insert(statistics);
+        world.insert(Dungeon::with_levels(depth, levels));
+        world.write_resource::<Terrain>().replace(terrain);
+        {
+            let mut queue = world.write_resource::<TurnQueue>();
+            queue.clear();
+            queue.extend(turns);
+        }
+        {
+            let mut entity_map = world.write_resource::<EntityMap>();
+            entity_map.actors.reset_map();
+            for (x, y, entity) in actors {
+                entity_map.actors.set_point(x, y, Some(entity));
+            }
+        }
+        {
+            let mut seen_tiles = world.write_resource::<SeenTiles>();
+            seen_tiles.tiles.reset_map();
+            for tile in seen {
+                seen_tiles
+                    .tiles
+                    .set_point(tile.position.x, tile.position.y, Some(tile));
+            }
+        }
+        world.maintain();
+        Ok(())
+    }
+
+    // writes `entities` out as `(entities: 3, components: (...))`, to be brought back with
+    // `unstash`. references to entities that aren't in the stash are dropped
+    pub fn stash(&self, world: &World, entities: Vec<Entity>) -> Result<Value, SaveError> {
+        let ids = EntityIds::new(entities);
+        let mut out = format!("(entities: {}, components: (\n", ids.entities.len());
+        self.write_components(world, &ids, &mut out, "")?;
+        out.push_str("))");
+        value::from_str(&out).map_err(|err| SaveError::Serialize {
+            key: String::from("stash"),
+            message: err.to_string(),
+        })
+    }
+
+    // creates the stashed entities again. nothing is created if any of it can't be read
+    pub fn unstash(&self, world: &mut World, stash: &Value) -> Result<Vec<Entity>, String> {
+        let count: u32 = read_field(stash, "entities")?;
+        let ids = EntityIds::new((0..count).map(|_| world.create_entity().build()).collect());
+        match self.read_components(stash, &ids) {
+            Ok(inserts) => {
+                for insert in inserts {
+                    insert(world);
+                }
+                Ok(ids.entities)
+            }
+            Err(message) => {
+                world.delete_entities(&ids.entities).ok();
+                Err(message)
+            }
+        }
+    }
+
+    fn write_components(
+        &self,
+        world: &World,
+        ids: &EntityIds,
+        out: &mut String,
+        indent: &str,
+    ) -> Result<(), SaveError> {
+        for (key, saver) in &self.components {
+            let entries = saver
+                .save(world, ids)
+                .map_err(|message| SaveError::Serialize {
+                    key: key.clone(),
+                    message,
+                })?;
+            if entries.is_empty() {
+                continue;
+            }
+            writeln!(out, "{}{}: [", indent, key).unwrap();
+            for (id, text) in entries {
+                writeln!(out, "{}    ({}, {}),", indent, id, text).unwrap();
+            }
+            writeln!(out, "{}],", indent).unwrap();
+        }
+        Ok(())
+    }
+
+    // deserializes the `components` of a save or stash without touching the world
+    fn read_components(&self, save: &Value, ids: &EntityIds) -> Result<Vec<Insert>, String> {
+        let mut inserts = Vec::new();
+        if let Some(Value::Struct(_, components)) = save.field("components") {
+            for (key, entries) in components {
+                let saver = self
+                    .components
+                    .get(key)
+                    .ok_or_else(|| format!("unknown component key `{}`", key))?;
+                let entries = saved_entries(entries.clone(), ids)
+                    .and_then(|entries| saver.read(entries, ids))
+                    .map_err(|err| format!("{}: {}", key, err))?;
+                inserts.push(entries);
+            }
+        }
+        Ok(inserts)
+    }
+}
+
+impl Default for SaveRegistry {
+    fn default() -> Self {
+        let mut registry = SaveRegistry::new();
+        registry
+            .register::<Name>("name")
+            .register::<Actor>("actor")
+            .register::<PlayerControl>("player")
+            .register::<Camera>("camera")
+            .register::<Renderable>("renderable")
+            .register::<RandomRenderable>("random_renderable")
+            .register::<Corporeal>("corporeal")
+            .register::<Seeing>("seeing")
+            .register::<AiControl>("ai_control")
+            .register::<Mobile>("mobile")
+            .register::<Position>("position")
+            .register::<Invulnerable>("invulnerable")
+            .register::<BlockMovement>("blocks_movement")
+            .register::<BlockSight>("blocks_sight")
+            .register::<Elevation>("elevation")
+            .register::<Material>("material")
+            .register::<Carryable>("carryable")
+            .register::<Stairs>("stairs")
+            .register::<FromBlueprint>("from_blueprint")
+            .register::<PrintDebug>("print_debug")
+            .register::<MyTurn>("my_turn")
+            .register::<Floor>("floor")
+            .register::<OnFloor>("on_floor")
+            .register::<InView>("in_view")
+            .register::<Death>("death")
+            .register::<Corpse>("corpse")
+            .register::<CanSeeTarget>("can_see_target")
+            .register::<ActionResult>("action_result")
+            .register::<MoveRequest>("move_request")
+            .register::<AttackRequest>("attack_request")
+            .register::<PickupRequest>("pickup_request")
+            .register_with("target", save_target, load_target)
+            .register_with("inventory", save_inventory, load_inventory)
+            .register_with("body", save_body, load_body)
+            .register_with("command_sequence", save_commands, load_commands);
+        registry
+    }
+}
+
+// which number every entity has in a save
+pub struct EntityIds {
+    entities: Vec<Entity>,
+    ids: HashMap<Entity, u32>,
+}
+
+impl EntityIds {
+    fn new(entities: Vec<Entity>) -> Self {
+        let ids = entities
+            .iter()
+            .enumerate()
+            .map(|(id, entity)| (*entity, id as u32))
+            .collect();
+        EntityIds { entities, ids }
+    }
+
+    // `None` for entities that aren't in the save
+    pub fn id(&self, entity: Entity) -> Option<u32> {
+        self.ids.get(&entity).cloned()
+    }
+
+    pub fn entity(&self, id: u32) -> Result<Entity, String> {
+        self.entities
+            .get(id as usize)
+            .cloned()
+            .ok_or_else(|| format!("no entity {} in the save", id))
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    Version {
+        path: PathBuf,
+        found: u32,
+    },
+    MapSize {
+        path: PathBuf,
+        found: (i32, i32),
+        expected: (i32, i32),
+    },
+    Serialize {
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SaveError::Parse { path, message } => {
+                write!(f, "{}: could not load save: {}", path.display(), message)
+            }
+            SaveError::Version { path, found } => write!(
+                f,
+                "{}: save is version {}, this game reads version {}",
+                path.display(),
+                found,
+                SAVE_VERSION
+            ),
+            SaveError::MapSize {
+                path,
+                found,
+                expected,
+            } => write!(
+                f,
+                "{}: save is for a {}x{} map, the config has {}x{}",
+                path.display(),
+                found.0,
+                found.1,
+                expected.0,
+                expected.1
+            ),
+            SaveError::Serialize { key, message } => {
+                write!(f, "could not save `{}`: {}", key, message)
+            }
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+fn write_field<T: Serialize>(out: &mut String, key: &str, value: &T) -> Result<(), SaveError> {
+    let text = ron::ser::to_string(value).map_err(|err| SaveError::Serialize {
+        key: String::from(key),
+        message: err.to_string(),
+    })?;
+    writeln!(out, "    {}: {},", key, text).unwrap();
+    Ok(())
+}
+
+fn read_field<T: DeserializeOwned>(save: &Value, key: &str) -> Result<T, String> {
+    save.field(key)
+        .ok_or_else(|| format!("missing `{}`", key))?
+        .clone()
+        .into_rust()
+        .map_err(|err| format!("{}: {}", key, err))
+}
+
+fn read_levels(save: &Value) -> Result<Vec<(u32, StoredLevel)>, String> {
+    let levels = match save.field("levels") {
+        Some(Value::List(levels)) => levels,
+        _ => return Err(String::from("missing `levels`")),
+    };
+    levels
+        .iter()
+        .map(|level| match level {
+            Value::Tuple(None, fields) if fields.len() == 5 => {
+                let read = |i: usize| fields[i].clone();
+                let level = StoredLevel {
+                    left_at: read(1)
+                        .into_rust()
+                        .map_err(|err| format!("levels: {}", err))?,
+                    seen: read(2)
+                        .into_rust()
+                        .map_err(|err| format!("levels: {}", err))?,
+                    terrain: read(3)
+                        .into_rust()
+                        .map_err(|err| format!("levels: {}", err))?,
+                    entities: read(4),
+                };
+                let depth = read(0)
+                    .into_rust()
+                    .map_err(|err| format!("levels: {}", err))?;
+                Ok((depth, level))
+            }
+            _ => Err(String::from(
+                "levels: expected (depth, tick, seen tiles, terrain, entities)",
+            )),
+        })
+        .collect()
+}
+
+// the `(entity, component)` pairs saved under one component key
+fn saved_entries(entries: Value, ids: &EntityIds) -> Result<Vec<(Entity, Value)>, String> {
+    let entries = match entries {
+        Value::List(entries) => entries,
+        _ => return Err(String::from("expected a list of (entity, component) pairs")),
+    };
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Value::Tuple(None, mut pair) if pair.len() == 2 => {
+                let component = pair.pop().unwrap();
+                match pair.pop().unwrap() {
+                    Value::Int(id) if id >= 0 => Ok((ids.entity(id as u32)?, component)),
+                    _ => Err(String::from("expected an entity number")),
+                }
+            }
+            _ => Err(String::from("expected an (entity, component) pair")),
+        })
+        .collect()
+}
+
+type Insert = Box<dyn FnOnce(&World)>;
+
+trait ComponentSaver: Send + Sync {
+    fn setup(&self, world: &mut World);
+    fn save(&self, world: &World, ids: &EntityIds) -> Result<Vec<(u32, String)>, String>;
+    // deserializes the components without touching the world, the returned closure inserts them
+    fn read(&self, entries: Vec<(Entity, Value)>, ids: &EntityIds) -> Result<Insert, String>;
+}
+
+struct Saver<T, S, F, G> {
+    save: F,
+    load: G,
+    marker: PhantomData<fn(S) -> T>,
+}
+
+impl<T, S, F, G> ComponentSaver for Saver<T, S, F, G>
+where
+    T: Component + Send + Sync,
+    T::Storage: Default,
+    S: Serialize + DeserializeOwned + 'static,
+    F: Fn(&T, &EntityIds) -> Option<S> + Send + Sync,
+    G: Fn(S, &EntityIds) -> Result<T, String> + Send + Sync,
+{
+    fn setup(&self, world: &mut World) {
+        world.register::<T>();
+    }
+
+    fn save(&self, world: &World, ids: &EntityIds) -> Result<Vec<(u32, String)>, String> {
+        let mut entries = Vec::new();
+        for (entity, component) in (&world.entities(), &world.read_storage::<T>()).join() {
+            let id = match ids.id(entity) {
+                Some(id) => id,
+                None => continue,
+            };
+            if let Some(saved) = (self.save)(component, ids) {
+                let text = ron::ser::to_string(&saved).map_err(|err| err.to_string())?;
+                entries.push((id, text));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, entries: Vec<(Entity, Value)>, ids: &EntityIds) -> Result<Insert, String> {
+        let mut components = Vec::new();
+        for (entity, value) in entries {
+            let saved: S = value.into_rust().map_err(|err| err.to_string())?;
+            components.push((entity, (self.load)(saved, ids)?));
+        }
+        Ok(Box::new(move |world: &World| {
+            let mut storage = world.write_storage::<T>();
+            for (entity, component) in components {
+                storage.insert(entity, component).ok();
+            }
+        }))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedTarget {
+    entity: u32,
+    give_up_timer: u32,
+    position: Position,
+}
+
+fn save_target(target: &Target, ids: &EntityIds) -> Option<SavedTarget> {
+    Some(SavedTarget {
+        entity: ids.id(target.entity)?,
+        give_up_timer: target.give_up_timer,
+        position: target.position,
+    })
+}
+
+fn load_target(saved: SavedTarget, ids: &EntityIds) -> Result<Target, String> {
+    Ok(Target {
+        entity: ids.entity(saved.entity)?,
+        give_up_timer: saved.give_up_timer,
+        position: saved.position,
+    })
+}
+
+fn save_inventory(inventory: &Inventory, ids: &EntityIds) -> Option<Vec<u32>> {
+    Some(
+        inventory
+            .items
+            .iter()
+            .filter_map(|item| ids.id(*item))
+            .collect(),
+    )
+}
+
+fn load_inventory(saved: Vec<u32>, ids: &EntityIds) -> Result<Inventory, String> {
+    let items = saved
+        .into_iter()
+        .map(|id| ids.entity(id))
+        .collect::<Result<_, _>>()?;
+    Ok(Inventory { items })
+}
+
+// the body part tree, and the armor worn on each part in the order `BodyPart::walk` visits them
+fn save_body(body: &Body, ids: &EntityIds) -> Option<(BodyPart, Vec<Vec<u32>>)> {
+    let mut armor = Vec::new();
+    body.core().walk(&mut |part| {
+        armor.push(
+            part.equipped_armor
+                .iter()
+                .filter_map(|item| ids.id(*item))
+                .collect(),
+        );
+    });
+    Some((body.core().clone(), armor))
+}
+
+fn load_body(saved: (BodyPart, Vec<Vec<u32>>), ids: &EntityIds) -> Result<Body, String> {
+    let (mut core, armor) = saved;
+    let mut armor = armor.into_iter();
+    let mut result = Ok(());
+    core.walk_mut(&mut |part| {
+        for id in armor.next().unwrap_or_default() {
+            match ids.entity(id) {
+                Ok(item) => part.equipped_armor.push(item),
+                Err(err) => result = Err(err),
+            }
+        }
+    });
+    result.map(|_| Body::from_plan(&core))
+}
+
+// `Command` with the entity in `_Use` swapped for its number
+#[derive(Serialize, Deserialize)]
+enum SavedCommand {
+    Move(Dir),
+    Attack(Dir),
+    Descend,
+    Ascend,
+    PickUp,
+    _Use(u32),
+    _Rest,
+    _ToggleRealTime,
+    EndGame,
+}
+
+fn save_commands(sequence: &CommandSequence, ids: &EntityIds) -> Option<Vec<SavedCommand>> {
+    let commands = sequence
+        .commands
+        .iter()
+        .filter_map(|command| {
+            Some(match *command {
+                Command::Move(dir) => SavedCommand::Move(dir),
+                Command::Attack(dir) => SavedCommand::Attack(dir),
+                Command::Descend => SavedCommand::Descend,
+                Command::Ascend => SavedCommand::Ascend,
+                Command::PickUp => SavedCommand::PickUp,
+                Command::_Use(entity) => SavedCommand::_Use(ids.id(entity)?),
+                Command::_Rest => SavedCommand::_Rest,
+                Command::_ToggleRealTime => SavedCommand::_ToggleRealTime,
+                Command::EndGame => SavedCommand::EndGame,
+                // only ever typed by the player, never queued up
+                Command::Pause
+                | Command::OpenInventory
+                | Command::Target
+                | Command::OpenConsole
+                | Command::Wizard(_) => return None,
+            })
+        })
+        .collect();
+    Some(commands)
+}
+
+fn load_commands(saved: Vec<SavedCommand>, ids: &EntityIds) -> Result<CommandSequence, String> {
+    let commands = saved
+        .into_iter()
+        .map(|command| {
+            Ok(match command {
+                SavedCommand::Move(dir) => Command::Move(dir),
+                SavedCommand::Attack(dir) => Command::Attack(dir),
+                SavedCommand::Descend => Command::Descend,
+                SavedCommand::Ascend => Command::Ascend,
+                SavedCommand::PickUp => Command::PickUp,
+                SavedCommand::_Use(id) => Command::_Use(ids.entity(id)?),
+                SavedCommand::_Rest => Command::_Rest,
+                SavedCommand::_ToggleRealTime => Command::_ToggleRealTime,
+                SavedCommand::EndGame => Command::EndGame,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(CommandSequence { commands })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bodyparts::{ArmorTag, BodyPartTag};
+    use crate::config::Config;
+    use crate::ecs::State;
+    use std::process;
+
+    fn world(registry: &SaveRegistry) -> World {
+        let mut world = World::new();
+        registry.setup(&mut world);
+        world.insert(GameState::new(State::PlayerTurn));
+        world.insert(MessageLog::new());
+        world.insert(TurnQueue::new());
+        let config = Config::default();
+        let (width, height) = (config.map_width, config.map_height);
+        world.insert(EntityMap::new(width as usize, height as usize));
+        world.insert(SeenTiles::new(width, height));
+        world.insert(Terrain::new(width, height));
+        world.insert(Dungeon::new());
+        world.insert(Statistics::default());
+        world
+    }
+
+    fn named(world: &mut World, name: &str) -> Entity {
+        world
+            .create_entity()
+            .with(Name {
+                name: String::from(name),
+            })
+            .build()
+    }
+
+    fn find(world: &World, name: &str) -> Entity {
+        (&world.entities(), &world.read_storage::<Name>())
+            .join()
+            .find(|(_, n)| n.name == name)
+            .map(|(entity, _)| entity)
+            .unwrap_or_else(|| panic!("no {} after loading", name))
+    }
+
+    // a world with entities pointing at each other, saved to a file named after the test
+    fn saved_world(registry: &SaveRegistry, test: &str) -> (World, PathBuf) {
+        let mut world = world(registry);
+        // leaves a gap, so the saved numbers don't line up with the entity ids
+        let gone = named(&mut world, "Gone");
+        world.delete_entity(gone).unwrap();
+
+        let sword = named(&mut world, "Sword");
+        let helmet = named(&mut world, "Helmet");
+        let core = BodyPart {
+            name: String::from("Core"),
+            children: Vec::new(),
+            tags: vec![BodyPartTag::Core],
+            armor_tags: vec![ArmorTag::Head],
+            equipped_armor: vec![helmet],
+        };
+        let player = world
+            .create_entity()
+            .with(Name {
+                name: String::from("Player"),
+            })
+            .with(Inventory { items: vec![sword] })
+            .with(Body::from_plan(&core))
+            .build();
+        world
+            .create_entity()
+            .with(Name {
+                name: String::from("Zombie"),
+            })
+            .with(Target {
+                entity: player,
+                give_up_timer: 15,
+                position: Position::new(4, 6),
+            })
+            .build();
+        world.maintain();
+
+        let path = std::env::temp_dir().join(format!("rust_rl_{}_{}.ron", test, process::id()));
+        registry.save(&world, &path).unwrap();
+        (world, path)
+    }
+
+    // swaps one field in the save file for another
+    fn edit(path: &Path, from: &str, to: &str) {
+        let text = fs::read_to_string(path).unwrap();
+        assert!(text.contains(from), "the save has no {}", from);
+        fs::write(path, text.replace(from, to)).unwrap();
+    }
+
+    #[test]
+    fn entity_references_survive_a_round_trip() {
+        let registry = SaveRegistry::default();
+        let (mut world, path) = saved_world(&registry, "round_trip");
+        let old_player = find(&world, "Player");
+
+        let loaded = registry.load(&mut world, &path);
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        assert!(!world.is_alive(old_player));
+
+        let player = find(&world, "Player");
+        let zombie = find(&world, "Zombie");
+        let sword = find(&world, "Sword");
+        let helmet = find(&world, "Helmet");
+
+        let targets = world.read_storage::<Target>();
+        assert_eq!(targets.get(zombie).unwrap().entity, player);
+        let inventories = world.read_storage::<Inventory>();
+        assert_eq!(inventories.get(player).unwrap().items, [sword]);
+        let bodies = world.read_storage::<Body>();
+        assert_eq!(bodies.get(player).unwrap().core().equipped_armor, [helmet]);
+    }
+
+    #[test]
+    fn rejects_old_versions() {
+        let registry = SaveRegistry::default();
+        let (mut world, path) = saved_world(&registry, "old_version");
+        let version = |version| format!("version: {},", version);
+        edit(&path, &version(SAVE_VERSION), &version(SAVE_VERSION - 1));
+
+        let loaded = registry.load(&mut world, &path);
+        fs::remove_file(&path).unwrap();
+        match loaded {
+            Err(SaveError::Version { found, .. }) => assert_eq!(found, SAVE_VERSION - 1),
+            result => panic!("expected a version error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_other_map_sizes_before_touching_the_world() {
+        let registry = SaveRegistry::default();
+        let (mut world, path) = saved_world(&registry, "map_size");
+        let player = find(&world, "Player");
+        let config = Config::default();
+        let (width, height) = (config.map_width, config.map_height);
+        let map_size = |width, height| {
+            let size = ron::ser::to_string(&(width, height)).unwrap();
+            format!("map_size: {},", size)
+        };
+        let wider = map_size(width + 1, height);
+        edit(&path, &map_size(width, height), &wider);
+
+        let loaded = registry.load(&mut world, &path);
+        fs::remove_file(&path).unwrap();
+        match loaded {
+            Err(SaveError::MapSize { found, .. }) => assert_eq!(found, (width + 1, height)),
+            result => panic!("expected a map size error, got {:?}", result),
+        }
+        assert!(world.is_alive(player));
+    }
+}
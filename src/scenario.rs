@@ -0,0 +1,289 @@
+use crate::backend::{Headless, Key};
+use crate::components::{Actor, Corporeal, MyTurn, Position};
+use crate::config::Config;
+use crate::ecs::{self, Ecs, GameState, MessageLog, State};
+use crate::terrain;
+use specs::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+// builds a small world from an ascii map for tests, instead of generating a level:
+//
+//     let mut scenario = ScenarioBuilder::new(
+//         "
+//         #####
+//         #@.M#
+//         #####
+//         ",
+//     )
+//     .build()?;
+//     let monster = scenario.entity('M');
+//     scenario.press(Key::Char('l'));
+//     scenario.step_n(10);
+//     assert_eq!(scenario.position(scenario.player()), Some(Position::new(2, 1)));
+//
+// every character but a space gets a floor, and whatever the legend says on top of it. terrain
+// in the legend, like the walls, goes in the terrain grid instead and isn't returned by
+// `entities`. the map's top left corner is (0, 0), and indentation shared by every line is
+// ignored. it runs on a `Headless` backend, so nothing needs a window.
+pub struct ScenarioBuilder {
+    map: Vec<String>,
+    legend: BTreeMap<char, Option<String>>,
+    seed: u64,
+    config: Config,
+}
+
+impl ScenarioBuilder {
+    pub fn new(map: &str) -> Self {
+        let mut legend = BTreeMap::new();
+        legend.insert('.', None);
+        legend.insert('#', Some(String::from("terrain.base_wall")));
+        legend.insert('@', Some(String::from("player")));
+        legend.insert('M', Some(String::from("creatures.base_monster")));
+        legend.insert('h', Some(String::from("creatures.human")));
+        legend.insert('Z', Some(String::from("creatures.zombie")));
+        legend.insert('>', Some(String::from("terrain.stairs_down")));
+        legend.insert('<', Some(String::from("terrain.stairs_up")));
+
+        ScenarioBuilder {
+            map: trim_map(map),
+            legend,
+            seed: 0,
+            config: Config::default(),
+        }
+    }
+
+    // `blueprint` can also be a prefab, anchored on the character
+    pub fn legend(mut self, glyph: char, blueprint: &str) -> Self {
+        self.legend.insert(glyph, Some(String::from(blueprint)));
+        self
+    }
+
+    // the character is only floor
+    pub fn floor(mut self, glyph: char) -> Self {
+        self.legend.insert(glyph, None);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // the defaults otherwise, not whatever CONFIG.toml says. its seed is ignored, see `seed`
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn build(self) -> Result<Scenario, String> {
+        let height = self.map.len() as i32;
+        let width = self
+            .map
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as i32;
+        let config = self.config;
+        config.validate().map_err(|err| err.to_string())?;
+        if width > config.map_width || height > config.map_height {
+            return Err(format!(
+                "the map is {}x{}, it has to fit in {}x{}",
+                width, height, config.map_width, config.map_height
+            ));
+        }
+
+        let headless = Headless::new(config.screen_width, config.screen_height);
+        let mut ecs =
+            ecs::world_setup_with(headless.screen(), headless.keyboard(), self.seed, config)
+                .map_err(|errors| {
+                    let errors: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+                    errors.join("\n")
+                })?;
+
+        let mut entities: HashMap<char, Vec<Entity>> = HashMap::new();
+        for (y, line) in self.map.iter().enumerate() {
+            for (x, glyph) in line.chars().enumerate() {
+                if glyph == ' ' {
+                    continue;
+                }
+                let blueprint = self
+                    .legend
+                    .get(&glyph)
+                    .ok_or_else(|| format!("`{}` isn't in the legend", glyph))?;
+                let pos = Position::new(x as i32, y as i32);
+                spawn(&mut ecs, "terrain.base_floor", pos)?;
+                if let Some(blueprint) = blueprint {
+                    let spawned = spawn(&mut ecs, blueprint, pos)?;
+                    entities.entry(glyph).or_default().extend(spawned);
+                }
+            }
+        }
+
+        // the map is already there, so skip generating one
+        ecs.world_mut()
+            .write_resource::<GameState>()
+            .reset(State::TurnProcess);
+        ecs.world_mut().maintain();
+
+        Ok(Scenario {
+            ecs,
+            headless,
+            entities,
+        })
+    }
+}
+
+// terrain goes in the grid, and gives back no entities
+fn spawn(ecs: &mut Ecs, blueprint: &str, pos: Position) -> Result<Vec<Entity>, String> {
+    if terrain::place(ecs.world_mut(), blueprint, pos) {
+        return Ok(Vec::new());
+    }
+    let spawned = ecs.spawn(blueprint, Some(pos));
+    if spawned.is_empty() {
+        return Err(format!(
+            "could not spawn `{}` at ({}, {})",
+            blueprint, pos.x, pos.y
+        ));
+    }
+    Ok(spawned)
+}
+
+// drops blank lines around the map and the indentation every line shares
+fn trim_map(map: &str) -> Vec<String> {
+    let lines: Vec<&str> = map
+        .lines()
+        .map(|line| line.trim_end())
+        .skip_while(|line| line.is_empty())
+        .collect();
+    let end = lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(0, |i| i + 1);
+    let indent = lines[..end]
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines[..end]
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or("").to_string())
+        .collect()
+}
+
+pub struct Scenario {
+    ecs: Ecs,
+    headless: Headless,
+    entities: HashMap<char, Vec<Entity>>,
+}
+
+impl Scenario {
+    // one frame of whichever state the game is in, `TurnProcess` or `PlayerTurn`. false once
+    // the game has ended
+    pub fn step(&mut self) -> bool {
+        self.ecs.step()
+    }
+
+    pub fn step_n(&mut self, frames: usize) {
+        for _ in 0..frames {
+            if !self.step() {
+                return;
+            }
+        }
+    }
+
+    // steps until it's the player's turn to act, or gives up after `max_frames`
+    pub fn until_player_turn(&mut self, max_frames: usize) -> bool {
+        for _ in 0..max_frames {
+            if self.state() == State::PlayerTurn {
+                return true;
+            }
+            if !self.step() {
+                return false;
+            }
+        }
+        self.state() == State::PlayerTurn
+    }
+
+    // read the next time the player acts
+    pub fn press(&mut self, key: Key) {
+        self.headless.press(key);
+    }
+
+    pub fn state(&self) -> State {
+        self.world().read_resource::<GameState>().current()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.world().read_resource::<GameState>().world_time.tick
+    }
+
+    // everything placed with `glyph`, left to right and top to bottom. prefabs add all of their
+    // members
+    pub fn entities(&self, glyph: char) -> &[Entity] {
+        self.entities.get(&glyph).map_or(&[], |entities| entities)
+    }
+
+    // the first thing placed with `glyph`
+    pub fn entity(&self, glyph: char) -> Entity {
+        *self
+            .entities(glyph)
+            .first()
+            .unwrap_or_else(|| panic!("nothing was placed with `{}`", glyph))
+    }
+
+    pub fn player(&self) -> Entity {
+        self.entity('@')
+    }
+
+    pub fn position(&self, entity: Entity) -> Option<Position> {
+        self.world().read_storage::<Position>().get(entity).cloned()
+    }
+
+    pub fn hp(&self, entity: Entity) -> Option<i32> {
+        self.world()
+            .read_storage::<Corporeal>()
+            .get(entity)
+            .map(|corporeal| corporeal.hp)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.world().is_alive(entity)
+    }
+
+    pub fn has_turn(&self, entity: Entity) -> bool {
+        self.world().read_storage::<MyTurn>().contains(entity)
+    }
+
+    // every actor by the tick of its next turn, soonest first
+    pub fn turn_order(&self) -> Vec<(u64, Entity)> {
+        let world = self.world();
+        let mut order: Vec<(u64, Entity)> = (&world.entities(), &world.read_storage::<Actor>())
+            .join()
+            .map(|(entity, actor)| (actor.next_turn, entity))
+            .collect();
+        order.sort();
+        order
+    }
+
+    // newest first, the way the game keeps them
+    pub fn messages(&self) -> Vec<String> {
+        self.world().read_resource::<MessageLog>().messages.clone()
+    }
+
+    pub fn screen(&self) -> &Headless {
+        &self.headless
+    }
+
+    pub fn world(&self) -> &World {
+        self.ecs.world()
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        self.ecs.world_mut()
+    }
+
+    pub fn ecs(&mut self) -> &mut Ecs {
+        &mut self.ecs
+    }
+}
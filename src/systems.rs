@@ -2,10 +2,21 @@ pub mod action;
 pub mod actor_setup;
 pub mod ai;
 pub mod combat;
+pub mod effects;
+pub mod event_log;
 pub mod input;
+pub mod inspector;
+pub mod items;
 pub mod mapgen;
+pub mod menu;
 pub mod movement;
 pub mod naming;
+pub mod profiler;
 pub mod render;
+pub mod replay;
+pub mod spatial;
+pub mod statistics;
 pub mod stats;
+pub mod targeting;
 pub mod time;
+pub mod wizard;
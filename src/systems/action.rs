@@ -1,20 +1,20 @@
 use crate::command::{Command, CommandEvent};
 use crate::components::flags::requests::*;
+use crate::components::flags::ActionResult;
 use crate::components::*;
+use crate::dungeon::Dungeon;
 use crate::State;
+use crate::config::Config;
 use crate::systems::movement::Dir;
 use shrev::{EventChannel, ReaderId};
 use specs::prelude::*;
 
-pub struct ActionHandler {
-    command_event_reader: Option<ReaderId<CommandEvent>>,
-}
+#[derive(Default)]
+pub struct ActionHandler;
 
 impl ActionHandler {
     pub fn new() -> Self {
-        ActionHandler {
-            command_event_reader: None,
-        }
+        ActionHandler
     }
 }
 
@@ -23,8 +23,12 @@ pub struct ActionHandlerSystemData<'a> {
     move_requests: WriteStorage<'a, MoveRequest>,
     players: WriteStorage<'a, PlayerControl>,
     attack_requests: WriteStorage<'a, AttackRequest>,
+    pickup_requests: WriteStorage<'a, PickupRequest>,
     my_turns: WriteStorage<'a, MyTurn>,
     game_state: WriteExpect<'a, crate::GameState>,
+    dungeon: WriteExpect<'a, Dungeon>,
+    action_results: WriteStorage<'a, ActionResult>,
+    config: ReadExpect<'a, Config>,
 
     command_event_reader: WriteExpect<'a, ReaderId<CommandEvent>>,
 
@@ -46,7 +50,11 @@ impl<'a> System<'a> for ActionHandler {
         for command_event in command_events {
             let entity = command_event.entity;
             // println!("{:?}: {:?}", command_event.entity, &command_event.command);
+            let mut next_state = State::TurnProcess;
             match command_event.command {
+                // carried out by `Wizard`
+                Command::Wizard(_) => continue,
+
                 Command::Move(dir) => {
                     let (dx, dy) = Dir::dir_to_pos(dir);
                     let move_request = MoveRequest::new(dx, dy);
@@ -63,17 +71,32 @@ impl<'a> System<'a> for ActionHandler {
                     }
                     // println!("added attack request");
                 }
+
+                Command::PickUp => {
+                    if let Err(err) = data.pickup_requests.insert(entity, PickupRequest) {
+                        error!("Failed to insert pickup request: {}", err)
+                    }
+                }
+
+                // `Input` already checked there are stairs to take
+                Command::Descend | Command::Ascend => {
+                    let depth = data.dungeon.depth;
+                    data.dungeon.destination = Some(match command_event.command {
+                        Command::Descend => depth + 1,
+                        _ => u32::max(depth - 1, 1),
+                    });
+                    let result = ActionResult::from(data.config.base_turn_time);
+                    if let Err(err) = data.action_results.insert(entity, result) {
+                        error!("Failed to insert action result: {}", err)
+                    }
+                    next_state = State::ChangeLevel;
+                }
                 _ => (),
             }
             data.my_turns.remove(entity);
-            if let Some(_) = data.players.get(entity) {
-                data.game_state.transition(State::TurnProcess);
-                // println!("turned off", );
+            if data.players.get(entity).is_some() {
+                data.game_state.transition(next_state);
             }
-            // println!("removed my turn", );
         }
     }
-
-    fn setup(&mut self, world: &mut World) {
-    }
 }
@@ -1,5 +1,4 @@
 use crate::components::*;
-use crate::systems::ai::types::AiType;
 use specs::prelude::*;
 
 #[derive(SystemData)]
@@ -14,10 +13,14 @@ impl<'a> System<'a> for ActorSetup {
     type SystemData = ActorSetupSystemData<'a>;
 
     fn run(&mut self, mut data: Self::SystemData) {
-        for (ent, actor) in (&data.entities, &data.actors).join() {
-            if data.command_sequences.get(ent) == None {
-                data.command_sequences
-                    .insert(ent, CommandSequence::default());
+        for (ent, _actor) in (&data.entities, &data.actors).join() {
+            if data.command_sequences.get(ent).is_none() {
+                if let Err(err) = data
+                    .command_sequences
+                    .insert(ent, CommandSequence::default())
+                {
+                    error!("Failed to insert command sequence: {}", err)
+                }
             }
         }
     }
@@ -5,13 +5,13 @@ use crate::systems::movement::Dir;
 use shrev::EventChannel;
 use specs::prelude::*;
 mod pathfinding;
+pub mod perception;
 pub mod thinking;
 pub mod types;
 use crate::MessageLog;
 use thinking::Thinking;
 use types::monster::*;
 use types::AiType;
-use crate::ecs::State;
 
 // use std::sync::{Arc, Mutex};
 
@@ -63,10 +63,6 @@ impl<'a> System<'a> for Ai {
         )
             .join()
         {
-            let mut command_sequence: Vec<Command> = Vec::new();
-            let mut command_event: Option<CommandEvent> = None;
-
-            let mut command = Command::Move(Dir::Nowhere);
             let mut reset_command_sequence = false;
             let ai_type = ai_unit.ai_type;
 
@@ -81,14 +77,12 @@ impl<'a> System<'a> for Ai {
             }
 
             // if nothing in command sequence, get the command
-            command_sequence = match sequence.commands.is_empty() || reset_command_sequence {
+            let mut command_sequence = match sequence.commands.is_empty() || reset_command_sequence {
                 true => Self::get_command(ent, ai_type, &data),
                 false => sequence.commands.clone(),
             };
 
-            //            println!("{:?}", &command_sequence);
-
-            command_event = Some(CommandEvent::new(command_sequence.pop().unwrap(), ent));
+            let command_event = Some(CommandEvent::new(command_sequence.pop().unwrap(), ent));
             commands.push((command_sequence, command_event));
         }
 
@@ -110,16 +104,9 @@ impl<'a> System<'a> for Ai {
             .join()
         {
             target.decrement_timer();
-            // println!("{}", target.give_up_timer);
-            if target.give_up_timer <= 0 {
+            if target.give_up_timer == 0 {
                 data.world_updater.remove::<Target>(entity);
             }
         }
     }
-
-    fn setup(&mut self, world: &mut World) {
-        Self::SystemData::setup(world);
-        let command_event_channel: EventChannel<CommandEvent> = EventChannel::new();
-        world.insert(command_event_channel);
-    }
 }
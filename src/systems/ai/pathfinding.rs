@@ -16,16 +16,13 @@ pub fn rank_distance(
     let x = point.0;
     let y = point.1;
     pathfinder.find((x, y));
-    // println!("{}", num_steps);
     let ranking = (((dest.0 - x).pow(2) + (dest.1 - y).pow(2)) as f32).sqrt() as i32;
-    if !fov_map.is_walkable(x as i32, y as i32) {
+    if !fov_map.is_walkable(x, y) {
         return None;
     }
 
-    if let Ok(point) = entity_map.actors.retrieve(x as i32, y as i32) {
-        if let Some(actor) = point {
-            return None;
-        }
+    if let Ok(Some(_actor)) = entity_map.actors.retrieve(x, y) {
+        return None;
     }
     Some((ranking, (x, y)))
 }
@@ -52,10 +49,9 @@ pub fn choose_close_point(
     if rankings.is_empty() {
         return start;
     }
-    rankings.sort_by(|a, b| a.0.cmp(&b.0));
+    rankings.sort_by_key(|ranking| ranking.0);
 
-    let closest = ((rankings[0].1).0 as i32, (rankings[0].1).1 as i32);
-    closest
+    rankings[0].1
 }
 
 pub fn path_to_target(entity: Entity, data: &AiSystemData) -> Vec<Dir> {
@@ -68,7 +64,7 @@ pub fn path_to_target(entity: Entity, data: &AiSystemData) -> Vec<Dir> {
             let mut fov_map = data.view.map.lock().unwrap();
 
             fov_map.compute_fov(pos.x, pos.y, seer.fov, true, FovAlgorithm::Basic);
-            let mut step_pos = (pos.x, pos.y);
+            let step_pos = (pos.x, pos.y);
 
             let mut pathfinder = Dijkstra::new_from_map(fov_map.clone(), f32::sqrt(2.0));
             pathfinder.compute_grid(step_pos);
@@ -82,7 +78,7 @@ pub fn path_to_target(entity: Entity, data: &AiSystemData) -> Vec<Dir> {
             );
 
             if pathfinder.find((dest_point.0, dest_point.1)) {
-                if let Some(_) = pathfinder.get(0) {
+                if pathfinder.get(0).is_some() {
                     let mut path = Vec::new();
                     for step in pathfinder.iter() {
                         let dx = step.0 - pos.x;
@@ -0,0 +1,154 @@
+use crate::components::{AiControl, CanSeeTarget, PlayerControl, Position, Seeing, Target};
+use crate::events::{self, AttackEvent, CollisionEvent, MoveEvent, SpawnEvent};
+use crate::map::View;
+use shrev::{EventChannel, ReaderId};
+use specs::prelude::*;
+use std::collections::HashSet;
+use tcod::map::FovAlgorithm;
+
+// keeps track of which players the ai can see, by `Target` and `CanSeeTarget`. instead of
+// looking around every turn, an ai looks again when it or a player moves or turns up, and
+// notices whoever bumps into it or attacks it
+#[derive(Default)]
+pub struct Perception {
+    move_reader: Option<ReaderId<MoveEvent>>,
+    spawn_reader: Option<ReaderId<SpawnEvent>>,
+    collision_reader: Option<ReaderId<CollisionEvent>>,
+    attack_reader: Option<ReaderId<AttackEvent>>,
+}
+
+impl Perception {
+    pub fn new() -> Self {
+        Perception::default()
+    }
+
+    fn in_range(pos: Position, other: Position, fov: i32) -> bool {
+        // tcod takes a radius of 0 as no limit
+        fov <= 0 || (i32::abs(pos.x - other.x) <= fov && i32::abs(pos.y - other.y) <= fov)
+    }
+
+    // the ai turns to face the player, unless it's already after them
+    fn notice(ai: Entity, player: Entity, data: &mut PerceptionSystemData) {
+        let pos = match data.positions.get(player) {
+            Some(pos) => *pos,
+            None => return,
+        };
+        data.sees_targets.insert(ai, CanSeeTarget {}).ok();
+        if data
+            .targets
+            .get(ai)
+            .is_none_or(|target| target.entity != player)
+        {
+            data.targets.insert(ai, Target::new(player, pos)).ok();
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct PerceptionSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub players: ReadStorage<'a, PlayerControl>,
+    pub ai_units: ReadStorage<'a, AiControl>,
+    pub seers: ReadStorage<'a, Seeing>,
+    pub positions: ReadStorage<'a, Position>,
+    pub targets: WriteStorage<'a, Target>,
+    pub sees_targets: WriteStorage<'a, CanSeeTarget>,
+    pub view: ReadExpect<'a, View>,
+
+    // read event channels
+    pub move_events: Read<'a, EventChannel<MoveEvent>>,
+    pub spawn_events: Read<'a, EventChannel<SpawnEvent>>,
+    pub collision_events: Read<'a, EventChannel<CollisionEvent>>,
+    pub attack_events: Read<'a, EventChannel<AttackEvent>>,
+}
+
+impl<'a> System<'a> for Perception {
+    type SystemData = PerceptionSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        // everything that showed up somewhere new
+        let appeared: HashSet<Entity> = data
+            .move_events
+            .read(self.move_reader.as_mut().unwrap())
+            .map(|event| event.entity)
+            .chain(
+                data.spawn_events
+                    .read(self.spawn_reader.as_mut().unwrap())
+                    .map(|event| event.entity),
+            )
+            .collect();
+        // whoever came into contact with an ai, and the ai
+        let touched: Vec<(Entity, Entity)> = data
+            .collision_events
+            .read(self.collision_reader.as_mut().unwrap())
+            .filter_map(|event| Some((event.collidee?, event.collider)))
+            .chain(
+                data.attack_events
+                    .read(self.attack_reader.as_mut().unwrap())
+                    .map(|event| (event.target, event.attacker)),
+            )
+            .collect();
+
+        // the ai and player pairs that have to look again
+        let mut pairs = Vec::new();
+        for ent in appeared {
+            if data.players.get(ent).is_some() {
+                let player_pos = match data.positions.get(ent) {
+                    Some(pos) => *pos,
+                    None => continue,
+                };
+                for (ai, _ai_unit, seer, pos) in
+                    (&data.entities, &data.ai_units, &data.seers, &data.positions).join()
+                {
+                    let watching = data.sees_targets.get(ai).is_some()
+                        && data.targets.get(ai).map(|target| target.entity) == Some(ent);
+                    if watching || Self::in_range(*pos, player_pos, seer.fov) {
+                        pairs.push((ai, ent));
+                    }
+                }
+            }
+            if data.ai_units.get(ent).is_some() && data.seers.get(ent).is_some() {
+                for (player, _player) in (&data.entities, &data.players).join() {
+                    pairs.push((ent, player));
+                }
+            }
+        }
+
+        for (ai, player) in pairs {
+            let in_fov = {
+                let (pos, seer) = match (data.positions.get(ai), data.seers.get(ai)) {
+                    (Some(pos), Some(seer)) => (*pos, seer.fov),
+                    _ => continue,
+                };
+                let player_pos = match data.positions.get(player) {
+                    Some(pos) => *pos,
+                    None => continue,
+                };
+                let mut fov_map = data.view.map.lock().unwrap();
+                fov_map.compute_fov(pos.x, pos.y, seer, true, FovAlgorithm::Basic);
+                fov_map.is_in_fov(player_pos.x, player_pos.y)
+            };
+
+            if in_fov {
+                Self::notice(ai, player, &mut data);
+            } else if data.targets.get(ai).map(|target| target.entity) == Some(player) {
+                // lost sight of them, `Ai` gives up the chase after a while
+                data.sees_targets.remove(ai);
+            }
+        }
+
+        for (ent, other) in touched {
+            if data.ai_units.get(ent).is_some() && data.players.get(other).is_some() {
+                Self::notice(ent, other, &mut data);
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.move_reader = Some(events::reader(world));
+        self.spawn_reader = Some(events::reader(world));
+        self.collision_reader = Some(events::reader(world));
+        self.attack_reader = Some(events::reader(world));
+    }
+}
@@ -1,8 +1,8 @@
 pub mod dummy;
 pub mod monster;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, Deserialize)]
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
 pub enum AiType {
     Monster,
     _Dummy,
@@ -2,10 +2,8 @@ use super::super::pathfinding;
 use super::super::thinking::Thinking;
 use super::super::AiSystemData;
 use crate::command::Command;
-use crate::components::{CanSeeTarget, Target};
 use crate::systems::movement::Dir;
 use specs::prelude::*;
-use tcod::map::FovAlgorithm;
 
 pub struct Monster;
 
@@ -34,40 +32,13 @@ impl Thinking for Monster {
                 }
             }
         }
-        return vec![Command::Move(Dir::Nowhere)];
+        vec![Command::Move(Dir::Nowhere)]
     }
 }
 
 impl Monster {
+    // `Perception` gives it a target once it has seen the player
     fn can_target_player(entity: Entity, data: &AiSystemData) -> bool {
-        let mut fov_map = data.view.map.lock().unwrap();
-
-        if let (Some(pos), Some(seer)) = (data.positions.get(entity), data.seers.get(entity)) {
-            for (player_entity, target_pos, _player) in
-                (&data.entities, &data.positions, &data.players).join()
-            {
-                fov_map.compute_fov(pos.x, pos.y, seer.fov, true, FovAlgorithm::Basic);
-                let target_is_in_fov = fov_map.is_in_fov(target_pos.x, target_pos.y);
-
-                if data.targets.get(entity) != None {
-                    if data.sees_targets.get(entity) != None && !target_is_in_fov {
-                        // println!("lost sight");
-                        data.world_updater.remove::<CanSeeTarget>(entity)
-                    }
-                    return true;
-                }
-
-                if target_is_in_fov {
-                    if data.sees_targets.get(entity) == None {
-                        // println!("gained sight");
-                        data.world_updater.insert(entity, CanSeeTarget {});
-                        data.world_updater
-                            .insert(entity, Target::new(player_entity, *target_pos));
-                        return true;
-                    }
-                }
-            }
-        }
-        false
+        data.targets.get(entity).is_some()
     }
 }
@@ -2,10 +2,12 @@ use crate::components::flags::requests::*;
 use crate::components::flags::*;
 use crate::components::Elevation;
 use crate::components::*;
-use crate::ecs::MessageLog;
+use crate::config::Config;
+use crate::ecs::{GameState, State};
+use crate::events::{self, AttackEvent, DamageEvent, DeathEvent};
 use crate::map::*;
 use crate::systems::movement::Dir;
-use crate::CONFIG;
+use shrev::{EventChannel, ReaderId};
 use specs::prelude::*;
 
 pub struct Attack;
@@ -20,22 +22,28 @@ pub struct CombatSystemData<'a> {
     pub world_updater: Read<'a, LazyUpdate>,
     pub positions: ReadStorage<'a, Position>,
     pub floors: ReadStorage<'a, Floor>,
-    pub message_log: WriteExpect<'a, MessageLog>,
     pub names: WriteStorage<'a, Name>,
     pub mobiles: ReadStorage<'a, Mobile>,
     pub corporeals: WriteStorage<'a, Corporeal>,
-    pub defenders: WriteStorage<'a, Defending>,
     pub invulnerables: ReadStorage<'a, Invulnerable>,
     pub bodies: WriteStorage<'a, Body>,
     pub deaths: WriteStorage<'a, Death>,
     pub corpses: WriteStorage<'a, Corpse>,
     pub entity_map: WriteExpect<'a, EntityMap>,
+    pub spatial_index: ReadExpect<'a, SpatialIndex>,
     pub renderables: WriteStorage<'a, Renderable>,
     pub move_requests: WriteStorage<'a, MoveRequest>,
     pub ai_units: WriteStorage<'a, AiControl>,
     pub view: WriteExpect<'a, View>,
     pub players: ReadStorage<'a, PlayerControl>,
     pub elevations: WriteStorage<'a, Elevation>,
+    pub game_state: WriteExpect<'a, GameState>,
+    pub config: ReadExpect<'a, Config>,
+
+    // events
+    pub attack_events: Write<'a, EventChannel<AttackEvent>>,
+    pub damage_events: Write<'a, EventChannel<DamageEvent>>,
+    pub death_events: Write<'a, EventChannel<DeathEvent>>,
 }
 
 impl Attack {
@@ -48,10 +56,9 @@ impl<'a> System<'a> for Attack {
     type SystemData = CombatSystemData<'a>;
 
     fn run(&mut self, mut data: Self::SystemData) {
-        for (ent, pos, name, corporeal, attack_request) in (
+        for (ent, pos, corporeal, attack_request) in (
             &data.entities,
             &data.positions,
-            &data.names,
             &data.corporeals,
             &mut data.attack_requests,
         )
@@ -62,34 +69,22 @@ impl<'a> System<'a> for Attack {
             let attack_pos = Position::new(pos.x + attack_pos.0, pos.y + attack_pos.1);
             let attack_damage = corporeal.base_damage;
 
-            for (target_entity, target_pos, target_name, corporeal, _floor) in (
-                &data.entities,
-                &data.positions,
-                &data.names,
-                &data.corporeals,
-                !&data.floors,
-            )
-                .join()
-            {
-                // don't do anything when entity attacks empty space
-                if *target_pos == attack_pos {
-                    data.message_log.log(format!(
-                        "{} attempts to attack {}!",
-                        name.name, target_name.name
-                    ));
-                    data.defenders.insert(
+            // don't do anything when entity attacks empty space
+            for target_entity in data.spatial_index.at(attack_pos) {
+                if data.corporeals.get(target_entity).is_some()
+                    && data.floors.get(target_entity).is_none()
+                {
+                    data.attack_events.single_write(AttackEvent::new(
+                        ent,
                         target_entity,
-                        Defending {
-                            damage_source: ent,
-                            damage_amount: attack_damage,
-                        },
-                    );
+                        attack_damage,
+                    ));
                 }
             }
 
             let cost = match data.mobiles.get(ent) {
                 Some(quickness) => Self::get_cost(quickness.quickness, 1.0),
-                None => Self::get_cost(CONFIG.base_turn_time, 1.0),
+                None => Self::get_cost(data.config.base_turn_time, 1.0),
             };
 
             if let Err(err) = data.action_results.insert(ent, ActionResult::from(cost)) {
@@ -99,58 +94,82 @@ impl<'a> System<'a> for Attack {
     }
 }
 
-pub struct Defend;
+// takes the hits from `AttackEvent`s
+#[derive(Default)]
+pub struct Defend {
+    attack_reader: Option<ReaderId<AttackEvent>>,
+}
+
+impl Defend {
+    pub fn new() -> Self {
+        Defend::default()
+    }
+}
+
 impl<'a> System<'a> for Defend {
     type SystemData = CombatSystemData<'a>;
 
     fn run(&mut self, mut data: Self::SystemData) {
-        for (ent, pos, name, mut corporeal, defender) in (
-            &data.entities,
-            &data.positions,
-            &data.names,
-            &mut data.corporeals,
-            &mut data.defenders,
-        )
-            .join()
-        {
-            data.world_updater.remove::<Defending>(ent);
-            let dmg = defender.damage_amount;
-            let attacker = defender.damage_source;
-            let mut attacker_name = match data.names.get(attacker) {
-                Some(name) => name.name.clone(),
-                None => String::from("ATTACKER"),
+        let reader = self.attack_reader.as_mut().unwrap();
+        let attacks: Vec<AttackEvent> = data.attack_events.read(reader).cloned().collect();
+
+        for attack in attacks {
+            let ent = attack.target;
+            let corporeal = match data.corporeals.get_mut(ent) {
+                Some(corporeal) => corporeal,
+                None => continue,
             };
+            // already killed by an earlier attack this turn
+            if corporeal.hp <= 0 || data.deaths.get(ent).is_some() {
+                continue;
+            }
 
-            match data.invulnerables.get(ent) {
-                None => {
-                    corporeal.hp -= dmg;
-                    data.message_log.log(format!(
-                        "{} hits {} for {} damage!!",
-                        attacker_name, name.name, dmg
-                    ));
-                    if corporeal.hp <= 0 {
-                        data.message_log
-                            .log(format!("{} is vanquished!!!", name.name));
-                        match data.bodies.get(ent) {
-                            Some(_) => {
-                                corporeal.hp = corporeal.max_hp;
-                                data.deaths.insert(ent, Death {});
-                            }
-                            None => {
-                                data.entities.delete(ent);
-                            }
+            if data.invulnerables.get(ent).is_some() {
+                data.damage_events.single_write(DamageEvent {
+                    entity: ent,
+                    source: attack.attacker,
+                    amount: 0,
+                    deflected: true,
+                });
+                continue;
+            }
+
+            corporeal.hp -= attack.damage;
+            data.damage_events.single_write(DamageEvent {
+                entity: ent,
+                source: attack.attacker,
+                amount: attack.damage,
+                deflected: false,
+            });
+            if corporeal.hp <= 0 {
+                data.death_events.single_write(DeathEvent {
+                    entity: ent,
+                    killer: Some(attack.attacker),
+                });
+                match data.bodies.get(ent) {
+                    Some(_) => {
+                        corporeal.hp = corporeal.max_hp;
+                        if let Err(err) = data.deaths.insert(ent, Death {}) {
+                            error!("Failed to insert death from Defend system: {}", err)
+                        }
+                    }
+                    None => {
+                        if data.players.get(ent).is_some() {
+                            data.game_state.push(State::GameOver);
+                        }
+                        if let Err(err) = data.entities.delete(ent) {
+                            error!("Failed to delete entity from Defend system: {}", err)
                         }
                     }
-                }
-
-                Some(_) => {
-                    data.message_log.log(String::from(
-                        "It's as if the attack is deflected by a divine force!",
-                    ));
                 }
             }
         }
     }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.attack_reader = Some(events::reader(world));
+    }
 }
 
 pub struct DeathSystem;
@@ -158,8 +177,10 @@ impl<'a> System<'a> for DeathSystem {
     type SystemData = CombatSystemData<'a>;
 
     fn run(&mut self, mut data: Self::SystemData) {
-        for (death, ent) in (&data.deaths, &data.entities).join() {
-            data.corpses.insert(ent, Corpse {});
+        for (_death, ent) in (&data.deaths, &data.entities).join() {
+            if let Err(err) = data.corpses.insert(ent, Corpse {}) {
+                error!("Failed to insert corpse from Death system: {}", err)
+            }
             data.world_updater.remove::<Death>(ent);
             if let Some(name) = data.names.get_mut(ent) {
                 name.name = format!("corpse of {}", { &name.name });
@@ -177,12 +198,18 @@ impl<'a> System<'a> for DeathSystem {
                 view.set(pos.x, pos.y, true, true);
             }
 
-            data.elevations.insert(ent, Elevation::OnFloor);
+            if let Err(err) = data.elevations.insert(ent, Elevation::OnFloor) {
+                error!("Failed to insert elevation from Death system: {}", err)
+            }
 
             data.move_requests.remove(ent);
             data.attack_requests.remove(ent);
             data.action_results.remove(ent);
             data.ai_units.remove(ent);
+
+            if data.players.get(ent).is_some() {
+                data.game_state.push(State::GameOver);
+            }
         }
     }
 }
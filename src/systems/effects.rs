@@ -0,0 +1,85 @@
+use crate::components::Position;
+use crate::events::{self, DamageEvent, DeathEvent};
+use shrev::{EventChannel, ReaderId};
+use specs::prelude::*;
+use std::time::{Duration, Instant};
+
+// how long a flash stays on screen
+const FLASH_TIME: Duration = Duration::from_millis(200);
+
+// tints the background where something just happened, drawn by `RenderViewport`
+#[derive(Copy, Clone, Debug)]
+pub struct Flash {
+    pub position: Position,
+    pub color: (u8, u8, u8),
+    pub until: Instant,
+}
+
+#[derive(Default)]
+pub struct Effects {
+    pub flashes: Vec<Flash>,
+}
+
+// flashes whoever gets hit or dies
+#[derive(Default)]
+pub struct EffectsSystem {
+    damage_reader: Option<ReaderId<DamageEvent>>,
+    death_reader: Option<ReaderId<DeathEvent>>,
+}
+
+impl EffectsSystem {
+    pub fn new() -> Self {
+        EffectsSystem::default()
+    }
+}
+
+#[derive(SystemData)]
+pub struct EffectsSystemData<'a> {
+    pub positions: ReadStorage<'a, Position>,
+    pub effects: Write<'a, Effects>,
+
+    // read event channels
+    pub damage_events: Read<'a, EventChannel<DamageEvent>>,
+    pub death_events: Read<'a, EventChannel<DeathEvent>>,
+}
+
+impl<'a> System<'a> for EffectsSystem {
+    type SystemData = EffectsSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let now = Instant::now();
+        let positions = &data.positions;
+        let flashes = &mut data.effects.flashes;
+        flashes.retain(|flash| flash.until > now);
+
+        let mut flash = |entity: Entity, color: (u8, u8, u8)| {
+            if let Some(pos) = positions.get(entity) {
+                flashes.push(Flash {
+                    position: *pos,
+                    color,
+                    until: now + FLASH_TIME,
+                });
+            }
+        };
+
+        for damage in data
+            .damage_events
+            .read(self.damage_reader.as_mut().unwrap())
+        {
+            let color = match damage.deflected {
+                true => (200, 200, 120),
+                false => (160, 20, 20),
+            };
+            flash(damage.entity, color);
+        }
+        for death in data.death_events.read(self.death_reader.as_mut().unwrap()) {
+            flash(death.entity, (90, 0, 0));
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.damage_reader = Some(events::reader(world));
+        self.death_reader = Some(events::reader(world));
+    }
+}
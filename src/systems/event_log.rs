@@ -0,0 +1,102 @@
+use crate::components::Name;
+use crate::ecs::MessageLog;
+use crate::events::{self, AttackEvent, DamageEvent, DeathEvent, PickupEvent};
+use shrev::{EventChannel, ReaderId};
+use specs::prelude::*;
+
+// tells the player what happened, by writing the events to the message log
+#[derive(Default)]
+pub struct EventLog {
+    attack_reader: Option<ReaderId<AttackEvent>>,
+    damage_reader: Option<ReaderId<DamageEvent>>,
+    death_reader: Option<ReaderId<DeathEvent>>,
+    pickup_reader: Option<ReaderId<PickupEvent>>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog::default()
+    }
+
+    fn name(names: &ReadStorage<Name>, entity: Entity) -> String {
+        match names.get(entity) {
+            Some(name) => name.name.clone(),
+            None => String::from("something"),
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct EventLogSystemData<'a> {
+    pub names: ReadStorage<'a, Name>,
+    pub message_log: WriteExpect<'a, MessageLog>,
+
+    // read event channels
+    pub attack_events: Read<'a, EventChannel<AttackEvent>>,
+    pub damage_events: Read<'a, EventChannel<DamageEvent>>,
+    pub death_events: Read<'a, EventChannel<DeathEvent>>,
+    pub pickup_events: Read<'a, EventChannel<PickupEvent>>,
+}
+
+impl<'a> System<'a> for EventLog {
+    type SystemData = EventLogSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let names = &data.names;
+
+        for attack in data
+            .attack_events
+            .read(self.attack_reader.as_mut().unwrap())
+        {
+            data.message_log.log(format!(
+                "{} attempts to attack {}!",
+                Self::name(names, attack.attacker),
+                Self::name(names, attack.target)
+            ));
+        }
+
+        for damage in data
+            .damage_events
+            .read(self.damage_reader.as_mut().unwrap())
+        {
+            if damage.deflected {
+                data.message_log.log(String::from(
+                    "It's as if the attack is deflected by a divine force!",
+                ));
+                continue;
+            }
+            data.message_log.log(format!(
+                "{} hits {} for {} damage!!",
+                Self::name(names, damage.source),
+                Self::name(names, damage.entity),
+                damage.amount
+            ));
+        }
+
+        for death in data.death_events.read(self.death_reader.as_mut().unwrap()) {
+            data.message_log.log(format!(
+                "{} is vanquished!!!",
+                Self::name(names, death.entity)
+            ));
+        }
+
+        for pickup in data
+            .pickup_events
+            .read(self.pickup_reader.as_mut().unwrap())
+        {
+            data.message_log.log(format!(
+                "{} picks up {}.",
+                Self::name(names, pickup.entity),
+                Self::name(names, pickup.item)
+            ));
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.attack_reader = Some(events::reader(world));
+        self.damage_reader = Some(events::reader(world));
+        self.death_reader = Some(events::reader(world));
+        self.pickup_reader = Some(events::reader(world));
+    }
+}
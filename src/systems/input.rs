@@ -1,15 +1,16 @@
 use shrev::EventChannel;
 use specs::prelude::*;
 
+use crate::backend::{Key, Keyboard};
 use crate::command::{Command, CommandEvent};
-use crate::components::{MyTurn, PlayerControl, Position};
+use crate::components::{Carryable, MyTurn, PlayerControl, Position, Stairs};
+use crate::ecs::{MessageLog, State};
 use crate::map::*;
+use crate::replay::{Recorder, Replay};
 use crate::systems::movement::Dir;
-use crate::CONFIG;
-use tcod::console::*;
-use tcod::input::*;
+use crate::config::Config;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Input {
     command_queue: Vec<Command>,
 }
@@ -24,20 +25,20 @@ impl Input {
     }
 
     fn get_command_from_key(key: Key) -> Option<Command> {
-        match key.code {
-            KeyCode::Escape => Some(Command::EndGame),
-
-            KeyCode::Char => match key.printable {
-                // actor commands
-                'h' => Some(Command::Move(Dir::W)),
-                'j' => Some(Command::Move(Dir::S)),
-                'k' => Some(Command::Move(Dir::N)),
-                'l' => Some(Command::Move(Dir::E)),
-                'y' => Some(Command::Move(Dir::NW)),
-                'u' => Some(Command::Move(Dir::NE)),
-                'b' => Some(Command::Move(Dir::SW)),
-                'n' => Some(Command::Move(Dir::SE)),
-                '.' => Some(Command::Move(Dir::Nowhere)),
+        if let Some(dir) = dir_from_key(key) {
+            return Some(Command::Move(dir));
+        }
+
+        match key {
+            Key::Escape => Some(Command::Pause),
+
+            Key::Char(c) => match c {
+                'i' => Some(Command::OpenInventory),
+                '>' => Some(Command::Descend),
+                '<' => Some(Command::Ascend),
+                't' => Some(Command::Target),
+                '`' => Some(Command::OpenConsole),
+                'g' => Some(Command::PickUp),
                 _ => None,
             },
 
@@ -46,19 +47,42 @@ impl Input {
     }
 }
 
+// the vi keys, and '.' to stay put
+pub fn dir_from_key(key: Key) -> Option<Dir> {
+    match key {
+        Key::Char('h') => Some(Dir::W),
+        Key::Char('j') => Some(Dir::S),
+        Key::Char('k') => Some(Dir::N),
+        Key::Char('l') => Some(Dir::E),
+        Key::Char('y') => Some(Dir::NW),
+        Key::Char('u') => Some(Dir::NE),
+        Key::Char('b') => Some(Dir::SW),
+        Key::Char('n') => Some(Dir::SE),
+        Key::Char('.') => Some(Dir::Nowhere),
+        _ => None,
+    }
+}
+
 #[derive(SystemData)]
 pub struct InputSystemData<'a> {
     pub entities: Entities<'a>,
     pub entity_map: ReadExpect<'a, EntityMap>,
     pub view: ReadExpect<'a, View>,
+    pub config: ReadExpect<'a, Config>,
     pub players: ReadStorage<'a, PlayerControl>,
     pub positions: ReadStorage<'a, Position>,
+    pub stairs: ReadStorage<'a, Stairs>,
+    pub carryables: ReadStorage<'a, Carryable>,
+    pub spatial_index: ReadExpect<'a, SpatialIndex>,
+    pub message_log: WriteExpect<'a, MessageLog>,
     pub my_turns: WriteStorage<'a, MyTurn>,
     pub world_updater: Read<'a, LazyUpdate>,
     pub game_state: WriteExpect<'a, crate::GameState>,
     pub key_channel: ReadExpect<'a, EventChannel<Key>>,
     pub key_reader: WriteExpect<'a, ReaderId<Key>>,
     pub command_event_channel: Write<'a, EventChannel<CommandEvent>>,
+    pub replay: Read<'a, Replay>,
+    pub recorder: Write<'a, Recorder>,
 }
 
 impl<'a> System<'a> for Input {
@@ -70,6 +94,10 @@ impl<'a> System<'a> for Input {
         for key in keys {
             if self.command_queue.len() < 3 {
                 if let Some(command) = Self::get_command_from_key(*key) {
+                    // a replay is giving the player's commands, keys can only pause and look
+                    if data.replay.is_playing() && !command.is_meta() {
+                        continue;
+                    }
                     self.command_queue.push(command);
                 }
             }
@@ -78,19 +106,88 @@ impl<'a> System<'a> for Input {
         if self.command_queue.is_empty() {
             return;
         }
-        //        println!("{:?}", self.command_queue);
         let command = self.command_queue.pop();
 
+        // these open another state on top of this one, whoever's turn it is
+        match command {
+            Some(Command::EndGame) => {
+                data.game_state.game_end = true;
+                return;
+            }
+            Some(Command::Pause) => {
+                data.game_state.push(State::Paused);
+                return;
+            }
+            Some(Command::OpenInventory) => {
+                data.game_state.push(State::Inventory);
+                return;
+            }
+            _ => (),
+        }
+
         for (ent, _player, _my_turn) in (&data.entities, &data.players, &mut data.my_turns).join() {
             match command {
                 None => return,
-                // meta commands
-                Some(Command::EndGame) => data.game_state.game_end = true,
+                Some(Command::Target) => data.game_state.push(State::Targeting),
+                Some(Command::OpenConsole) => data.game_state.push(State::Console),
+
+                Some(Command::Descend) | Some(Command::Ascend) => {
+                    let wanted = match command {
+                        Some(Command::Descend) => Stairs::Down,
+                        _ => Stairs::Up,
+                    };
+                    let pos = match data.positions.get(ent) {
+                        Some(pos) => *pos,
+                        None => continue,
+                    };
+                    let stairs = &data.stairs;
+                    let on_stairs = data
+                        .spatial_index
+                        .at(pos)
+                        .any(|here| stairs.get(here) == Some(&wanted));
+                    if !on_stairs {
+                        let way = if wanted == Stairs::Down { "down" } else { "up" };
+                        data.message_log
+                            .log(format!("There are no stairs {} here.", way));
+                        continue;
+                    }
+
+                    let command = command.clone().unwrap();
+                    data.recorder
+                        .record(data.game_state.world_time.tick, &command);
+                    data.command_event_channel
+                        .single_write(CommandEvent::new(command, ent));
+                    data.game_state.world_time.increment_player_turn();
+                }
+
+                Some(Command::PickUp) => {
+                    let pos = match data.positions.get(ent) {
+                        Some(pos) => *pos,
+                        None => continue,
+                    };
+                    let carryables = &data.carryables;
+                    let anything = data
+                        .spatial_index
+                        .at(pos)
+                        .any(|here| carryables.get(here).is_some());
+                    if !anything {
+                        data.message_log
+                            .log(String::from("There's nothing here to pick up."));
+                        continue;
+                    }
+
+                    let command = command.clone().unwrap();
+                    data.recorder
+                        .record(data.game_state.world_time.tick, &command);
+                    data.command_event_channel
+                        .single_write(CommandEvent::new(command, ent));
+                    data.game_state.world_time.increment_player_turn();
+                }
 
                 // player commands
                 Some(Command::Move(dir)) => {
                     // attach action component to player entity
-                    let mut command_event = CommandEvent::new(command.unwrap(), ent);
+                    let mut command_event = CommandEvent::new(command.clone().unwrap(), ent);
 
                     if let Some(pos) = data.positions.get(ent) {
                         let fov_map = data.view.map.lock().unwrap();
@@ -108,19 +205,15 @@ impl<'a> System<'a> for Input {
                             },
                         );
 
-                        //                        println!("{}, {}", dest.0, dest.1);
-
-                        if (dest.0 as usize) >= CONFIG.map_width as usize
-                            || (dest.1 as usize) >= CONFIG.map_height as usize
-                            || (dest.0 as usize) < 0
-                            || (dest.1 as usize) < 0
+                        if (dest.0 as usize) >= data.config.map_width as usize
+                            || (dest.1 as usize) >= data.config.map_height as usize
                         {
                             continue;
                         }
 
                         if !fov_map.is_walkable(dest.0, dest.1) && dir != Dir::Nowhere {
                             // attack enemy instead if closeby
-                            if let Ok(_) = data.entity_map.actors.retrieve(dest.0, dest.1) {
+                            if data.entity_map.actors.retrieve(dest.0, dest.1).is_ok() {
                                 command_event = CommandEvent::new(Command::Attack(dir), ent);
 
                             // make sure bumping into walls doesnt take a turn
@@ -128,14 +221,14 @@ impl<'a> System<'a> for Input {
                                 continue;
                             }
                         }
+                        data.recorder
+                            .record(data.game_state.world_time.tick, &command_event.command);
                         data.command_event_channel.single_write(command_event);
-                        // println!("{:?}", command_event);
                         data.game_state.world_time.increment_player_turn();
                     }
                 }
                 _ => (),
             }
-            //            println!("{:?}", command);
         }
     }
 }
@@ -146,18 +239,15 @@ pub struct InputListener;
 pub struct InputListenerSystemData<'a> {
     pub key_channel: WriteExpect<'a, EventChannel<Key>>,
     pub key_reader: WriteExpect<'a, ReaderId<Key>>,
-    pub console: ReadExpect<'a, Root>,
+    pub keyboard: WriteExpect<'a, Keyboard>,
 }
 
 impl<'a> System<'a> for InputListener {
     type SystemData = InputListenerSystemData<'a>;
 
     fn run(&mut self, mut data: Self::SystemData) {
-        let key = data.console.check_for_keypress(KeyPressFlags::all());
-        if let Some(key_pressed) = key {
-            if key_pressed.pressed {
-                data.key_channel.single_write(key_pressed);
-            }
+        if let Some(key) = data.keyboard.poll_key() {
+            data.key_channel.single_write(key);
         }
     }
 
@@ -0,0 +1,322 @@
+use crate::backend::{Color, Screen};
+use crate::components::flags::requests::{AttackRequest, MoveRequest, PickupRequest};
+use crate::components::flags::ActionResult;
+use crate::components::*;
+use crate::config::Config;
+use crate::ecs::{GameState, State};
+use crate::systems::menu::put_str;
+use crate::time::TurnQueue;
+use specs::prelude::*;
+use std::fmt::Debug;
+
+// the inspector, a panel down the right of the viewport with every component of every entity
+// tagged `PrintDebug` and what's in it. 'x' while targeting tags or untags what's under the
+// cursor, and blueprints can start things off tagged with `print_debug: Some(())`. it's drawn
+// again every frame, so it keeps up with the turns as they pass
+
+const PANEL_WIDTH: i32 = 34;
+const PANEL_FG: Color = (200, 200, 200);
+const PANEL_BG: Color = (20, 20, 35);
+const HEADING_FG: Color = (255, 220, 120);
+
+#[derive(SystemData)]
+pub struct RenderInspectorSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub print_debugs: ReadStorage<'a, PrintDebug>,
+    pub names: ReadStorage<'a, Name>,
+    pub from_blueprints: ReadStorage<'a, FromBlueprint>,
+    pub positions: ReadStorage<'a, Position>,
+    pub elevations: ReadStorage<'a, Elevation>,
+    pub actors: ReadStorage<'a, Actor>,
+    pub my_turns: ReadStorage<'a, MyTurn>,
+    pub action_results: ReadStorage<'a, ActionResult>,
+    pub mobiles: ReadStorage<'a, Mobile>,
+    pub corporeals: ReadStorage<'a, Corporeal>,
+    pub ai_controls: ReadStorage<'a, AiControl>,
+    pub targets: ReadStorage<'a, Target>,
+    pub can_see_targets: ReadStorage<'a, CanSeeTarget>,
+    pub command_sequences: ReadStorage<'a, CommandSequence>,
+    pub move_requests: ReadStorage<'a, MoveRequest>,
+    pub attack_requests: ReadStorage<'a, AttackRequest>,
+    pub pickup_requests: ReadStorage<'a, PickupRequest>,
+    pub seeings: ReadStorage<'a, Seeing>,
+    pub inventories: ReadStorage<'a, Inventory>,
+    pub bodies: ReadStorage<'a, Body>,
+    pub materials: ReadStorage<'a, Material>,
+    pub stairs: ReadStorage<'a, Stairs>,
+    pub renderables: ReadStorage<'a, Renderable>,
+    pub random_renderables: ReadStorage<'a, RandomRenderable>,
+    pub player_controls: ReadStorage<'a, PlayerControl>,
+    pub cameras: ReadStorage<'a, Camera>,
+    pub invulnerables: ReadStorage<'a, Invulnerable>,
+    pub block_movements: ReadStorage<'a, BlockMovement>,
+    pub block_sights: ReadStorage<'a, BlockSight>,
+    pub carryables: ReadStorage<'a, Carryable>,
+    pub floors: ReadStorage<'a, Floor>,
+    pub on_floors: ReadStorage<'a, OnFloor>,
+    pub in_views: ReadStorage<'a, InView>,
+    pub deaths: ReadStorage<'a, Death>,
+    pub corpses: ReadStorage<'a, Corpse>,
+    pub turn_queue: ReadExpect<'a, TurnQueue>,
+    pub game_state: ReadExpect<'a, GameState>,
+    pub screen: WriteExpect<'a, Screen>,
+    pub config: ReadExpect<'a, Config>,
+}
+
+pub struct RenderInspector;
+
+impl RenderInspector {
+    // `Zombie #12`, or just the number for things without a name
+    fn label(data: &RenderInspectorSystemData, ent: Entity) -> String {
+        match data.names.get(ent) {
+            Some(name) => format!("{} #{}", name.name, ent.id()),
+            None => format!("#{}", ent.id()),
+        }
+    }
+
+    // a line for every component `ent` has, with the ones that matter most for the turn order
+    // and the ai first
+    fn describe(data: &RenderInspectorSystemData, ent: Entity) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = |key: &str, value: String| lines.push(format!("{}: {}", key, value));
+
+        if let Some(actor) = data.actors.get(ent) {
+            line("next_turn", actor.next_turn.to_string());
+            let stats = actor.stats;
+            line(
+                "stats",
+                format!(
+                    "str {}, agi {}, int {}",
+                    stats.strength, stats.agility, stats.intelligence
+                ),
+            );
+        }
+        // where it is in the queue, which can be behind `next_turn` until it's put back
+        let queued = data
+            .turn_queue
+            .iter()
+            .filter(|turn| turn.entity == ent)
+            .map(|turn| turn.tick.to_string())
+            .collect::<Vec<_>>();
+        if !queued.is_empty() {
+            line("queued", queued.join(", "));
+        }
+        if data.my_turns.get(ent).is_some() {
+            line("my_turn", String::from("yes"));
+        }
+        if let Some(result) = data.action_results.get(ent) {
+            line("action_result", result.cost.to_string());
+        }
+        if let Some(mobile) = data.mobiles.get(ent) {
+            line("quickness", mobile.quickness.to_string());
+        }
+        if let Some(corporeal) = data.corporeals.get(ent) {
+            line(
+                "hp",
+                format!(
+                    "{}/{}, hits for {}",
+                    corporeal.hp, corporeal.max_hp, corporeal.base_damage
+                ),
+            );
+        }
+        if let Some(ai) = data.ai_controls.get(ent) {
+            line("ai", debug(&ai.ai_type));
+        }
+        if let Some(target) = data.targets.get(ent) {
+            line(
+                "target",
+                format!(
+                    "{} last seen at {},{}, gives up in {}",
+                    Self::label(data, target.entity),
+                    target.position.x,
+                    target.position.y,
+                    target.give_up_timer
+                ),
+            );
+        }
+        if data.can_see_targets.get(ent).is_some() {
+            line("can_see_target", String::from("yes"));
+        }
+        if let Some(sequence) = data.command_sequences.get(ent) {
+            line("commands", debug(&sequence.commands));
+        }
+        if let Some(request) = data.move_requests.get(ent) {
+            line("move_request", format!("{},{}", request.dx, request.dy));
+        }
+        if let Some(request) = data.attack_requests.get(ent) {
+            line("attack_request", debug(&request.dir));
+        }
+        if data.pickup_requests.get(ent).is_some() {
+            line("pickup_request", String::from("yes"));
+        }
+
+        if let Some(pos) = data.positions.get(ent) {
+            line("position", format!("{},{}", pos.x, pos.y));
+        }
+        if let Some(elevation) = data.elevations.get(ent) {
+            line("elevation", debug(elevation));
+        }
+        if let Some(seeing) = data.seeings.get(ent) {
+            line(
+                "seeing",
+                format!("fov {}, {} tiles seen", seeing.fov, seeing.seen.len()),
+            );
+        }
+        if let Some(inventory) = data.inventories.get(ent) {
+            let items: Vec<String> = inventory
+                .items
+                .iter()
+                .map(|item| Self::label(data, *item))
+                .collect();
+            line("inventory", format!("[{}]", items.join(", ")));
+        }
+        if let Some(body) = data.bodies.get(ent) {
+            let mut parts = Vec::new();
+            body.core().walk(&mut |part| parts.push(part.name.clone()));
+            line("body", parts.join(", "));
+        }
+        if let Some(material) = data.materials.get(ent) {
+            line("material", debug(material));
+        }
+        if let Some(stairs) = data.stairs.get(ent) {
+            line("stairs", debug(stairs));
+        }
+        if let Some(renderable) = data.renderables.get(ent) {
+            line("glyph", renderable.glyph.to_string());
+        }
+        if let Some(random) = data.random_renderables.get(ent) {
+            line("glyphs", random.glyphs.clone());
+        }
+        if let Some(from) = data.from_blueprints.get(ent) {
+            line("blueprint", from.blueprint.clone());
+        }
+
+        // the markers, all on one line
+        let flags: Vec<&str> = [
+            ("player", data.player_controls.get(ent).is_some()),
+            ("camera", data.cameras.get(ent).is_some()),
+            ("invulnerable", data.invulnerables.get(ent).is_some()),
+            ("blocks_movement", data.block_movements.get(ent).is_some()),
+            ("blocks_sight", data.block_sights.get(ent).is_some()),
+            ("carryable", data.carryables.get(ent).is_some()),
+            ("floor", data.floors.get(ent).is_some()),
+            ("on_floor", data.on_floors.get(ent).is_some()),
+            ("in_view", data.in_views.get(ent).is_some()),
+            ("death", data.deaths.get(ent).is_some()),
+            ("corpse", data.corpses.get(ent).is_some()),
+        ]
+        .iter()
+        .filter(|(_, has)| *has)
+        .map(|(key, _)| *key)
+        .collect();
+        if !flags.is_empty() {
+            line("flags", flags.join(", "));
+        }
+        lines
+    }
+}
+
+impl<'a> System<'a> for RenderInspector {
+    type SystemData = RenderInspectorSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        // nothing is drawn while the turns are processed
+        if data.game_state.current() == State::TurnProcess {
+            return;
+        }
+        let inspected: Vec<Entity> = (&data.entities, &data.print_debugs)
+            .join()
+            .map(|(ent, _)| ent)
+            .collect();
+        if inspected.is_empty() {
+            return;
+        }
+
+        // narrower in a small viewport, with a column of space either side of the text
+        let width = PANEL_WIDTH.min(data.config.viewport_width);
+        let mut rows = vec![(
+            format!("tick {}", data.game_state.world_time.tick),
+            HEADING_FG,
+        )];
+        for ent in inspected {
+            rows.push((String::new(), PANEL_FG));
+            rows.push((Self::label(&data, ent), HEADING_FG));
+            for line in Self::describe(&data, ent) {
+                for row in wrap(&line, (width - 2).max(0) as usize) {
+                    rows.push((row, PANEL_FG));
+                }
+            }
+        }
+
+        let config = &data.config;
+        let x = config.viewport_x + config.viewport_width - width;
+        let screen = &mut **data.screen;
+        // whatever doesn't fit is left off the bottom
+        for (dy, (row, fg)) in rows
+            .iter()
+            .take(config.viewport_height as usize)
+            .enumerate()
+        {
+            let y = config.viewport_y + dy as i32;
+            for dx in 0..width {
+                screen.put_char(x + dx, y, ' ', PANEL_FG, PANEL_BG);
+            }
+            put_str(screen, x + 1, y, width - 1, row, *fg, PANEL_BG);
+        }
+    }
+}
+
+fn debug<T: Debug>(value: &T) -> String {
+    format!("{:?}", value)
+}
+
+// splits a line that's too long into rows of `width`, at a space if there's one, with the rows
+// after the first indented. at least a character goes on every row, however narrow
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut rows = Vec::new();
+    let mut rest: Vec<char> = line.chars().collect();
+    let mut room = width.max(1);
+    while rest.len() > room {
+        let split = match rest[..=room].iter().rposition(|c| *c == ' ') {
+            Some(space) if space > 0 => space,
+            _ => room,
+        };
+        let row: String = rest[..split].iter().collect();
+        rows.push(row);
+        rest = rest[split..]
+            .iter()
+            .skip_while(|c| **c == ' ')
+            .cloned()
+            .collect();
+        room = width.saturating_sub(2).max(1);
+    }
+    rows.push(rest.into_iter().collect());
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| match i {
+            0 => row.clone(),
+            _ => format!("  {}", row),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::wrap;
+
+    #[test]
+    fn wraps_at_spaces_and_indents() {
+        assert_eq!(wrap("hp: 10/10", 20), vec!["hp: 10/10"]);
+        assert_eq!(
+            wrap("flags: player, camera, carryable", 16),
+            vec!["flags: player,", "  camera,", "  carryable"]
+        );
+        assert_eq!(wrap("abcdefgh", 4), vec!["abcd", "  ef", "  gh"]);
+    }
+
+    #[test]
+    fn wraps_in_tiny_panels() {
+        assert_eq!(wrap("abc", 1), vec!["a", "  b", "  c"]);
+        assert_eq!(wrap("ab", 0), vec!["a", "  b"]);
+    }
+}
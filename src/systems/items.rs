@@ -0,0 +1,66 @@
+use crate::components::flags::requests::PickupRequest;
+use crate::components::flags::ActionResult;
+use crate::components::*;
+use crate::config::Config;
+use crate::events::PickupEvent;
+use crate::map::SpatialIndex;
+use shrev::EventChannel;
+use specs::prelude::*;
+
+pub struct Pickup;
+
+#[derive(SystemData)]
+pub struct PickupSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub positions: WriteStorage<'a, Position>,
+    pub carryables: ReadStorage<'a, Carryable>,
+    pub inventories: WriteStorage<'a, Inventory>,
+    pub spatial_index: ReadExpect<'a, SpatialIndex>,
+    pub config: ReadExpect<'a, Config>,
+    pub action_results: WriteStorage<'a, ActionResult>,
+    pub world_updater: Read<'a, LazyUpdate>,
+
+    // requests
+    pub pickup_requests: ReadStorage<'a, PickupRequest>,
+
+    // events
+    pub pickup_events: Write<'a, EventChannel<PickupEvent>>,
+}
+
+impl<'a> System<'a> for Pickup {
+    type SystemData = PickupSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let requests: Vec<Entity> = (&data.entities, &data.pickup_requests)
+            .join()
+            .map(|(ent, _request)| ent)
+            .collect();
+
+        for ent in requests {
+            data.world_updater.remove::<PickupRequest>(ent);
+
+            let pos = match data.positions.get(ent) {
+                Some(pos) => *pos,
+                None => continue,
+            };
+            let item = data
+                .spatial_index
+                .at(pos)
+                .find(|here| data.carryables.get(*here).is_some());
+
+            // unless it has gone since, or there's nowhere to put it
+            if let (Some(item), Some(inventory)) = (item, data.inventories.get_mut(ent)) {
+                // carried things aren't anywhere on the map
+                data.positions.remove(item);
+                inventory.items.push(item);
+                data.pickup_events
+                    .single_write(PickupEvent { entity: ent, item });
+            }
+
+            let cost = ActionResult::from(data.config.base_turn_time);
+            if let Err(err) = data.action_results.insert(ent, cost) {
+                error!("Failed to insert action result from Pickup system: {}", err)
+            }
+        }
+    }
+}
@@ -1,87 +1,179 @@
 use crate::components::*;
-use crate::entity_factory::EntityLoadQueue;
+use crate::entity_factory::{BlueprintIndex, EntityLoadQueue};
 use crate::mapgen::bsp::*;
 use crate::GameState;
 use crate::mapgen::level::*;
-use crate::mapgen::*;
-use crate::CONFIG;
+use crate::dungeon::Dungeon;
+use crate::rng::{GameRng, Stream};
+use crate::terrain::{Terrain, TerrainKinds};
+use crate::config::Config;
 use rand;
-use rand::distributions::{Alphanumeric, Standard};
+use rand::distributions::Alphanumeric;
 use rand::prelude::*;
-use sha2::{Digest, Sha256};
 use specs::prelude::*;
 use crate::ecs::State;
 
-pub struct MapGen {
-    initialized: bool,
+// how a level is filled in, by its depth
+pub struct LevelSettings {
+    // of a monster on any tile
+    pub monster_chance: f64,
+    // of a pack on any open floor tile
+    pub pack_chance: f64,
+    // how much of the map's width and height the level is laid out over, the rest is left empty
+    pub map_scale: f64,
 }
 
+impl LevelSettings {
+    // the same as it always was on the first level, smaller and more crowded further down
+    pub fn for_depth(depth: u32) -> Self {
+        let below = (depth.max(1) - 1) as f64;
+        LevelSettings {
+            monster_chance: f64::min(0.05 + 0.01 * below, 0.15),
+            pack_chance: f64::min(0.01 + 0.005 * below, 0.04),
+            map_scale: f64::max(1.0 - 0.05 * below, 0.6),
+        }
+    }
+
+    // the size of the level on a map of `width` by `height`
+    pub fn map_size(&self, width: i32, height: i32) -> (i32, i32) {
+        let scale = |size: i32| ((size as f64 * self.map_scale) as i32).max(1).min(size);
+        (scale(width), scale(height))
+    }
+}
+
+#[derive(Default)]
+pub struct MapGen;
+
 impl MapGen {
     pub fn new() -> Self {
-        MapGen { initialized: false }
+        MapGen
     }
 }
 
 #[derive(SystemData)]
 pub struct MapGenSystemData<'a> {
-    entities: Entities<'a>,
-    world_updater: Read<'a, LazyUpdate>,
     entity_load_queue: WriteExpect<'a, EntityLoadQueue>,
+    blueprint_index: ReadExpect<'a, BlueprintIndex>,
     game_state: WriteExpect<'a, GameState>,
+    rng: WriteExpect<'a, GameRng>,
+    dungeon: ReadExpect<'a, Dungeon>,
+    players: ReadStorage<'a, PlayerControl>,
+    positions: WriteStorage<'a, Position>,
+    terrain: WriteExpect<'a, Terrain>,
+    terrain_kinds: ReadExpect<'a, TerrainKinds>,
+    config: ReadExpect<'a, Config>,
 }
 
 impl<'a> System<'a> for MapGen {
     type SystemData = MapGenSystemData<'a>;
 
     fn run(&mut self, mut data: Self::SystemData) {
-        let seed: String = rand::thread_rng()
+        // newer versions of rand sample bytes rather than chars
+        #[allow(clippy::useless_conversion)]
+        let seed: String = data
+            .rng
+            .stream(Stream::MapLayout)
             .sample_iter(&Alphanumeric)
             .take(30)
+            .map(char::from)
             .collect();
 
-        println! {"Seed: {}", seed};
-
-        let mut bsp_level = BspLevel::create(CONFIG.map_width, CONFIG.map_height, &seed);
-//        println!("{}", bsp_level);
+        let depth = data.dungeon.depth;
+        let settings = LevelSettings::for_depth(depth);
+        let (width, height) = settings.map_size(data.config.map_width, data.config.map_height);
+        let bsp_level = BspLevel::create(width, height, &seed);
         let mut player_placed = false;
-        let mut dummy_placed = false;
-        let mut rng = thread_rng();
+        let rng = data.rng.stream(Stream::Spawning);
+        // placed once everything is picked, how it looks is rolled from another stream
+        let mut terrain = Vec::new();
+
+        let index = &data.blueprint_index;
+
+        // the way down is on the last floor tile, as far from the way in as it gets
+        let stairs_down = bsp_level
+            .tile_map
+            .items
+            .iter()
+            .rposition(|tile| matches!(tile, TileType::Floor))
+            .map(|i| bsp_level.tile_map.idx_xy(i));
 
         for (i, tile) in bsp_level.tile_map.items.iter().enumerate() {
             let (x, y) = bsp_level.tile_map.idx_xy(i);
-            data.entity_load_queue
-                .push(("terrain.base_floor".to_string(), Some(Position::new(x, y))));
+            let mut spawns = vec![index.query().tagged("floor")];
 
-            match *tile {
-                TileType::Wall => {
-                    data.entity_load_queue
-                        .push(("terrain.base_wall".to_string(), Some(Position::new(x, y))));
-                }
-                _ => (),
+            if let TileType::Wall = *tile {
+                spawns.push(index.query().tagged("wall"));
+            }
+
+            if is_open(&bsp_level, x, y) && rng.gen_bool(settings.pack_chance) {
+                spawns.push(index.query().tagged("pack"));
+            }
+
+            if rng.gen_bool(settings.monster_chance) {
+                spawns.push(index.query().tagged("monster"));
             }
 
-            if rng.gen_bool(0.05) {
-                data.entity_load_queue
-                    .push(
-                        (
-                            "creatures.base_monster".to_string(),
-                            Some(Position::new(x, y))
-                        )
-                    );
+            if stairs_down == Some((x, y)) {
+                spawns.push(index.query().tagged("stairs_down"));
+            }
+
+            for query in spawns {
+                if let Some(name) = query.at_depth(depth).pick(rng) {
+                    if data.terrain_kinds.get(&name).is_some() {
+                        terrain.push((name, x, y));
+                    } else {
+                        data.entity_load_queue
+                            .push((name, Some(Position::new(x, y))));
+                    }
+                }
             }
 
             if !player_placed {
-                match *tile {
-                    TileType::Floor => {
+                if let TileType::Floor = *tile {
+                    let pos = Position::new(x, y);
+                    // the player came down the stairs, and arrives on the way back up. or the
+                    // level was regenerated, and the first one has no way up
+                    let mut arrived = false;
+                    for (_player, player_pos) in (&data.players, &mut data.positions).join() {
+                        *player_pos = pos;
+                        arrived = true;
+                    }
+                    if arrived {
+                        if depth > 1 {
+                            if let Some(name) = index.query().tagged("stairs_up").pick(rng) {
+                                data.entity_load_queue.push((name, Some(pos)));
+                            }
+                        }
+                    } else {
                         data.entity_load_queue
-                            .push(("player".to_string(), Some(Position::new(x, y))));
-                        player_placed = true;
+                            .push(("player".to_string(), Some(pos)));
                     }
-                    _ => (),
+                    player_placed = true;
                 }
             }
         }
 
+        data.terrain.clear();
+        let rng = data.rng.stream(Stream::Cosmetic);
+        for (name, x, y) in terrain {
+            if let Some(kind) = data.terrain_kinds.get(&name) {
+                data.terrain.place(x, y, kind, rng);
+            }
+        }
+
         data.game_state.transition(State::TurnProcess);
     }
 }
+
+// the tile and the 8 around it are floor, enough room for a small prefab
+fn is_open(bsp_level: &BspLevel, x: i32, y: i32) -> bool {
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            match bsp_level.tile_map.retrieve(x + dx, y + dy) {
+                Ok(TileType::Floor) => (),
+                _ => return false,
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,242 @@
+use crate::backend::{Color, Key, Renderer, Screen};
+use crate::components::{Inventory, Name, PlayerControl};
+use crate::config::Config;
+use crate::ecs::{GameState, StateHooks};
+use crate::systems::statistics::Statistics;
+use shrev::EventChannel;
+use specs::prelude::*;
+
+const MENU_FG: Color = (200, 200, 200);
+const MENU_BG: Color = (20, 20, 35);
+const SELECTED_FG: Color = (0, 0, 0);
+const SELECTED_BG: Color = (200, 200, 200);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MenuAction {
+    // pops the menu's state
+    Close,
+    Quit,
+}
+
+// what the menu state on top shows. it's filled in by the state's enter hook
+#[derive(Default, Debug)]
+pub struct Menu {
+    pub title: String,
+    pub text: Vec<String>,
+    pub items: Vec<(String, MenuAction)>,
+    pub selected: usize,
+    // what escape does, if anything
+    pub cancel: Option<MenuAction>,
+}
+
+impl Menu {
+    pub fn main(world: &World) -> Self {
+        let started = world.read_resource::<GameState>().world_time.tick > 0;
+        Menu {
+            title: String::from("rust-rl"),
+            items: vec![
+                (
+                    String::from(if started { "Continue" } else { "New game" }),
+                    MenuAction::Close,
+                ),
+                (String::from("Quit"), MenuAction::Quit),
+            ],
+            cancel: Some(MenuAction::Quit),
+            ..Menu::default()
+        }
+    }
+
+    pub fn paused(_world: &World) -> Self {
+        Menu {
+            title: String::from("Paused"),
+            items: vec![
+                (String::from("Resume"), MenuAction::Close),
+                (String::from("Save and quit"), MenuAction::Quit),
+            ],
+            cancel: Some(MenuAction::Close),
+            ..Menu::default()
+        }
+    }
+
+    // the player's items, by name
+    pub fn inventory(world: &World) -> Self {
+        let players = world.read_storage::<PlayerControl>();
+        let inventories = world.read_storage::<Inventory>();
+        let names = world.read_storage::<Name>();
+        let mut text: Vec<String> = (&players, &inventories)
+            .join()
+            .flat_map(|(_, inventory)| inventory.items.iter())
+            .map(|item| match names.get(*item) {
+                Some(name) => name.name.clone(),
+                None => String::from("something"),
+            })
+            .collect();
+        if text.is_empty() {
+            text.push(String::from("(empty)"));
+        }
+
+        Menu {
+            title: String::from("Inventory"),
+            text,
+            items: vec![(String::from("Close"), MenuAction::Close)],
+            cancel: Some(MenuAction::Close),
+            ..Menu::default()
+        }
+    }
+
+    pub fn game_over(world: &World) -> Self {
+        let game_state = world.read_resource::<GameState>();
+        let statistics = world.read_resource::<Statistics>();
+        Menu {
+            title: String::from("You died"),
+            text: vec![
+                format!("You lasted {} turns.", game_state.world_time.player_turns),
+                format!(
+                    "You killed {} and dealt {} damage.",
+                    statistics.kills, statistics.damage_dealt
+                ),
+            ],
+            items: vec![(String::from("Quit"), MenuAction::Quit)],
+            cancel: None,
+            ..Menu::default()
+        }
+    }
+}
+
+// enter hook for the menu states, puts up the menu built by the function
+pub struct OpenMenu(pub fn(&World) -> Menu);
+
+impl StateHooks for OpenMenu {
+    fn enter(&mut self, world: &mut World) {
+        let menu = (self.0)(world);
+        world.insert(menu);
+    }
+
+    fn exit(&mut self, world: &mut World) {
+        world.insert(Menu::default());
+    }
+}
+
+#[derive(SystemData)]
+pub struct MenuInputSystemData<'a> {
+    pub menu: Write<'a, Menu>,
+    pub game_state: WriteExpect<'a, GameState>,
+    pub key_channel: ReadExpect<'a, EventChannel<Key>>,
+    pub key_reader: WriteExpect<'a, ReaderId<Key>>,
+}
+
+// the input of the menu states
+pub struct MenuInput;
+
+impl<'a> System<'a> for MenuInput {
+    type SystemData = MenuInputSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let keys: Vec<Key> = data
+            .key_channel
+            .read(&mut data.key_reader)
+            .cloned()
+            .collect();
+        for key in keys {
+            let count = data.menu.items.len();
+            let action = match key {
+                Key::Up | Key::Char('k') if count > 0 => {
+                    data.menu.selected = (data.menu.selected + count - 1) % count;
+                    None
+                }
+                Key::Down | Key::Char('j') if count > 0 => {
+                    data.menu.selected = (data.menu.selected + 1) % count;
+                    None
+                }
+                Key::Enter => data
+                    .menu
+                    .items
+                    .get(data.menu.selected)
+                    .map(|(_, action)| *action),
+                Key::Escape => data.menu.cancel,
+                _ => None,
+            };
+
+            match action {
+                Some(MenuAction::Close) => {
+                    data.game_state.pop();
+                    return;
+                }
+                Some(MenuAction::Quit) => {
+                    data.game_state.game_end = true;
+                    return;
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct RenderMenuSystemData<'a> {
+    pub menu: Read<'a, Menu>,
+    pub screen: WriteExpect<'a, Screen>,
+    pub config: ReadExpect<'a, Config>,
+}
+
+// draws the menu in a box in the middle of the viewport
+pub struct RenderMenu;
+
+impl<'a> System<'a> for RenderMenu {
+    type SystemData = RenderMenuSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let menu = &data.menu;
+        let mut lines: Vec<(String, bool)> = vec![(menu.title.clone(), false)];
+        lines.push((String::new(), false));
+        for line in &menu.text {
+            lines.push((line.clone(), false));
+        }
+        if !menu.text.is_empty() {
+            lines.push((String::new(), false));
+        }
+        for (i, (label, _)) in menu.items.iter().enumerate() {
+            lines.push((label.clone(), i == menu.selected));
+        }
+
+        let longest = lines
+            .iter()
+            .map(|(line, _)| line.chars().count() as i32)
+            .max()
+            .unwrap_or(0);
+        let config = &data.config;
+        let width = i32::min(longest + 4, config.viewport_width);
+        let height = i32::min(lines.len() as i32 + 2, config.viewport_height);
+        let x = config.viewport_x + (config.viewport_width - width) / 2;
+        let y = config.viewport_y + (config.viewport_height - height) / 2;
+
+        let screen = &mut **data.screen;
+        for dy in 0..height {
+            for dx in 0..width {
+                screen.put_char(x + dx, y + dy, ' ', MENU_FG, MENU_BG);
+            }
+        }
+        for (i, (line, selected)) in lines.iter().enumerate().take((height - 2) as usize) {
+            let (fg, bg) = match selected {
+                true => (SELECTED_FG, SELECTED_BG),
+                false => (MENU_FG, MENU_BG),
+            };
+            put_str(screen, x + 2, y + 1 + i as i32, width - 4, line, fg, bg);
+        }
+    }
+}
+
+// writes `text` on one line, cut off at `width` characters
+pub fn put_str(
+    screen: &mut dyn Renderer,
+    x: i32,
+    y: i32,
+    width: i32,
+    text: &str,
+    fg_color: Color,
+    bg_color: Color,
+) {
+    for (i, glyph) in text.chars().take(width.max(0) as usize).enumerate() {
+        screen.put_char(x + i as i32, y, glyph, fg_color, bg_color);
+    }
+}
@@ -1,18 +1,21 @@
 use crate::components::flags::requests::*;
 use crate::components::flags::ActionResult;
 use crate::components::*;
+use crate::config::Config;
+use crate::events::{CollisionEvent, MoveEvent};
 use crate::map::View;
 use crate::map::*;
-use crate::CONFIG;
+use crate::terrain::Terrain;
+use serde::{Deserialize, Serialize};
+use shrev::EventChannel;
 use specs::prelude::*;
+use specs::world::Index;
+use std::collections::{HashMap, HashSet};
 use tcod::map::Map as TcodMap;
-use vecmap::*;
-
-// use crate::systems::control::{CommandEvent};
 
 // remember that commands are *requesting* an action, and events
 // are the result of an action having happened.
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Dir {
     N,
     S,
@@ -55,80 +58,54 @@ impl Dir {
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub struct MoveEvent {
-    pub entity: Entity,
-    pub start_x: i32,
-    pub start_y: i32,
-    pub dest_x: i32,
-    pub dest_y: i32,
-}
-
-impl MoveEvent {
-    pub fn new(entity: Entity, start_x: i32, start_y: i32, dest_x: i32, dest_y: i32) -> Self {
-        MoveEvent {
-            entity,
-            start_x,
-            start_y,
-            dest_x,
-            dest_y,
-        }
-    }
-}
-
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub struct CollisionEvent {
-    collider: Entity,
-    collidee: Entity,
-}
-
-impl CollisionEvent {
-    pub fn _new(collider: Entity, collidee: Entity) -> Self {
-        CollisionEvent { collider, collidee }
-    }
-}
-
 pub struct Movement;
 
 impl Movement {
+    // moves `position` if nothing is in the way, or says what it bumped into
     fn try_move_position(
         entity: Entity,
         position: &mut Position,
         move_command: &MoveRequest,
         view: &TcodMap,
         actor_map: &ActorMap,
-    ) -> MoveEvent {
+        config: &Config,
+    ) -> Result<MoveEvent, CollisionEvent> {
         let start_x = position.x;
         let start_y = position.y;
         let mut dest_x = position.x + move_command.dx;
         let mut dest_y = position.y + move_command.dy;
 
         // if out of bounds, dont go anywhere
-        if dest_x >= CONFIG.map_width || dest_x < 0 || dest_y >= CONFIG.map_height || dest_y < 0 {
-            return MoveEvent::new(entity, start_x, start_y, start_x, start_y);
+        if dest_x >= config.map_width || dest_x < 0 || dest_y >= config.map_height || dest_y < 0 {
+            return Err(CollisionEvent::new(entity, None));
         }
 
-        if !view.is_walkable(dest_x, dest_y) || actor_map.retrieve(dest_x, dest_y).unwrap() != None
-        {
-            dest_x = start_x;
-            dest_y = start_y;
+        if (dest_x, dest_y) != (start_x, start_y) {
+            if let Some(actor) = actor_map.retrieve(dest_x, dest_y).unwrap() {
+                return Err(CollisionEvent::new(entity, Some(actor)));
+            }
+            if !view.is_walkable(dest_x, dest_y) {
+                return Err(CollisionEvent::new(entity, None));
+            }
         }
 
-        if dest_x >= CONFIG.map_width {
+        if dest_x >= config.map_width {
             dest_x = 0;
         } else if dest_x <= -1 {
-            dest_x = CONFIG.screen_width - 1;
+            dest_x = config.screen_width - 1;
         }
-        if dest_y >= CONFIG.map_height {
+        if dest_y >= config.map_height {
             dest_y = 0;
         } else if dest_y <= -1 {
-            dest_y = CONFIG.map_height - 1;
+            dest_y = config.map_height - 1;
         }
 
         position.x = dest_x;
         position.y = dest_y;
 
-        MoveEvent::new(entity, start_x, start_y, position.x, position.y)
+        Ok(MoveEvent::new(
+            entity, start_x, start_y, position.x, position.y,
+        ))
     }
 
     fn get_cost(base: u32, modifier: f32) -> u32 {
@@ -146,10 +123,15 @@ pub struct MovementSystemData<'a> {
     pub world_updater: Read<'a, LazyUpdate>,
     pub action_results: WriteStorage<'a, ActionResult>,
     pub mobiles: ReadStorage<'a, Mobile>,
+    pub config: ReadExpect<'a, Config>,
 
     // requests
     pub move_requests: WriteStorage<'a, MoveRequest>,
     pub attack_requests: WriteStorage<'a, AttackRequest>,
+
+    // events
+    pub move_events: Write<'a, EventChannel<MoveEvent>>,
+    pub collision_events: Write<'a, EventChannel<CollisionEvent>>,
 }
 
 impl<'a> System<'a> for Movement {
@@ -166,7 +148,16 @@ impl<'a> System<'a> for Movement {
                 // println!("got here");
 
                 let actor_map = &data.entity_map.actors;
-                let move_event = Self::try_move_position(ent, pos, move_request, &view, actor_map);
+                let config = &data.config;
+                let move_event =
+                    match Self::try_move_position(ent, pos, move_request, &view, actor_map, config)
+                    {
+                        Ok(move_event) => move_event,
+                        Err(collision) => {
+                            data.collision_events.single_write(collision);
+                            MoveEvent::new(ent, pos.x, pos.y, pos.x, pos.y)
+                        }
+                    };
 
                 // diagonals cost should be more
                 let cost_modifier: f32 = match i32::abs(move_event.dest_x - move_event.start_x)
@@ -179,7 +170,7 @@ impl<'a> System<'a> for Movement {
 
                 let cost = match data.mobiles.get(ent) {
                     Some(mobile) => Self::get_cost(mobile.quickness, cost_modifier),
-                    None => Self::get_cost(CONFIG.base_turn_time, cost_modifier),
+                    None => Self::get_cost(data.config.base_turn_time, cost_modifier),
                 };
 
                 let (x, y) = (move_event.start_x, move_event.start_y);
@@ -192,6 +183,10 @@ impl<'a> System<'a> for Movement {
                 data.entity_map.actors.set_point(dx, dy, Some(ent));
                 view.set(dx, dy, true, false);
 
+                if (x, y) != (dx, dy) {
+                    data.move_events.single_write(move_event);
+                }
+
                 if let Err(err) = data.action_results.insert(ent, ActionResult::from(cost)) {
                     error!(
                         "Failed to insert action result from Movement system: {}",
@@ -203,12 +198,162 @@ impl<'a> System<'a> for Movement {
     }
 }
 
+// where an entity was last put on the collision map, and what it counted for there
+#[derive(Clone, Copy)]
+struct Placed {
+    entity: Entity,
+    position: Position,
+    actor: bool,
+    blocks_movement: bool,
+    blocks_sight: bool,
+}
+
+// what `CollisionMapUpdater` knows about the collision map between runs. it's a resource, not
+// part of the system, because the updater runs in more than one dispatcher and each change
+// should only be applied once
+pub struct CollisionTracker {
+    position_reader: ReaderId<ComponentEvent>,
+    actor_reader: ReaderId<ComponentEvent>,
+    movement_blocker_reader: ReaderId<ComponentEvent>,
+    sight_blocker_reader: ReaderId<ComponentEvent>,
+    // only the entities that block something or are actors
+    placed: HashMap<Index, Placed>,
+    // how many things block (movement, sight) on each tile, actors and terrain included
+    blockers: Vec<(u32, u32)>,
+    // of the terrain the counts were made with
+    terrain_revision: u64,
+}
+
+impl CollisionTracker {
+    fn new(world: &mut World) -> Self {
+        CollisionTracker {
+            position_reader: world.write_storage::<Position>().register_reader(),
+            actor_reader: world.write_storage::<Actor>().register_reader(),
+            movement_blocker_reader: world.write_storage::<BlockMovement>().register_reader(),
+            sight_blocker_reader: world.write_storage::<BlockSight>().register_reader(),
+            placed: HashMap::new(),
+            blockers: Vec::new(),
+            terrain_revision: 0,
+        }
+    }
+}
+
+// keeps `EntityMap.actors`, `View.block_map` and the tcod map up to date. only the entities
+// whose `Position`, `Actor`, `BlockMovement` or `BlockSight` changed since the last run are looked
+// at, and only the tiles they left or arrived on are recomputed. the terrain hardly ever changes,
+// so when it does everything is counted again
+#[derive(Default)]
 pub struct CollisionMapUpdater;
 
 impl CollisionMapUpdater {
     pub fn new() -> Self {
         CollisionMapUpdater {}
     }
+
+    fn placed(ent: Entity, data: &CollisionMapUpdaterSystemData) -> Option<Placed> {
+        if !data.entities.is_alive(ent) {
+            return None;
+        }
+        let position = *data.positions.get(ent)?;
+        let placed = Placed {
+            entity: ent,
+            position,
+            actor: data.actors.get(ent).is_some(),
+            blocks_movement: data.movement_blockers.get(ent).is_some(),
+            blocks_sight: data.sight_blockers.get(ent).is_some(),
+        };
+        let on_map = data.view.block_map.retrieve(position.x, position.y).is_ok();
+
+        if on_map && (placed.actor || placed.blocks_movement || placed.blocks_sight) {
+            Some(placed)
+        } else {
+            None
+        }
+    }
+
+    // adds (or with `sign` -1 takes away) what `placed` counts for on its tile
+    fn count(tracker: &mut CollisionTracker, map: &mut EntityMap, placed: &Placed, sign: i32) {
+        let (x, y) = (placed.position.x, placed.position.y);
+        let idx = map.actors.xy_idx(x, y);
+        let (movement, sight) = &mut tracker.blockers[idx];
+
+        if placed.actor || placed.blocks_movement {
+            Self::shift(movement, sign);
+        }
+        if placed.blocks_sight {
+            Self::shift(sight, sign);
+        }
+
+        if placed.actor {
+            if sign > 0 {
+                map.actors.set_point(x, y, Some(placed.entity));
+            } else if map.actors.retrieve(x, y) == Ok(Some(placed.entity)) {
+                // someone else might have arrived already
+                map.actors.reset_point(x, y);
+            }
+        }
+    }
+
+    fn shift(blockers: &mut u32, sign: i32) {
+        if sign > 0 {
+            *blockers += 1;
+        } else {
+            // only ever takes away what was added, so going below zero means the counts drifted
+            let left = blockers.checked_sub(1);
+            debug_assert!(left.is_some(), "took a blocker away from an empty tile");
+            *blockers = left.unwrap_or(0);
+        }
+    }
+
+    // starts over from the terrain and every entity, when the map is new, has changed size or
+    // has different terrain
+    fn rebuild(tracker: &mut CollisionTracker, data: &mut CollisionMapUpdaterSystemData) {
+        tracker.placed.clear();
+        tracker.blockers = (0..data.view.block_map.items.len())
+            .map(|idx| {
+                let (x, y) = data.view.block_map.idx_xy(idx);
+                (
+                    data.terrain.blocks_movement(x, y) as u32,
+                    data.terrain.blocks_sight(x, y) as u32,
+                )
+            })
+            .collect();
+        tracker.terrain_revision = data.terrain.revision();
+        data.entity_map.actors.reset_map();
+
+        let all: Vec<Entity> = (&data.entities, &data.positions)
+            .join()
+            .map(|(ent, _pos)| ent)
+            .collect();
+        for ent in all {
+            if let Some(placed) = Self::placed(ent, data) {
+                Self::count(tracker, &mut data.entity_map, &placed, 1);
+                tracker.placed.insert(ent.id(), placed);
+            }
+        }
+
+        let tiles: Vec<usize> = (0..tracker.blockers.len()).collect();
+        Self::set_tiles(tracker, data, &tiles);
+    }
+
+    fn set_tiles(
+        tracker: &CollisionTracker,
+        data: &mut CollisionMapUpdaterSystemData,
+        tiles: &[usize],
+    ) {
+        let view = &mut *data.view;
+        let mut tcod_map = view.map.lock().unwrap();
+
+        for &idx in tiles {
+            let (movement, sight) = tracker.blockers[idx];
+            let tile = &mut view.block_map.items[idx];
+            tile.blocks_movement = movement > 0;
+            tile.blocks_sight = sight > 0;
+
+            let (x, y) = view.block_map.idx_xy(idx);
+            tcod_map.set(x, y, sight == 0, movement == 0);
+        }
+    }
 }
 
 #[derive(SystemData)]
@@ -220,42 +365,237 @@ pub struct CollisionMapUpdaterSystemData<'a> {
     pub positions: ReadStorage<'a, Position>,
     pub entity_map: WriteExpect<'a, EntityMap>,
     pub view: WriteExpect<'a, View>,
+    pub terrain: ReadExpect<'a, Terrain>,
 }
 
 impl<'a> System<'a> for CollisionMapUpdater {
-    type SystemData = CollisionMapUpdaterSystemData<'a>;
+    type SystemData = (
+        CollisionMapUpdaterSystemData<'a>,
+        WriteExpect<'a, CollisionTracker>,
+    );
+
+    fn run(&mut self, (mut data, mut tracker): Self::SystemData) {
+        let tracker = &mut *tracker;
+
+        // everything that changed, in whatever order
+        let mut changed: HashSet<Index> = HashSet::new();
+        let index = |event: &ComponentEvent| match *event {
+            ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => id,
+            ComponentEvent::Removed(id) => id,
+        };
+        changed.extend(
+            data.positions
+                .channel()
+                .read(&mut tracker.position_reader)
+                .map(index),
+        );
+        // an actor's turn changes all the time, but only whether it is one matters here
+        changed.extend(
+            data.actors
+                .channel()
+                .read(&mut tracker.actor_reader)
+                .filter_map(|event| match *event {
+                    ComponentEvent::Modified(_) => None,
+                    ref event => Some(index(event)),
+                }),
+        );
+        changed.extend(
+            data.movement_blockers
+                .channel()
+                .read(&mut tracker.movement_blocker_reader)
+                .map(index),
+        );
+        changed.extend(
+            data.sight_blockers
+                .channel()
+                .read(&mut tracker.sight_blocker_reader)
+                .map(index),
+        );
+
+        if tracker.blockers.len() != data.view.block_map.items.len()
+            || tracker.terrain_revision != data.terrain.revision()
+        {
+            Self::rebuild(tracker, &mut data);
+            return;
+        }
 
-    fn run(&mut self, mut data: Self::SystemData) {
-        // for event in data.move_command_channel.read
+        let mut tiles = Vec::new();
+        for id in changed {
+            if let Some(old) = tracker.placed.remove(&id) {
+                Self::count(tracker, &mut data.entity_map, &old, -1);
+                tiles.push(data.view.block_map.xy_idx(old.position.x, old.position.y));
+            }
 
-        let mut view = data.view;
-        let mut map = data.entity_map;
+            let ent = data.entities.entity(id);
+            if let Some(new) = Self::placed(ent, &data) {
+                Self::count(tracker, &mut data.entity_map, &new, 1);
+                tiles.push(data.view.block_map.xy_idx(new.position.x, new.position.y));
+                tracker.placed.insert(id, new);
+            }
+        }
 
-        map.actors.reset_map();
-        view.block_map.reset_map();
+        tiles.sort();
+        tiles.dedup();
+        Self::set_tiles(tracker, &mut data, &tiles);
+    }
 
-        for (ent, pos) in (&data.entities, &data.positions).join() {
-            let id = view.block_map.xy_idx(pos.x, pos.y);
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        if !world.has_value::<CollisionTracker>() {
+            let tracker = CollisionTracker::new(world);
+            world.insert(tracker);
+        }
+    }
+}
 
-            if let Some(_sight_blocker) = data.sight_blockers.get(ent) {
-                view.block_map.items[id].blocks_sight = true
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::TerrainKind;
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+    use std::sync::{Arc, Mutex};
+    use vecmap::VecMap;
+
+    const SIZE: i32 = 8;
+
+    fn world() -> (World, CollisionMapUpdater) {
+        let mut world = World::new();
+        let mut updater = CollisionMapUpdater::new();
+        System::setup(&mut updater, &mut world);
+        world.insert(EntityMap::new(SIZE as usize, SIZE as usize));
+        world.insert(View {
+            map: Arc::new(Mutex::new(TcodMap::new(SIZE, SIZE))),
+            block_map: VecMap::filled_with(BlockTile::default(), SIZE, SIZE),
+        });
+        world.insert(Terrain::new(SIZE, SIZE));
+        (world, updater)
+    }
 
-            if let Some(_movement_blocker) = data.movement_blockers.get(ent) {
-                view.block_map.items[id].blocks_movement = true
-            }
+    fn wall(world: &mut World, x: i32, y: i32) -> Entity {
+        world
+            .create_entity()
+            .with(Position { x, y })
+            .with(BlockMovement)
+            .with(BlockSight)
+            .build()
+    }
 
-            if let Some(_actor) = data.actors.get(ent) {
-                map.actors.set_point(pos.x, pos.y, Some(ent));
-                view.block_map.items[id].blocks_movement = true
-            }
+    fn rock() -> TerrainKind {
+        TerrainKind {
+            name: String::from("rock"),
+            looks: RandomRenderable {
+                glyphs: String::from("#"),
+                fg_colors: vec![(255, 255, 255)],
+                bg_colors: None,
+            },
+            elevation: Elevation::Upright,
+            blocks_movement: true,
+            blocks_sight: true,
+            material: Material::Stone,
+        }
+    }
+
+    fn actor(world: &mut World, x: i32, y: i32) -> Entity {
+        world
+            .create_entity()
+            .with(Position { x, y })
+            .with(Actor {
+                next_turn: 0,
+                stats: Stats::default(),
+            })
+            .build()
+    }
+
+    fn snapshot(world: &World) -> Vec<(Option<Entity>, bool, bool)> {
+        let map = world.read_resource::<EntityMap>();
+        let view = world.read_resource::<View>();
+        let tcod_map = view.map.lock().unwrap();
+
+        (0..view.block_map.items.len())
+            .map(|i| {
+                let (x, y) = view.block_map.idx_xy(i);
+                let tile = view.block_map.items[i];
+                assert_eq!(tcod_map.is_transparent(x, y), !tile.blocks_sight);
+                assert_eq!(tcod_map.is_walkable(x, y), !tile.blocks_movement);
+                (map.actors.items[i], tile.blocks_movement, tile.blocks_sight)
+            })
+            .collect()
+    }
+
+    // runs the updater, then checks it ended up where starting over would have
+    fn check(world: &mut World, updater: &mut CollisionMapUpdater) {
+        updater.run_now(world);
+        world.maintain();
+        let incremental = snapshot(world);
+
+        {
+            let mut data = world.system_data::<CollisionMapUpdaterSystemData>();
+            let mut tracker = world.write_resource::<CollisionTracker>();
+            CollisionMapUpdater::rebuild(&mut tracker, &mut data);
         }
+        assert_eq!(snapshot(world), incremental);
+    }
 
-        for i in 0..view.block_map.items.len() {
-            let (x, y) = view.block_map.idx_xy(i);
-            let transparent = !view.block_map.items[i].blocks_sight;
-            let walkable = !view.block_map.items[i].blocks_movement;
-            view.map.lock().unwrap().set(x, y, transparent, walkable);
+    #[test]
+    fn keeps_up_with_a_full_rebuild() {
+        let (mut world, mut updater) = world();
+        let left = wall(&mut world, 2, 2);
+        wall(&mut world, 3, 2);
+        let orc = actor(&mut world, 1, 1);
+        check(&mut world, &mut updater);
+        assert!(snapshot(&world)[world.read_resource::<View>().block_map.xy_idx(1, 1)].1);
+
+        // move
+        *world.write_storage::<Position>().get_mut(orc).unwrap() = Position { x: 4, y: 4 };
+        check(&mut world, &mut updater);
+
+        // spawn, two on one tile and one where the actor is
+        let door = wall(&mut world, 2, 2);
+        let goblin = actor(&mut world, 5, 5);
+        wall(&mut world, 4, 4);
+        check(&mut world, &mut updater);
+
+        // delete one of the two, the tile still blocks
+        world.delete_entity(left).unwrap();
+        check(&mut world, &mut updater);
+        let idx = world.read_resource::<View>().block_map.xy_idx(2, 2);
+        assert!(snapshot(&world)[idx].1);
+
+        // the terrain changes: the door opens, then the goblin walks through it
+        world.write_storage::<BlockMovement>().remove(door);
+        world.write_storage::<BlockSight>().remove(door);
+        check(&mut world, &mut updater);
+        assert!(!snapshot(&world)[idx].1);
+
+        *world.write_storage::<Position>().get_mut(goblin).unwrap() = Position { x: 2, y: 2 };
+        check(&mut world, &mut updater);
+        assert_eq!(snapshot(&world)[idx], (Some(goblin), true, false));
+
+        // and the actor dies
+        world.delete_entity(goblin).unwrap();
+        check(&mut world, &mut updater);
+        assert_eq!(snapshot(&world)[idx], (None, false, false));
+
+        // rock under a wall entity, then the wall goes and the rock still blocks
+        let mut rng = StdRng::seed_from_u64(0);
+        let rock_idx = world.read_resource::<View>().block_map.xy_idx(3, 2);
+        {
+            let mut terrain = world.write_resource::<Terrain>();
+            terrain.place(3, 2, &rock(), &mut rng);
+            terrain.place(6, 6, &rock(), &mut rng);
         }
+        check(&mut world, &mut updater);
+        let walls: Vec<Entity> = (&world.entities(), &world.read_storage::<BlockSight>())
+            .join()
+            .map(|(ent, _)| ent)
+            .collect();
+        world.delete_entities(&walls).unwrap();
+        check(&mut world, &mut updater);
+        assert_eq!(snapshot(&world)[rock_idx], (None, true, true));
+
+        world.write_resource::<Terrain>().remove(3, 2);
+        check(&mut world, &mut updater);
+        assert_eq!(snapshot(&world)[rock_idx], (None, false, false));
     }
 }
@@ -12,10 +12,11 @@ impl<'a> System<'a> for Naming {
     type SystemData = NamingSystemData<'a>;
     fn run(&mut self, mut data: Self::SystemData) {
         for entity in (&data.entities).join() {
-            if data.names.get(entity) == None {
-                let entity_name = &String::from(format!("Entity{}", entity.id()));
-                let entity_name = Name::new(entity_name);
-                data.names.insert(entity, entity_name);
+            if data.names.get(entity).is_none() {
+                let entity_name = Name::new(&format!("Entity{}", entity.id()));
+                if let Err(err) = data.names.insert(entity, entity_name) {
+                    error!("Failed to insert name: {}", err)
+                }
             }
         }
     }
@@ -0,0 +1,86 @@
+use crate::backend::{Color, Screen};
+use crate::config::Config;
+use crate::ecs::{GameState, State};
+use crate::profiler::{millis, Profiler, DISPATCH};
+use crate::systems::menu::put_str;
+use specs::prelude::*;
+use std::sync::Arc;
+
+const PANEL_WIDTH: i32 = 46;
+const PANEL_FG: Color = (200, 200, 200);
+const PANEL_BG: Color = (35, 20, 20);
+const DISPATCH_FG: Color = (255, 220, 120);
+
+// how many of the slowest systems are listed
+const SHOWN: usize = 12;
+
+#[derive(SystemData)]
+pub struct RenderProfilerSystemData<'a> {
+    pub game_state: ReadExpect<'a, GameState>,
+    pub screen: WriteExpect<'a, Screen>,
+    pub config: ReadExpect<'a, Config>,
+}
+
+// the rolling averages from the `Profiler`, down the left of the viewport. every dispatch first,
+// then the slowest systems in any of them
+pub struct RenderProfiler {
+    profiler: Arc<Profiler>,
+}
+
+impl RenderProfiler {
+    pub fn new(profiler: Arc<Profiler>) -> Self {
+        RenderProfiler { profiler }
+    }
+}
+
+impl<'a> System<'a> for RenderProfiler {
+    type SystemData = RenderProfilerSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        // nothing is drawn while the turns are processed
+        if data.game_state.current() == State::TurnProcess {
+            return;
+        }
+
+        let rolling = self.profiler.rolling();
+        let (dispatches, systems): (Vec<_>, Vec<_>) = rolling
+            .iter()
+            .partition(|(_, system, _)| system == DISPATCH);
+        let rows: Vec<(String, Color)> = dispatches
+            .iter()
+            .map(|(dispatcher, _, elapsed)| {
+                let text = format!("{:<34.34}{:>8.3}ms", dispatcher, millis(*elapsed));
+                (text, DISPATCH_FG)
+            })
+            .chain(
+                systems
+                    .iter()
+                    .take(SHOWN)
+                    .map(|(dispatcher, system, elapsed)| {
+                        let name = format!("{} {}", dispatcher, system);
+                        (
+                            format!("{:<34.34}{:>8.3}ms", name, millis(*elapsed)),
+                            PANEL_FG,
+                        )
+                    }),
+            )
+            .collect();
+
+        let config = &data.config;
+        let width = PANEL_WIDTH.min(config.viewport_width);
+        let x = config.viewport_x;
+        let screen = &mut **data.screen;
+        for (dy, (row, fg)) in rows
+            .iter()
+            .take((config.viewport_height - 1).max(0) as usize)
+            .enumerate()
+        {
+            // under the top line, which targeting uses
+            let y = config.viewport_y + 1 + dy as i32;
+            for dx in 0..width {
+                screen.put_char(x + dx, y, ' ', PANEL_FG, PANEL_BG);
+            }
+            put_str(screen, x + 1, y, width - 1, row, *fg, PANEL_BG);
+        }
+    }
+}
@@ -1,13 +1,17 @@
+use crate::backend::{Renderer, Screen};
 use crate::components::*;
-use crate::map::{EntityMap, View};
+use crate::map::{EntityMap, SpatialIndex, View};
+use crate::rng::{GameRng, Stream};
+use crate::systems::effects::Effects;
+use crate::terrain::Terrain;
 use crate::MessageLog;
-use crate::CONFIG;
+use crate::config::Config;
 use crate::State;
 use rand::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use std::sync::MutexGuard;
-use tcod::console::*;
+use std::time::Instant;
 use tcod::map::FovAlgorithm;
 use tcod::Map as TcodMap;
 use vecmap::*;
@@ -30,7 +34,7 @@ impl LayeredTileMap {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct Tile {
     pub position: Position,
     pub elevation: Elevation,
@@ -39,6 +43,12 @@ pub struct Tile {
     pub bg_color: Option<(u8, u8, u8)>,
 }
 
+impl Default for Tile {
+    fn default() -> Self {
+        Tile::new()
+    }
+}
+
 impl Tile {
     pub fn new() -> Self {
         Tile {
@@ -51,14 +61,49 @@ impl Tile {
     }
 }
 
+// how a tile at `pos` is remembered once it's been seen
+pub fn seen_tile(pos: Position, walkable: bool) -> Tile {
+    let mut tile = Tile::new();
+    tile.bg_color = Some(match walkable {
+        true => (5, 5, 10),
+        false => (9, 9, 16),
+    });
+    tile.position = pos;
+    tile
+}
+
+// every tile the player has seen, drawn dimmed while out of view. it's a resource rather than
+// part of the viewport so it gets saved with the world
+pub struct SeenTiles {
+    pub tiles: TileMap,
+}
+
+impl SeenTiles {
+    pub fn new(width: i32, height: i32) -> Self {
+        SeenTiles {
+            tiles: TileMap::filled_with(None, width, height),
+        }
+    }
+}
+
 struct Viewport {
     width: i32,
     height: i32,
-    seen: TileMap,
+    map_width: i32,
+    map_height: i32,
 }
 
 impl Viewport {
-    pub fn set_tile(&self, mut tile: Tile, layered_tile_map: &mut LayeredTileMap) {
+    fn new(config: &Config) -> Self {
+        Viewport {
+            width: config.viewport_width,
+            height: config.viewport_height,
+            map_width: config.map_width,
+            map_height: config.map_height,
+        }
+    }
+
+    pub fn set_tile(&self, tile: Tile, layered_tile_map: &mut LayeredTileMap) {
         let rend_pos = tile.position;
         let (x, y) = (rend_pos.x, rend_pos.y);
 
@@ -79,10 +124,44 @@ impl Viewport {
         tile_map.set_point(x, y, Some(tile));
     }
 
+    // the terrain the player sees, under whatever is on it
+    fn set_terrain(&mut self, data: &mut RenderSystemData) {
+        let camera_pos = self.get_camera_position(&data.positions, &data.cameras);
+        let fov_map = data.view.map.lock().unwrap();
+        let layers: &mut LayeredTileMap = &mut data.layered_tile_map;
+
+        for (pos, kind, cell) in data.terrain.cells() {
+            if !fov_map.is_in_fov(pos.x, pos.y) {
+                continue;
+            }
+            let mut tile = Tile {
+                position: self.get_screen_coordinates(pos, camera_pos),
+                elevation: kind.elevation,
+                glyph: cell.glyph,
+                fg_color: cell.fg_color,
+                bg_color: cell.bg_color,
+            };
+            if data.config.debug_vision && !fov_map.is_walkable(pos.x, pos.y) {
+                tile.bg_color = Some((255, 0, 0))
+            }
+            self.set_tile(tile, layers);
+        }
+    }
+
     // creates full character map of what the player sees and has seen.
     fn set_map(&mut self, data: &mut RenderSystemData) {
-        let camera_pos = self.get_camera_position(data);
-        for (ent, pos, renderable) in (&data.entities, &data.positions, &data.renderables).join() {
+        let camera_pos = self.get_camera_position(&data.positions, &data.cameras);
+        // only what's on screen
+        let (left, top) = (camera_pos.x - self.width / 2, camera_pos.y - self.height / 2);
+        let on_screen = data
+            .spatial_index
+            .in_rect(left, top, self.width, self.height);
+
+        for ent in on_screen {
+            let (pos, renderable) = match (data.positions.get(ent), data.renderables.get(ent)) {
+                (Some(pos), Some(renderable)) => (pos, renderable),
+                _ => continue,
+            };
             let fov_map = data.view.map.lock().unwrap();
             let (glyph, fg_color, bg_color) =
                 (renderable.glyph, renderable.fg_color, renderable.bg_color);
@@ -106,8 +185,8 @@ impl Viewport {
                 bg_color,
             };
 
-            if CONFIG.debug_vision {
-                tile = self.debug_process_tile(tile, &data, *pos, screen_pos, ent, fov_map)
+            if data.config.debug_vision {
+                tile = self.debug_process_tile(tile, data, *pos, ent, fov_map)
             }
 
             self.set_tile(tile, &mut data.layered_tile_map);
@@ -116,31 +195,52 @@ impl Viewport {
 
     // TODO: maybe put this back in the Seeing component? or Actor? idk.
     fn set_seen(&mut self, data: &mut RenderSystemData) {
-        let camera_pos = self.get_camera_position(data);
+        let camera_pos = self.get_camera_position(&data.positions, &data.cameras);
         let fov_map = data.view.map.lock().unwrap();
 
-        for x in 0..CONFIG.map_width {
-            for y in 0..CONFIG.map_height {
+        for x in 0..self.map_width {
+            for y in 0..self.map_height {
                 let pos = Position::new(x, y);
                 let screen_pos = self.get_screen_coordinates(pos, camera_pos);
 
                 if fov_map.is_in_fov(x, y) {
-                    let color = match fov_map.is_walkable(x, y) {
-                        true => (5, 5, 10),
-                        false => (9, 9, 16),
-                    };
-
-                    let mut tile = Tile::new();
-                    tile.bg_color = Some(color);
-                    tile.position = pos;
-                    self.seen.set_point(x, y, Some(tile));
-                } else {
-                    if let Ok(mut tile) = self.seen.retrieve(x, y) {
-                        if let Some(mut tile) = tile {
-                            tile.position = screen_pos;
-                            self.set_tile(tile, &mut data.layered_tile_map);
-                        }
-                    }
+                    let tile = seen_tile(pos, fov_map.is_walkable(x, y));
+                    data.seen_tiles.tiles.set_point(x, y, Some(tile));
+                } else if let Ok(Some(mut tile)) = data.seen_tiles.tiles.retrieve(x, y) {
+                    tile.position = screen_pos;
+                    self.set_tile(tile, &mut data.layered_tile_map);
+                }
+            }
+        }
+    }
+
+    // tints whatever is drawn where something just happened, see `Effects`
+    fn set_flashes(&mut self, data: &mut RenderSystemData) {
+        let camera_pos = self.get_camera_position(&data.positions, &data.cameras);
+        let fov_map = data.view.map.lock().unwrap();
+        let now = Instant::now();
+        let layers: &mut LayeredTileMap = &mut data.layered_tile_map;
+
+        for flash in data.effects.flashes.iter().filter(|flash| flash.until > now) {
+            let pos = flash.position;
+            if !fov_map.is_in_fov(pos.x, pos.y) {
+                continue;
+            }
+            let screen_pos = self.get_screen_coordinates(pos, camera_pos);
+            if screen_pos.x < 0 || screen_pos.x >= self.width {
+                continue;
+            }
+            if screen_pos.y < 0 || screen_pos.y >= self.height {
+                continue;
+            }
+            for tile_map in [
+                &mut layers.floor_tiles,
+                &mut layers.on_floor_tiles,
+                &mut layers.upright_tiles,
+            ] {
+                if let Ok(Some(mut tile)) = tile_map.retrieve(screen_pos.x, screen_pos.y) {
+                    tile.bg_color = Some(flash.color);
+                    tile_map.set_point(screen_pos.x, screen_pos.y, Some(tile));
                 }
             }
         }
@@ -165,39 +265,43 @@ impl Viewport {
         let screen_center = Position::new(self.width / 2, self.height / 2);
         let mut wx = rend_pos.x - screen_center.x + camera_pos.x;
         let mut wy = rend_pos.x - screen_center.x + camera_pos.y;
-        if wx > CONFIG.map_width {
-            wx = CONFIG.map_width
+        if wx > self.map_width {
+            wx = self.map_width
         }
-        if wy > CONFIG.map_height {
-            wy = CONFIG.map_height
+        if wy > self.map_height {
+            wy = self.map_height
         }
         Position::new(wx, wy)
     }
 
-    fn get_camera_position(&self, data: &RenderSystemData) -> Position {
+    fn get_camera_position(
+        &self,
+        positions: &ReadStorage<Position>,
+        cameras: &ReadStorage<Camera>,
+    ) -> Position {
         let mut camera_position = Position::new(0, 0);
         let viewport_width = self.width;
         let viewport_height = self.height;
-        if viewport_width < CONFIG.map_width {
-            for (pos, _camera) in (&data.positions, &data.cameras).join() {
+        if viewport_width < self.map_width {
+            for (pos, _camera) in (positions, cameras).join() {
                 camera_position.x = pos.x;
                 if camera_position.x - viewport_width / 2 < 0 {
                     camera_position.x = viewport_width / 2;
-                } else if camera_position.x + viewport_width / 2 > CONFIG.map_width {
-                    camera_position.x = CONFIG.map_width - viewport_width / 2
+                } else if camera_position.x + viewport_width / 2 > self.map_width {
+                    camera_position.x = self.map_width - viewport_width / 2
                 }
             }
         } else {
             camera_position.x = viewport_width / 2
         }
 
-        if viewport_height < CONFIG.map_height {
-            for (pos, _camera) in (&data.positions, &data.cameras).join() {
+        if viewport_height < self.map_height {
+            for (pos, _camera) in (positions, cameras).join() {
                 camera_position.y = pos.y;
                 if camera_position.y - viewport_height / 2 < 1 {
                     camera_position.y = viewport_height / 2;
-                } else if camera_position.y + viewport_height / 2 > CONFIG.map_height {
-                    camera_position.y = CONFIG.map_height - viewport_height / 2
+                } else if camera_position.y + viewport_height / 2 > self.map_height {
+                    camera_position.y = self.map_height - viewport_height / 2
                 }
             }
         } else {
@@ -212,7 +316,6 @@ impl Viewport {
         mut tile: Tile,
         data: &RenderSystemData,
         pos: Position,
-        screen_pos: Position,
         ent: Entity,
         fov_map: MutexGuard<TcodMap>,
     ) -> Tile {
@@ -222,19 +325,17 @@ impl Viewport {
             tile.bg_color = Some((255, 0, 0))
         }
 
-        if let Ok(point) = entity_map.retrieve(pos.x, pos.y) {
-            if let Some(actor) = point {
-                let mut red = 0;
-                let mut green = 0;
-                if let Some(bg) = tile.bg_color {
-                    red = bg.0;
-                    green = bg.1;
-                }
-                tile.bg_color = Some((red, green, 255));
+        if let Ok(Some(_actor)) = entity_map.retrieve(pos.x, pos.y) {
+            let mut red = 0;
+            let mut green = 0;
+            if let Some(bg) = tile.bg_color {
+                red = bg.0;
+                green = bg.1;
             }
+            tile.bg_color = Some((red, green, 255));
         }
 
-        if let Some(my_turn) = data.my_turns.get(ent) {
+        if data.my_turns.get(ent).is_some() {
             let mut red = 0;
             let mut blue = 0;
             if let Some(bg) = tile.bg_color {
@@ -249,79 +350,77 @@ impl Viewport {
 
 #[derive(SystemData)]
 pub struct RenderSystemData<'a> {
-    entities: Entities<'a>,
     renderables: ReadStorage<'a, Renderable>,
     positions: ReadStorage<'a, Position>,
-    players: ReadStorage<'a, PlayerControl>,
     cameras: ReadStorage<'a, Camera>,
-    floors: ReadStorage<'a, Floor>,
-    on_floors: ReadStorage<'a, OnFloor>,
     game_state: ReadExpect<'a, crate::GameState>,
     view: ReadExpect<'a, View>,
     layered_tile_map: WriteExpect<'a, LayeredTileMap>,
-    console: WriteExpect<'a, Root>,
+    screen: WriteExpect<'a, Screen>,
     message_log: WriteExpect<'a, MessageLog>,
+    seen_tiles: WriteExpect<'a, SeenTiles>,
     entity_map: ReadExpect<'a, EntityMap>,
     my_turns: ReadStorage<'a, MyTurn>,
-    names: ReadStorage<'a, Name>,
-    actors: ReadStorage<'a, Actor>,
     elevations: ReadStorage<'a, Elevation>,
+    effects: Read<'a, Effects>,
+    terrain: ReadExpect<'a, Terrain>,
+    spatial_index: ReadExpect<'a, SpatialIndex>,
+    config: ReadExpect<'a, Config>,
 }
 
+#[derive(Default)]
 pub struct RenderViewport {
     viewport: Option<Viewport>,
 }
 
 impl RenderViewport {
     pub fn new() -> Self {
-        let viewport = Some(Viewport {
-            width: CONFIG.viewport_width,
-            height: CONFIG.viewport_height,
-            seen: TileMap::filled_with(None, CONFIG.map_width, CONFIG.map_height),
-        });
+        RenderViewport { viewport: None }
+    }
 
-        RenderViewport { viewport }
+    // where a map position ends up on the screen, following the camera like the viewport does
+    pub fn screen_position(
+        pos: Position,
+        positions: &ReadStorage<Position>,
+        cameras: &ReadStorage<Camera>,
+        config: &Config,
+    ) -> Position {
+        let viewport = Viewport::new(config);
+        let camera_pos = viewport.get_camera_position(positions, cameras);
+        let screen_pos = viewport.get_screen_coordinates(pos, camera_pos);
+        Position::new(
+            screen_pos.x + config.viewport_x,
+            screen_pos.y + config.viewport_y,
+        )
     }
 
-    pub fn render(console: &mut Root, tile_map: &mut TileMap) {
-        for tile in tile_map.items.iter() {
-            if let Some(tile) = tile {
-                if tile.glyph != ' ' || tile.bg_color != None {
-                    Self::render_char(console, *tile);
-                }
+    pub fn render(screen: &mut dyn Renderer, tile_map: &mut TileMap, config: &Config) {
+        for tile in tile_map.items.iter().flatten() {
+            if tile.glyph != ' ' || tile.bg_color.is_some() {
+                Self::render_char(screen, *tile, config);
             }
         }
     }
 
-    pub fn render_char(console: &mut Root, tile: Tile) {
-        if tile.position.x < 0 || tile.position.x >= console.width() {
+    pub fn render_char(screen: &mut dyn Renderer, tile: Tile, config: &Config) {
+        let (width, height) = screen.size();
+        if tile.position.x < 0 || tile.position.x >= width {
             return;
         };
-        if tile.position.y < 0 || tile.position.y >= console.height() {
+        if tile.position.y < 0 || tile.position.y >= height {
             return;
         };
 
-        //        println!("{:?}", tile);
-        let mut bg_color = console.get_char_background(tile.position.x, tile.position.y);
-
-        let (fg_r, fg_g, fg_b) = tile.fg_color;
-        if let Some((r, g, b)) = tile.bg_color {
-            bg_color.r = r;
-            bg_color.g = g;
-            bg_color.b = b;
-        }
-
-        let fg_color = tcod::colors::Color {
-            r: fg_r,
-            g: fg_g,
-            b: fg_b,
+        let bg_color = match tile.bg_color {
+            Some(color) => color,
+            None => screen.background(tile.position.x, tile.position.y),
         };
 
-        console.put_char_ex(
-            tile.position.x + CONFIG.viewport_x,
-            tile.position.y + CONFIG.viewport_y,
+        screen.put_char(
+            tile.position.x + config.viewport_x,
+            tile.position.y + config.viewport_y,
             tile.glyph,
-            fg_color,
+            tile.fg_color,
             bg_color,
         );
     }
@@ -332,38 +431,43 @@ impl<'a> System<'a> for RenderViewport {
 
     fn run(&mut self, mut data: Self::SystemData) {
         if data.game_state.current() == State::TurnProcess {
-            tcod::system::set_fps(0);
+            data.screen.set_fps(0);
             return;
         }
 
         {
-            let mut layered_tile_map = &mut data.layered_tile_map;
+            let layered_tile_map = &mut data.layered_tile_map;
             layered_tile_map.floor_tiles.reset_map();
             layered_tile_map.on_floor_tiles.reset_map();
             layered_tile_map.upright_tiles.reset_map();
         }
 
-        tcod::system::set_fps(60);
+        data.screen.set_fps(60);
 
         {
             let mut fov_map = data.view.map.lock().unwrap();
-            for (pos, camera) in (&data.positions, &data.cameras).join() {
+            for (pos, _camera) in (&data.positions, &data.cameras).join() {
                 fov_map.compute_fov(pos.x, pos.y, 100, true, FovAlgorithm::Restrictive);
             }
         }
 
         {
-            let mut viewport = self.viewport.as_mut().unwrap();
+            // made again every frame, in case the config changed
+            self.viewport = Some(Viewport::new(&data.config));
+            let viewport = self.viewport.as_mut().unwrap();
             viewport.set_seen(&mut data);
+            viewport.set_terrain(&mut data);
             viewport.set_map(&mut data);
+            viewport.set_flashes(&mut data);
         }
 
-        let console = &mut data.console;
-        console.clear();
-        let mut layered_tile_map = &mut data.layered_tile_map;
-        Self::render(console, &mut layered_tile_map.floor_tiles);
-        Self::render(console, &mut layered_tile_map.on_floor_tiles);
-        Self::render(console, &mut layered_tile_map.upright_tiles);
+        let screen = &mut **data.screen;
+        screen.clear();
+        let layered_tile_map = &mut data.layered_tile_map;
+        let config = &data.config;
+        Self::render(screen, &mut layered_tile_map.floor_tiles, config);
+        Self::render(screen, &mut layered_tile_map.on_floor_tiles, config);
+        Self::render(screen, &mut layered_tile_map.upright_tiles, config);
     }
 }
 
@@ -372,8 +476,9 @@ impl<'a> System<'a> for RenderUi {
     type SystemData = RenderSystemData<'a>;
     fn run(&mut self, mut data: Self::SystemData) {
         let message_log = data.message_log;
-        let console = &mut data.console;
-        let message_log_height = (CONFIG.screen_height - CONFIG.viewport_height) as usize;
+        let screen = &mut data.screen;
+        let config = &data.config;
+        let message_log_height = (config.screen_height - config.viewport_height) as usize;
         let mut formatted_message = String::new();
         for (i, message) in message_log.messages.iter().enumerate() {
             if i > message_log_height {
@@ -381,12 +486,12 @@ impl<'a> System<'a> for RenderUi {
             }
             formatted_message = format!("{}\n{}", message, formatted_message);
         }
-        console.print_rect(
+        screen.print_rect(
             0,
-            CONFIG.viewport_height,
-            CONFIG.screen_width,
+            config.viewport_height,
+            config.screen_width,
             message_log_height as i32,
-            formatted_message,
+            &formatted_message,
         );
     }
 }
@@ -397,24 +502,21 @@ pub struct RandomRenderSystemData<'a> {
     renderables: WriteStorage<'a, Renderable>,
     random_renderables: WriteStorage<'a, RandomRenderable>,
     world_updater: Read<'a, LazyUpdate>,
+    rng: WriteExpect<'a, GameRng>,
 }
 
 pub struct RandomRender;
 impl<'a> System<'a> for RandomRender {
     type SystemData = RandomRenderSystemData<'a>;
     fn run(&mut self, mut data: Self::SystemData) {
-        let mut rng = rand::thread_rng();
+        let rng = data.rng.stream(Stream::Cosmetic);
         for (random_renderable, ent) in (&mut data.random_renderables, &data.entities).join() {
-            let glyph = random_renderable.glyphs.chars().choose(&mut rng).unwrap();
+            let glyph = random_renderable.glyphs.chars().choose(rng).unwrap();
 
-            let fg_color = random_renderable
-                .fg_colors
-                .choose(&mut rng)
-                .unwrap()
-                .clone();
+            let fg_color = *random_renderable.fg_colors.choose(rng).unwrap();
             let mut bg_color = None;
             if let Some(colors) = &random_renderable.bg_colors {
-                bg_color = Some(colors.choose(&mut rng).unwrap().clone());
+                bg_color = Some(*colors.choose(rng).unwrap());
             }
 
             if let Some(renderable) = data.renderables.get_mut(ent) {
@@ -427,7 +529,9 @@ impl<'a> System<'a> for RandomRender {
                     fg_color,
                     bg_color,
                 };
-                data.renderables.insert(ent, new_renderable);
+                if let Err(err) = data.renderables.insert(ent, new_renderable) {
+                    error!("Failed to insert renderable from RandomRender system: {}", err)
+                }
             }
             data.world_updater.remove::<RandomRenderable>(ent);
         }
@@ -0,0 +1,88 @@
+use crate::backend::Screen;
+use crate::command::CommandEvent;
+use crate::components::{MyTurn, PlayerControl};
+use crate::ecs::MessageLog;
+use crate::replay::Replay;
+use shrev::EventChannel;
+use specs::prelude::*;
+use std::time::{Duration, Instant};
+
+// how long to wait between commands when not fast forwarding, so the replay can be followed
+const REPLAY_DELAY: Duration = Duration::from_millis(150);
+
+#[derive(SystemData)]
+pub struct ReplayInputSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub players: ReadStorage<'a, PlayerControl>,
+    pub my_turns: ReadStorage<'a, MyTurn>,
+    pub game_state: WriteExpect<'a, crate::GameState>,
+    pub replay: Write<'a, Replay>,
+    pub screen: WriteExpect<'a, Screen>,
+    pub message_log: WriteExpect<'a, MessageLog>,
+    pub command_event_channel: Write<'a, EventChannel<CommandEvent>>,
+}
+
+// plays back a recording in place of `Input`, giving the player each recorded command on their
+// turn
+#[derive(Default)]
+pub struct ReplayInput {
+    last_command: Option<Instant>,
+}
+
+impl ReplayInput {
+    pub fn new() -> Self {
+        ReplayInput { last_command: None }
+    }
+}
+
+impl<'a> System<'a> for ReplayInput {
+    type SystemData = ReplayInputSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        if !data.replay.is_playing() {
+            return;
+        }
+
+        if data.replay.fast_forward {
+            data.screen.set_fps(0);
+        } else if let Some(last_command) = self.last_command {
+            if last_command.elapsed() < REPLAY_DELAY {
+                return;
+            }
+        }
+
+        let tick = data.game_state.world_time.tick;
+        for (ent, _player, _my_turn) in (&data.entities, &data.players, &data.my_turns).join() {
+            if data.replay.stop_at.is_some_and(|stop_at| tick >= stop_at) {
+                data.replay.stop();
+                data.message_log
+                    .log(format!("[REPLAY STOPPED AT TICK {}]", tick));
+                return;
+            }
+
+            let recorded = match data.replay.next_command() {
+                Some(recorded) => recorded,
+                None => return,
+            };
+            if recorded.tick != tick {
+                warn!(
+                    "replay out of sync: command recorded at tick {} given at tick {}",
+                    recorded.tick, tick
+                );
+            }
+
+            let command = recorded.command.command();
+            // the wizard's commands don't take a turn when they're given either
+            if !command.is_wizard() {
+                data.game_state.world_time.increment_player_turn();
+            }
+            data.command_event_channel
+                .single_write(CommandEvent::new(command, ent));
+            self.last_command = Some(Instant::now());
+
+            if !data.replay.is_playing() {
+                data.message_log.log(String::from("[REPLAY FINISHED]"));
+            }
+        }
+    }
+}
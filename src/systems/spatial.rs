@@ -0,0 +1,109 @@
+use crate::components::{Elevation, Position};
+use crate::map::SpatialIndex;
+use specs::prelude::*;
+use specs::world::Index;
+use std::collections::HashSet;
+
+// the readers of `SpatialIndexer`, a resource like `CollisionTracker` so every dispatcher's
+// indexer picks up where the last one left off
+pub struct SpatialTracker {
+    position_reader: ReaderId<ComponentEvent>,
+    elevation_reader: ReaderId<ComponentEvent>,
+    // nothing has been indexed yet
+    fresh: bool,
+}
+
+impl SpatialTracker {
+    fn new(world: &mut World) -> Self {
+        SpatialTracker {
+            position_reader: world.write_storage::<Position>().register_reader(),
+            elevation_reader: world.write_storage::<Elevation>().register_reader(),
+            fresh: true,
+        }
+    }
+}
+
+// keeps the `SpatialIndex` up to date with the entities whose `Position` or `Elevation` changed,
+// were spawned or were deleted since it last ran
+#[derive(Default)]
+pub struct SpatialIndexer;
+
+impl SpatialIndexer {
+    pub fn new() -> Self {
+        SpatialIndexer {}
+    }
+
+    fn index(ent: Entity, data: &mut SpatialIndexerSystemData) {
+        let pos = match data.positions.get(ent) {
+            Some(pos) if data.entities.is_alive(ent) => *pos,
+            _ => return data.index.remove(ent),
+        };
+        let elevation = data
+            .elevations
+            .get(ent)
+            .cloned()
+            .unwrap_or(Elevation::Upright);
+        data.index.insert(ent, pos, elevation);
+    }
+}
+
+#[derive(SystemData)]
+pub struct SpatialIndexerSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub positions: ReadStorage<'a, Position>,
+    pub elevations: ReadStorage<'a, Elevation>,
+    pub index: WriteExpect<'a, SpatialIndex>,
+}
+
+impl<'a> System<'a> for SpatialIndexer {
+    type SystemData = (
+        SpatialIndexerSystemData<'a>,
+        WriteExpect<'a, SpatialTracker>,
+    );
+
+    fn run(&mut self, (mut data, mut tracker): Self::SystemData) {
+        let tracker = &mut *tracker;
+        let index = |event: &ComponentEvent| match *event {
+            ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => id,
+            ComponentEvent::Removed(id) => id,
+        };
+        let changed: HashSet<Index> = data
+            .positions
+            .channel()
+            .read(&mut tracker.position_reader)
+            .map(index)
+            .chain(
+                data.elevations
+                    .channel()
+                    .read(&mut tracker.elevation_reader)
+                    .map(index),
+            )
+            .collect();
+
+        if tracker.fresh {
+            tracker.fresh = false;
+            data.index.clear();
+            let all: Vec<Entity> = (&data.entities, &data.positions)
+                .join()
+                .map(|(ent, _pos)| ent)
+                .collect();
+            for ent in all {
+                Self::index(ent, &mut data);
+            }
+            return;
+        }
+
+        for id in changed {
+            let ent = data.entities.entity(id);
+            Self::index(ent, &mut data);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        if !world.has_value::<SpatialTracker>() {
+            let tracker = SpatialTracker::new(world);
+            world.insert(tracker);
+        }
+    }
+}
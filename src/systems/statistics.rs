@@ -0,0 +1,102 @@
+use crate::components::PlayerControl;
+use crate::events::{self, AttackEvent, DamageEvent, DeathEvent, MoveEvent, PickupEvent};
+use serde::{Deserialize, Serialize};
+use shrev::{EventChannel, ReaderId};
+use specs::prelude::*;
+
+// how the player's game has gone so far, shown when it ends. saved with the world
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Statistics {
+    pub steps: u32,
+    pub attacks: u32,
+    pub damage_dealt: i32,
+    pub damage_taken: i32,
+    pub kills: u32,
+    pub items_picked_up: u32,
+}
+
+// counts up the `Statistics` from the events the player was part of
+#[derive(Default)]
+pub struct StatisticsKeeper {
+    move_reader: Option<ReaderId<MoveEvent>>,
+    attack_reader: Option<ReaderId<AttackEvent>>,
+    damage_reader: Option<ReaderId<DamageEvent>>,
+    death_reader: Option<ReaderId<DeathEvent>>,
+    pickup_reader: Option<ReaderId<PickupEvent>>,
+}
+
+impl StatisticsKeeper {
+    pub fn new() -> Self {
+        StatisticsKeeper::default()
+    }
+}
+
+#[derive(SystemData)]
+pub struct StatisticsSystemData<'a> {
+    pub players: ReadStorage<'a, PlayerControl>,
+    pub statistics: Write<'a, Statistics>,
+
+    // read event channels
+    pub move_events: Read<'a, EventChannel<MoveEvent>>,
+    pub attack_events: Read<'a, EventChannel<AttackEvent>>,
+    pub damage_events: Read<'a, EventChannel<DamageEvent>>,
+    pub death_events: Read<'a, EventChannel<DeathEvent>>,
+    pub pickup_events: Read<'a, EventChannel<PickupEvent>>,
+}
+
+impl<'a> System<'a> for StatisticsKeeper {
+    type SystemData = StatisticsSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let players = &data.players;
+        let is_player = |entity: Entity| players.get(entity).is_some();
+        let statistics = &mut *data.statistics;
+
+        for step in data.move_events.read(self.move_reader.as_mut().unwrap()) {
+            if is_player(step.entity) {
+                statistics.steps += 1;
+            }
+        }
+        for attack in data
+            .attack_events
+            .read(self.attack_reader.as_mut().unwrap())
+        {
+            if is_player(attack.attacker) {
+                statistics.attacks += 1;
+            }
+        }
+        for damage in data
+            .damage_events
+            .read(self.damage_reader.as_mut().unwrap())
+        {
+            if is_player(damage.source) {
+                statistics.damage_dealt += damage.amount;
+            }
+            if is_player(damage.entity) {
+                statistics.damage_taken += damage.amount;
+            }
+        }
+        for death in data.death_events.read(self.death_reader.as_mut().unwrap()) {
+            if death.killer.is_some_and(is_player) {
+                statistics.kills += 1;
+            }
+        }
+        for pickup in data
+            .pickup_events
+            .read(self.pickup_reader.as_mut().unwrap())
+        {
+            if is_player(pickup.entity) {
+                statistics.items_picked_up += 1;
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.move_reader = Some(events::reader(world));
+        self.attack_reader = Some(events::reader(world));
+        self.damage_reader = Some(events::reader(world));
+        self.death_reader = Some(events::reader(world));
+        self.pickup_reader = Some(events::reader(world));
+    }
+}
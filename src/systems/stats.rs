@@ -1,5 +1,5 @@
 use crate::components::*;
-use crate::CONFIG;
+use crate::config::Config;
 use specs::prelude::*;
 pub struct _StatCalculator;
 
@@ -7,18 +7,19 @@ pub struct _StatCalculator;
 pub struct StatsUpdaterSystemData<'a> {
     actors: WriteStorage<'a, Actor>,
     mobiles: WriteStorage<'a, Mobile>,
+    config: ReadExpect<'a, Config>,
 }
 
 pub struct QuicknessSystem;
 impl QuicknessSystem {
-    fn quickness_from_agility(agility: u32) -> i32 {
+    fn quickness_from_agility(agility: u32, config: &Config) -> i32 {
         let agility = agility as i32;
-        let base = CONFIG.base_turn_time as i32;
+        let base = config.base_turn_time as i32;
 
         if agility > 9 {
-            (agility - 10) * (base as i32 / 100)
+            (agility - 10) * (base / 100)
         } else {
-            -((10 - agility) * (base as i32 / 100))
+            -((10 - agility) * (base / 100))
         }
     }
 }
@@ -27,9 +28,9 @@ impl<'a> System<'a> for QuicknessSystem {
     type SystemData = StatsUpdaterSystemData<'a>;
     fn run(&mut self, mut data: Self::SystemData) {
         for (actor, mobile) in (&data.actors, &mut data.mobiles).join() {
-            let modifier = Self::quickness_from_agility(actor.stats.agility);
+            let modifier = Self::quickness_from_agility(actor.stats.agility, &data.config);
             // println!("{}", modifier);
-            mobile.modify_quickness(modifier);
+            mobile.modify_quickness(modifier, &data.config);
         }
     }
 }
@@ -0,0 +1,233 @@
+use crate::backend::{Key, Screen};
+use crate::command::{Command, CommandEvent};
+use crate::components::{Camera, MyTurn, Name, PlayerControl, Position, PrintDebug};
+use crate::config::Config;
+use crate::ecs::{GameState, MessageLog, State, StateHooks};
+use crate::map::{EntityMap, SpatialIndex};
+use crate::replay::Recorder;
+use crate::systems::input::dir_from_key;
+use crate::systems::menu::put_str;
+use crate::systems::movement::Dir;
+use crate::systems::render::RenderViewport;
+use shrev::EventChannel;
+use specs::prelude::*;
+
+const CURSOR_BG: (u8, u8, u8) = (200, 150, 0);
+
+// the tile being looked at while targeting
+#[derive(Default, Debug)]
+pub struct Cursor {
+    pub position: Option<Position>,
+}
+
+// starts the cursor on the player
+pub struct TargetingHooks;
+
+impl StateHooks for TargetingHooks {
+    fn enter(&mut self, world: &mut World) {
+        let position = {
+            let players = world.read_storage::<PlayerControl>();
+            let positions = world.read_storage::<Position>();
+            (&players, &positions).join().map(|(_, pos)| *pos).next()
+        };
+        world.insert(Cursor { position });
+    }
+
+    fn exit(&mut self, world: &mut World) {
+        world.insert(Cursor::default());
+    }
+}
+
+#[derive(SystemData)]
+pub struct TargetingInputSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub players: ReadStorage<'a, PlayerControl>,
+    pub positions: ReadStorage<'a, Position>,
+    pub my_turns: ReadStorage<'a, MyTurn>,
+    pub names: ReadStorage<'a, Name>,
+    pub print_debugs: WriteStorage<'a, PrintDebug>,
+    pub entity_map: ReadExpect<'a, EntityMap>,
+    pub spatial_index: ReadExpect<'a, SpatialIndex>,
+    pub config: ReadExpect<'a, Config>,
+    pub cursor: Write<'a, Cursor>,
+    pub game_state: WriteExpect<'a, GameState>,
+    pub message_log: WriteExpect<'a, MessageLog>,
+    pub key_channel: ReadExpect<'a, EventChannel<Key>>,
+    pub key_reader: WriteExpect<'a, ReaderId<Key>>,
+    pub command_event_channel: Write<'a, EventChannel<CommandEvent>>,
+    pub recorder: Write<'a, Recorder>,
+}
+
+// moves the cursor with the movement keys. enter attacks what's under it, if it's in reach, and
+// 'x' shows or hides it in the inspector
+pub struct TargetingInput;
+
+impl<'a> System<'a> for TargetingInput {
+    type SystemData = TargetingInputSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let keys: Vec<Key> = data
+            .key_channel
+            .read(&mut data.key_reader)
+            .cloned()
+            .collect();
+        for key in keys {
+            let cursor = match data.cursor.position {
+                Some(cursor) => cursor,
+                None => {
+                    data.game_state.pop();
+                    return;
+                }
+            };
+
+            if let Some(dir) = dir_from_key(key) {
+                let (dx, dy) = Dir::dir_to_pos(dir);
+                data.cursor.position = Some(Position::new(
+                    (cursor.x + dx).max(0).min(data.config.map_width - 1),
+                    (cursor.y + dy).max(0).min(data.config.map_height - 1),
+                ));
+                continue;
+            }
+
+            match key {
+                Key::Escape => {
+                    data.game_state.pop();
+                    return;
+                }
+                // spawns and teleports go to the cursor
+                Key::Char('`') => {
+                    data.game_state.push(State::Console);
+                    return;
+                }
+                Key::Char('x') => Self::inspect(&mut data, cursor),
+                Key::Enter | Key::Char('t') if Self::attack(&mut data, cursor) => {
+                    data.game_state.pop();
+                    return;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl TargetingInput {
+    // tags the top thing on the tile with `PrintDebug`, or takes the tag off again
+    fn inspect(data: &mut TargetingInputSystemData, target: Position) {
+        let ent = match data.spatial_index.at(target).last() {
+            Some(ent) => ent,
+            None => {
+                data.message_log
+                    .log(String::from("There's nothing there to inspect."));
+                return;
+            }
+        };
+        let name = data
+            .names
+            .get(ent)
+            .map(|name| name.name.clone())
+            .unwrap_or_else(|| String::from("that"));
+        if data.print_debugs.remove(ent).is_some() {
+            data.message_log
+                .log(format!("Stopped inspecting {}.", name));
+        } else if let Err(err) = data.print_debugs.insert(ent, PrintDebug) {
+            error!(
+                "Failed to insert PrintDebug from TargetingInput system: {}",
+                err
+            )
+        } else {
+            data.message_log.log(format!("Inspecting {}.", name));
+        }
+    }
+
+    // true if the player attacked
+    fn attack(data: &mut TargetingInputSystemData, target: Position) -> bool {
+        let player = (
+            &data.entities,
+            &data.players,
+            &data.positions,
+            &data.my_turns,
+        )
+            .join()
+            .map(|(ent, _, pos, _)| (ent, *pos))
+            .next();
+        let (ent, pos) = match player {
+            Some(player) => player,
+            None => return false,
+        };
+
+        let (dx, dy) = (target.x - pos.x, target.y - pos.y);
+        if dx.abs() > 1 || dy.abs() > 1 {
+            data.message_log.log(String::from("That's out of reach."));
+            return false;
+        }
+        match data.entity_map.actors.retrieve(target.x, target.y) {
+            Ok(Some(actor)) if actor != ent => (),
+            _ => {
+                data.message_log
+                    .log(String::from("There's nothing there to attack."));
+                return false;
+            }
+        }
+
+        let command = Command::Attack(Dir::pos_to_dir((dx, dy)));
+        data.recorder
+            .record(data.game_state.world_time.tick, &command);
+        data.command_event_channel
+            .single_write(CommandEvent::new(command, ent));
+        data.game_state.world_time.increment_player_turn();
+        true
+    }
+}
+
+#[derive(SystemData)]
+pub struct RenderTargetingSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub positions: ReadStorage<'a, Position>,
+    pub cameras: ReadStorage<'a, Camera>,
+    pub names: ReadStorage<'a, Name>,
+    pub cursor: Read<'a, Cursor>,
+    pub screen: WriteExpect<'a, Screen>,
+    pub config: ReadExpect<'a, Config>,
+}
+
+// highlights the cursor and names what's under it along the top of the viewport
+pub struct RenderTargeting;
+
+impl<'a> System<'a> for RenderTargeting {
+    type SystemData = RenderTargetingSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let cursor = match data.cursor.position {
+            Some(cursor) => cursor,
+            None => return,
+        };
+
+        let screen_pos =
+            RenderViewport::screen_position(cursor, &data.positions, &data.cameras, &data.config);
+        let screen = &mut **data.screen;
+        let (width, height) = screen.size();
+        if screen_pos.x >= 0 && screen_pos.x < width && screen_pos.y >= 0 && screen_pos.y < height {
+            // TODO: keep the glyph under the cursor, the renderer can't read it back yet
+            screen.put_char(screen_pos.x, screen_pos.y, 'X', (0, 0, 0), CURSOR_BG);
+        }
+
+        let names: Vec<&str> = (&data.entities, &data.positions, &data.names)
+            .join()
+            .filter(|(_, pos, _)| **pos == cursor)
+            .map(|(_, _, name)| name.name.as_str())
+            .collect();
+        let text = match names.is_empty() {
+            true => String::from("Targeting: nothing"),
+            false => format!("Targeting: {}", names.join(", ")),
+        };
+        put_str(
+            screen,
+            data.config.viewport_x,
+            data.config.viewport_y,
+            data.config.viewport_width,
+            &text,
+            (255, 255, 255),
+            (0, 0, 0),
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use crate::components::flags::ActionResult;
 use crate::components::*;
 use crate::time::Turn;
-use crate::CONFIG;
+use crate::config::Config;
 use specs::prelude::*;
 use crate::ecs::State;
 
@@ -14,6 +14,7 @@ pub struct TurnAllocatorSystemData<'a> {
     game_state: WriteExpect<'a, crate::GameState>,
     turn_queue: WriteExpect<'a, crate::time::TurnQueue>,
     message_log: WriteExpect<'a, crate::MessageLog>,
+    config: ReadExpect<'a, Config>,
 }
 
 pub struct TurnAllocator;
@@ -25,7 +26,7 @@ impl<'a> System<'a> for TurnAllocator {
         {
             let actor_next_turn = Turn {
                 tick: actor.next_turn,
-                entity: entity,
+                entity,
             };
             // println!("{}, {}", name.name, actor_next_turn.tick);
             data.turn_queue.push(actor_next_turn);
@@ -43,17 +44,20 @@ impl<'a> System<'a> for TurnAllocator {
             let turn = data.turn_queue.pop().unwrap();
             assert_eq!(next_turn, turn.tick);
             data.game_state.world_time.tick = turn.tick;
-            data.game_state.world_time.determine_world_turn();
+            data
+                .game_state
+                .world_time
+                .determine_world_turn(data.config.base_turn_time);
             if let Err(err) = data.my_turns.insert(turn.entity, MyTurn {}) {
                 error!("Failed to insert turn: {}", err)
             }
 
-            if let Some(_) = data.players.get(turn.entity) {
-                if data.game_state.current() == State::TurnProcess {
-                    data.game_state.transition(State::PlayerTurn);
-                    if CONFIG.log_turn_start {
-                        data.message_log.log(String::from("[TURN START]"));
-                    }
+            if data.players.get(turn.entity).is_some()
+                && data.game_state.current() == State::TurnProcess
+            {
+                data.game_state.transition(State::PlayerTurn);
+                if data.config.log_turn_start {
+                    data.message_log.log(String::from("[TURN START]"));
                 }
             }
             // println!("turn queue length: {:?}", data.turn_queue.len());
@@ -67,7 +71,6 @@ pub struct EndTurnSystemData<'a> {
     actors: WriteStorage<'a, Actor>,
     action_results: WriteStorage<'a, ActionResult>,
     game_state: WriteExpect<'a, crate::GameState>,
-    players: ReadStorage<'a, PlayerControl>,
 }
 
 pub struct EndTurn;
@@ -77,10 +80,6 @@ impl<'a> System<'a> for EndTurn {
         for (ent, actor) in (&data.entities, &mut data.actors).join() {
             if let Some(result) = data.action_results.get_mut(ent) {
                 actor.set_next_turn_from_cost(data.game_state.world_time.tick, result.cost);
-                if let Some(_) = data.players.get(ent) {
-                    // println!("{}", data.game_state.world_time.tick);
-                    // println!("{:?} next turn set to {:?} from cost {:?}", ent, actor.next_turn, result.cost);
-                }
                 data.action_results.remove(ent);
             }
         }
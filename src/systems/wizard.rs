@@ -0,0 +1,568 @@
+use crate::backend::{Color, Key, Screen};
+use crate::command::{Command, CommandEvent};
+use crate::components::flags::ActionResult;
+use crate::components::{Actor, Invulnerable, MyTurn, PlayerControl, Position};
+use crate::config::Config;
+use crate::dungeon::Dungeon;
+use crate::ecs::{GameState, MessageLog, State, StateHooks};
+use crate::entity_factory::{BlueprintIndex, EntityLoadQueue};
+use crate::events;
+use crate::map::{EntityMap, View};
+use crate::replay::Recorder;
+use crate::rng::{GameRng, Stream};
+use crate::systems::menu::put_str;
+use crate::systems::render::{seen_tile, SeenTiles};
+use crate::systems::targeting::Cursor;
+use crate::terrain::{Terrain, TerrainKinds};
+use serde::{Deserialize, Serialize};
+use shrev::{EventChannel, ReaderId};
+use specs::prelude::*;
+use std::mem;
+
+// the wizard console, for poking at the game while testing. '`' opens it on the player's turn,
+// or while targeting to spawn and teleport at the cursor instead of next to the player. what's
+// typed into it becomes a `Command::Wizard` the player gives like any other command, so it's
+// recorded and a replay does the same thing at the same tick
+
+const CONSOLE_FG: Color = (200, 200, 200);
+const CONSOLE_BG: Color = (20, 20, 35);
+
+const HELP: &[&str] = &[
+    "spawn <blueprint> [x y]",
+    "teleport [x y]",
+    "reveal",
+    "invulnerable",
+    "debug_vision",
+    "stat <strength|agility|intelligence> <value>",
+    "skip <ticks>",
+    "regenerate",
+];
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum WizardCommand {
+    // a blueprint or prefab, built through the `EntityLoadQueue`. terrain goes into the grid
+    Spawn(String, Position),
+    Teleport(Position),
+    // the whole level counts as seen
+    RevealMap,
+    ToggleInvulnerable,
+    ToggleDebugVision,
+    SetStat(Stat, u32),
+    // ends the player's turn, their next one is this many ticks away
+    Skip(u32),
+    // throws the level away and makes a new one at the same depth
+    Regenerate,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Stat {
+    Strength,
+    Agility,
+    Intelligence,
+}
+
+// what's been typed so far
+#[derive(Default, Debug)]
+pub struct Console {
+    pub text: String,
+}
+
+// starts the console empty
+pub struct ConsoleHooks;
+
+impl StateHooks for ConsoleHooks {
+    fn enter(&mut self, world: &mut World) {
+        world.insert(Console::default());
+    }
+
+    fn exit(&mut self, world: &mut World) {
+        world.insert(Console::default());
+    }
+}
+
+#[derive(SystemData)]
+pub struct ConsoleInputSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub players: ReadStorage<'a, PlayerControl>,
+    pub positions: ReadStorage<'a, Position>,
+    pub my_turns: ReadStorage<'a, MyTurn>,
+    pub console: Write<'a, Console>,
+    pub cursor: Read<'a, Cursor>,
+    pub blueprint_index: ReadExpect<'a, BlueprintIndex>,
+    pub entity_map: ReadExpect<'a, EntityMap>,
+    pub view: ReadExpect<'a, View>,
+    pub config: ReadExpect<'a, Config>,
+    pub game_state: WriteExpect<'a, GameState>,
+    pub message_log: WriteExpect<'a, MessageLog>,
+    pub key_channel: ReadExpect<'a, EventChannel<Key>>,
+    pub key_reader: WriteExpect<'a, ReaderId<Key>>,
+    pub command_event_channel: Write<'a, EventChannel<CommandEvent>>,
+    pub recorder: Write<'a, Recorder>,
+}
+
+// types into the console. enter gives the command and closes it, escape or '`' closes it
+pub struct ConsoleInput;
+
+impl<'a> System<'a> for ConsoleInput {
+    type SystemData = ConsoleInputSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let keys: Vec<Key> = data
+            .key_channel
+            .read(&mut data.key_reader)
+            .cloned()
+            .collect();
+        for key in keys {
+            match key {
+                Key::Escape | Key::Char('`') => {
+                    data.game_state.pop();
+                    return;
+                }
+                Key::Backspace => {
+                    data.console.text.pop();
+                }
+                Key::Char(c) => data.console.text.push(c),
+                Key::Enter => {
+                    let text = mem::take(&mut data.console.text);
+                    if Self::enter(&mut data, text.trim()) {
+                        data.game_state.pop();
+                        // and back out of targeting, so the command is carried out right away
+                        let stack = data.game_state.stack();
+                        if stack.len() > 2 && stack[stack.len() - 2] == State::Targeting {
+                            data.game_state.pop();
+                        }
+                        return;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl ConsoleInput {
+    // true if a command was given
+    fn enter(data: &mut ConsoleInputSystemData, text: &str) -> bool {
+        if text.is_empty() {
+            return false;
+        }
+        data.message_log.log(format!("> {}", text));
+
+        let player = (
+            &data.entities,
+            &data.players,
+            &data.positions,
+            &data.my_turns,
+        )
+            .join()
+            .map(|(ent, _, pos, _)| (ent, *pos))
+            .next();
+        let (ent, pos) = match player {
+            Some(player) => player,
+            None => {
+                data.message_log.log(String::from("It isn't your turn."));
+                return false;
+            }
+        };
+
+        let command = match Self::parse(data, text, pos) {
+            Ok(Some(command)) => Command::Wizard(command),
+            Ok(None) => return false,
+            Err(err) => {
+                data.message_log.log(err);
+                return false;
+            }
+        };
+        data.recorder
+            .record(data.game_state.world_time.tick, &command);
+        data.command_event_channel
+            .single_write(CommandEvent::new(command, ent));
+        true
+    }
+
+    // `None` for the commands the console answers itself
+    fn parse(
+        data: &mut ConsoleInputSystemData,
+        text: &str,
+        player_pos: Position,
+    ) -> Result<Option<WizardCommand>, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let command = match words.as_slice() {
+            ["help"] | ["?"] => {
+                for line in HELP.iter().rev() {
+                    data.message_log.log(format!("  {}", line));
+                }
+                return Ok(None);
+            }
+            ["spawn", name, at @ ..] => {
+                if data.blueprint_index.get(name).is_none() {
+                    return Err(format!("There's no blueprint called {}.", name));
+                }
+                let pos = match Self::position(data, at)? {
+                    Some(pos) => pos,
+                    None => data
+                        .cursor
+                        .position
+                        .unwrap_or_else(|| Self::beside(data, player_pos)),
+                };
+                WizardCommand::Spawn(name.to_string(), pos)
+            }
+            ["teleport", at @ ..] | ["tp", at @ ..] => {
+                let pos = match Self::position(data, at)? {
+                    Some(pos) => pos,
+                    None => data.cursor.position.ok_or_else(|| {
+                        String::from("Teleport where? Give x y, or target a spot.")
+                    })?,
+                };
+                match data.entity_map.actors.retrieve(pos.x, pos.y) {
+                    Ok(Some(_)) if pos != player_pos => {
+                        return Err(String::from("Something is already there."))
+                    }
+                    _ => (),
+                }
+                WizardCommand::Teleport(pos)
+            }
+            ["reveal"] => WizardCommand::RevealMap,
+            ["invulnerable"] | ["god"] => WizardCommand::ToggleInvulnerable,
+            ["debug_vision"] => WizardCommand::ToggleDebugVision,
+            ["stat", stat, value] => {
+                let stat = match *stat {
+                    "strength" | "str" => Stat::Strength,
+                    "agility" | "agi" => Stat::Agility,
+                    "intelligence" | "int" => Stat::Intelligence,
+                    _ => return Err(format!("There's no stat called {}.", stat)),
+                };
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("`{}` isn't a number.", value))?;
+                WizardCommand::SetStat(stat, value)
+            }
+            ["skip", ticks] => {
+                let ticks = ticks
+                    .parse()
+                    .map_err(|_| format!("`{}` isn't a number of ticks.", ticks))?;
+                WizardCommand::Skip(ticks)
+            }
+            ["regenerate"] => WizardCommand::Regenerate,
+            _ => return Err(format!("Unknown command `{}`, try help.", text)),
+        };
+        Ok(Some(command))
+    }
+
+    fn position(data: &ConsoleInputSystemData, at: &[&str]) -> Result<Option<Position>, String> {
+        let (x, y) = match at {
+            [] => return Ok(None),
+            [x, y] => (x, y),
+            _ => return Err(String::from("A position is x y.")),
+        };
+        let pos = match (x.parse(), y.parse()) {
+            (Ok(x), Ok(y)) => Position::new(x, y),
+            _ => return Err(format!("`{} {}` isn't a position.", x, y)),
+        };
+        let config = &data.config;
+        if pos.x < 0 || pos.y < 0 || pos.x >= config.map_width || pos.y >= config.map_height {
+            return Err(format!("{} {} is off the map.", pos.x, pos.y));
+        }
+        Ok(Some(pos))
+    }
+
+    // a free tile next to the player, or where they stand if there isn't one
+    fn beside(data: &ConsoleInputSystemData, pos: Position) -> Position {
+        let fov_map = data.view.map.lock().unwrap();
+        let (width, height) = fov_map.size();
+        for (dx, dy) in &[
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (-1, 1),
+            (1, -1),
+            (-1, -1),
+        ] {
+            let (x, y) = (pos.x + dx, pos.y + dy);
+            if x < 0 || y < 0 || x >= width || y >= height {
+                continue;
+            }
+            if fov_map.is_walkable(x, y) {
+                if let Ok(None) = data.entity_map.actors.retrieve(x, y) {
+                    return Position::new(x, y);
+                }
+            }
+        }
+        pos
+    }
+}
+
+#[derive(SystemData)]
+pub struct RenderConsoleSystemData<'a> {
+    pub console: Read<'a, Console>,
+    pub screen: WriteExpect<'a, Screen>,
+    pub config: ReadExpect<'a, Config>,
+}
+
+// the line being typed, along the bottom of the viewport
+pub struct RenderConsole;
+
+impl<'a> System<'a> for RenderConsole {
+    type SystemData = RenderConsoleSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let config = &data.config;
+        let (x, y) = (
+            config.viewport_x,
+            config.viewport_y + config.viewport_height - 1,
+        );
+        let width = config.viewport_width;
+        let screen = &mut **data.screen;
+        for dx in 0..width {
+            screen.put_char(x + dx, y, ' ', CONSOLE_FG, CONSOLE_BG);
+        }
+        // the end of the line if it's too long to fit
+        let line = format!("> {}_", data.console.text);
+        let skip = line.chars().count().saturating_sub(width.max(0) as usize);
+        let line: String = line.chars().skip(skip).collect();
+        put_str(screen, x, y, width, &line, CONSOLE_FG, CONSOLE_BG);
+    }
+}
+
+#[derive(SystemData)]
+pub struct WizardSystemData<'a> {
+    pub entities: Entities<'a>,
+    pub players: ReadStorage<'a, PlayerControl>,
+    pub positions: WriteStorage<'a, Position>,
+    pub actors: WriteStorage<'a, Actor>,
+    pub invulnerables: WriteStorage<'a, Invulnerable>,
+    pub my_turns: WriteStorage<'a, MyTurn>,
+    pub action_results: WriteStorage<'a, ActionResult>,
+    pub entity_load_queue: WriteExpect<'a, EntityLoadQueue>,
+    pub terrain: WriteExpect<'a, Terrain>,
+    pub terrain_kinds: ReadExpect<'a, TerrainKinds>,
+    pub rng: WriteExpect<'a, GameRng>,
+    pub seen_tiles: WriteExpect<'a, SeenTiles>,
+    pub view: ReadExpect<'a, View>,
+    pub config: WriteExpect<'a, Config>,
+    pub dungeon: WriteExpect<'a, Dungeon>,
+    pub game_state: WriteExpect<'a, GameState>,
+    pub message_log: WriteExpect<'a, MessageLog>,
+
+    // read event channels
+    pub command_events: Read<'a, EventChannel<CommandEvent>>,
+}
+
+// carries out the wizard commands, the rest are left to `ActionHandler`
+#[derive(Default)]
+pub struct Wizard {
+    command_reader: Option<ReaderId<CommandEvent>>,
+}
+
+impl Wizard {
+    pub fn new() -> Self {
+        Wizard::default()
+    }
+
+    fn run_command(data: &mut WizardSystemData, ent: Entity, command: WizardCommand) {
+        let message = match command {
+            WizardCommand::Spawn(name, pos) => {
+                match data.terrain_kinds.get(&name) {
+                    Some(kind) => {
+                        let rng = data.rng.stream(Stream::Cosmetic);
+                        data.terrain.place(pos.x, pos.y, kind, rng);
+                    }
+                    None => data.entity_load_queue.push((name.clone(), Some(pos))),
+                }
+                format!("Spawned {} at {} {}.", name, pos.x, pos.y)
+            }
+            WizardCommand::Teleport(pos) => {
+                if let Some(player_pos) = data.positions.get_mut(ent) {
+                    *player_pos = pos;
+                }
+                format!("Teleported to {} {}.", pos.x, pos.y)
+            }
+            WizardCommand::RevealMap => {
+                let fov_map = data.view.map.lock().unwrap();
+                let (width, height) = fov_map.size();
+                for x in 0..width {
+                    for y in 0..height {
+                        if let Ok(None) = data.seen_tiles.tiles.retrieve(x, y) {
+                            let tile = seen_tile(Position::new(x, y), fov_map.is_walkable(x, y));
+                            data.seen_tiles.tiles.set_point(x, y, Some(tile));
+                        }
+                    }
+                }
+                String::from("The level is revealed.")
+            }
+            WizardCommand::ToggleInvulnerable => match data.invulnerables.remove(ent) {
+                Some(_) => String::from("You are no longer invulnerable."),
+                None => {
+                    data.invulnerables.insert(ent, Invulnerable).ok();
+                    String::from("You are invulnerable.")
+                }
+            },
+            WizardCommand::ToggleDebugVision => {
+                data.config.debug_vision = !data.config.debug_vision;
+                format!("Debug vision is {}.", on_off(data.config.debug_vision))
+            }
+            WizardCommand::SetStat(stat, value) => {
+                if let Some(actor) = data.actors.get_mut(ent) {
+                    match stat {
+                        Stat::Strength => actor.stats.strength = value,
+                        Stat::Agility => actor.stats.agility = value,
+                        Stat::Intelligence => actor.stats.intelligence = value,
+                    }
+                }
+                format!("{:?} is now {}.", stat, value)
+            }
+            WizardCommand::Skip(ticks) => {
+                data.action_results
+                    .insert(ent, ActionResult::from(ticks))
+                    .ok();
+                data.my_turns.remove(ent);
+                data.game_state.transition(State::TurnProcess);
+                format!("Skipped {} ticks.", ticks)
+            }
+            WizardCommand::Regenerate => {
+                data.dungeon.regenerate = true;
+                data.my_turns.remove(ent);
+                data.game_state.transition(State::ChangeLevel);
+                String::from("The level is regenerated.")
+            }
+        };
+        data.message_log.log(message);
+    }
+}
+
+impl<'a> System<'a> for Wizard {
+    type SystemData = WizardSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let commands: Vec<(Entity, WizardCommand)> = data
+            .command_events
+            .read(self.command_reader.as_mut().unwrap())
+            .filter_map(|event| match &event.command {
+                Command::Wizard(command) => Some((event.entity, command.clone())),
+                _ => None,
+            })
+            .collect();
+
+        for (ent, command) in commands {
+            if data.players.get(ent).is_some() {
+                Self::run_command(&mut data, ent, command);
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.command_reader = Some(events::reader(world));
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::headless::Headless;
+    use crate::ecs;
+
+    // what the console makes of each line, on a fresh world with nothing on the map
+    fn parse(lines: &[&str]) -> Vec<Result<Option<WizardCommand>, String>> {
+        let config = Config::default();
+        let headless = Headless::new(config.screen_width, config.screen_height);
+        let ecs = ecs::world_setup_with(headless.screen(), headless.keyboard(), 1, config).unwrap();
+        let mut data = ecs.world().system_data::<ConsoleInputSystemData>();
+        let player_pos = Position::new(1, 1);
+        lines
+            .iter()
+            .map(|line| ConsoleInput::parse(&mut data, line, player_pos))
+            .collect()
+    }
+
+    fn errors(lines: &[&str]) -> Vec<String> {
+        parse(lines)
+            .into_iter()
+            .zip(lines)
+            .map(|(result, line)| match result {
+                Err(err) => err,
+                Ok(command) => panic!("`{}` gave {:?}", line, command),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_commands() {
+        let parsed = parse(&["stat agi 12", "skip 500", "tp 2 3", "regenerate", "help"]);
+        assert_eq!(
+            parsed,
+            vec![
+                Ok(Some(WizardCommand::SetStat(Stat::Agility, 12))),
+                Ok(Some(WizardCommand::Skip(500))),
+                Ok(Some(WizardCommand::Teleport(Position::new(2, 3)))),
+                Ok(Some(WizardCommand::Regenerate)),
+                Ok(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_positions() {
+        let config = Config::default();
+        let (width, height) = (config.map_width, config.map_height);
+        let off_right = format!("teleport {} 0", width);
+        let off_bottom = format!("teleport 0 {}", height);
+        let errors = errors(&[
+            "teleport 3",
+            "teleport 1 2 3",
+            "teleport a b",
+            "teleport -1 0",
+            &off_right,
+            &off_bottom,
+            // no cursor to fall back on
+            "teleport",
+        ]);
+        assert_eq!(
+            errors,
+            vec![
+                String::from("A position is x y."),
+                String::from("A position is x y."),
+                String::from("`a b` isn't a position."),
+                String::from("-1 0 is off the map."),
+                format!("{} 0 is off the map.", width),
+                format!("0 {} is off the map.", height),
+                String::from("Teleport where? Give x y, or target a spot."),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_numbers_and_stats() {
+        let errors = errors(&[
+            "stat str many",
+            "stat int -4",
+            "stat luck 3",
+            "skip soon",
+            "skip -100",
+            "spawn nothing.at.all",
+            "frobnicate",
+        ]);
+        assert_eq!(
+            errors,
+            vec![
+                "`many` isn't a number.",
+                "`-4` isn't a number.",
+                "There's no stat called luck.",
+                "`soon` isn't a number of ticks.",
+                "`-100` isn't a number of ticks.",
+                "There's no blueprint called nothing.at.all.",
+                "Unknown command `frobnicate`, try help.",
+            ]
+        );
+    }
+}
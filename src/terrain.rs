@@ -0,0 +1,351 @@
+use crate::components::{Elevation, Material, Position, RandomRenderable, Renderable};
+use crate::entity_factory::{EntityBlueprint, EntityFactory};
+use crate::rng::{GameRng, Stream};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::collections::HashMap;
+use std::mem;
+
+// the floor and walls of a level. a level is mostly terrain, and as entities every tile of it
+// would be walked by every `Position` join, so it's kept in a grid instead. each cell points at
+// one of the grid's `TerrainKind`s and keeps how it was rolled to look.
+//
+// terrain is still written as blueprints, any blueprint tagged "terrain". its `renderable` or
+// `random_renderable`, `elevation`, `blocks_movement`, `blocks_sight` and `material` are all
+// that's used. things on a level that change, like stairs, doors and traps, stay entities.
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TerrainKind {
+    // the blueprint it was made from
+    pub name: String,
+    pub looks: RandomRenderable,
+    pub elevation: Elevation,
+    pub blocks_movement: bool,
+    pub blocks_sight: bool,
+    pub material: Material,
+}
+
+impl TerrainKind {
+    pub fn from_blueprint(name: &str, blueprint: &EntityBlueprint) -> Result<Self, String> {
+        let looks = match (
+            blueprint.get::<RandomRenderable>("random_renderable"),
+            blueprint.get::<Renderable>("renderable"),
+        ) {
+            (Some(looks), _) => looks,
+            (None, Some(renderable)) => RandomRenderable {
+                glyphs: renderable.glyph.to_string(),
+                fg_colors: vec![renderable.fg_color],
+                bg_colors: renderable.bg_color.map(|color| vec![color]),
+            },
+            (None, None) => return Err(String::from("terrain needs a renderable")),
+        };
+
+        Ok(TerrainKind {
+            name: String::from(name),
+            looks,
+            elevation: blueprint.get("elevation").unwrap_or(Elevation::Floor),
+            blocks_movement: blueprint.has("blocks_movement"),
+            blocks_sight: blueprint.has("blocks_sight"),
+            material: blueprint.get("material").unwrap_or_default(),
+        })
+    }
+
+    fn roll<R: Rng + ?Sized>(&self, rng: &mut R) -> Renderable {
+        let looks = &self.looks;
+        Renderable {
+            glyph: looks.glyphs.chars().choose(rng).unwrap_or(' '),
+            fg_color: looks
+                .fg_colors
+                .choose(rng)
+                .cloned()
+                .unwrap_or((255, 255, 255)),
+            bg_color: looks
+                .bg_colors
+                .as_ref()
+                .and_then(|colors| colors.choose(rng).cloned()),
+        }
+    }
+}
+
+// every kind of terrain in the blueprints, by blueprint name. a resource, replaced when the
+// blueprints are reloaded
+#[derive(Clone, Default)]
+pub struct TerrainKinds {
+    kinds: HashMap<String, TerrainKind>,
+}
+
+impl TerrainKinds {
+    pub fn from_factory(factory: &EntityFactory) -> Self {
+        let mut kinds = HashMap::new();
+        for name in factory.names() {
+            let blueprint = match factory.blueprint(&name) {
+                Some(blueprint) if blueprint.meta.has_tag("terrain") => blueprint,
+                _ => continue,
+            };
+            match TerrainKind::from_blueprint(&name, blueprint) {
+                Ok(kind) => {
+                    kinds.insert(name, kind);
+                }
+                Err(err) => error!("could not make terrain from {}: {}", name, err),
+            }
+        }
+        TerrainKinds { kinds }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TerrainKind> {
+        self.kinds.get(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct TerrainCell {
+    // into the grid's `kinds`
+    pub kind: u16,
+    pub glyph: char,
+    pub fg_color: (u8, u8, u8),
+    pub bg_color: Option<(u8, u8, u8)>,
+}
+
+impl TerrainCell {
+    pub fn renderable(&self) -> Renderable {
+        Renderable::new(self.glyph, self.fg_color, self.bg_color)
+    }
+}
+
+// the terrain of the level the player is on, a resource. saved with the world, and with every
+// level the player has left
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Terrain {
+    width: i32,
+    height: i32,
+    // every kind placed so far, so the grid doesn't depend on the blueprints it was made from
+    kinds: Vec<TerrainKind>,
+    cells: Vec<Option<TerrainCell>>,
+    // bumped on every change, see `revision`
+    #[serde(skip)]
+    revision: u64,
+}
+
+impl Terrain {
+    pub fn new(width: i32, height: i32) -> Self {
+        Terrain {
+            width,
+            height,
+            kinds: Vec::new(),
+            cells: vec![None; (width * height).max(0) as usize],
+            revision: 0,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // changes whenever any cell does, so whatever is worked out from the terrain knows when to
+    // work it out again
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn idx(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y * self.width + x) as usize)
+    }
+
+    // false if (x, y) is off the grid
+    pub fn place<R: Rng + ?Sized>(
+        &mut self,
+        x: i32,
+        y: i32,
+        kind: &TerrainKind,
+        rng: &mut R,
+    ) -> bool {
+        let idx = match self.idx(x, y) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let kind_idx = match self.kinds.iter().position(|k| k.name == kind.name) {
+            Some(kind_idx) => kind_idx,
+            None => {
+                self.kinds.push(kind.clone());
+                self.kinds.len() - 1
+            }
+        };
+        let looks = kind.roll(rng);
+        self.cells[idx] = Some(TerrainCell {
+            kind: kind_idx as u16,
+            glyph: looks.glyph,
+            fg_color: looks.fg_color,
+            bg_color: looks.bg_color,
+        });
+        self.revision += 1;
+        true
+    }
+
+    pub fn remove(&mut self, x: i32, y: i32) {
+        if let Some(idx) = self.idx(x, y) {
+            self.cells[idx] = None;
+            self.revision += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let (width, height) = (self.width, self.height);
+        self.replace(Terrain::new(width, height));
+    }
+
+    // swaps in another level's terrain, like a loaded one
+    pub fn replace(&mut self, terrain: Terrain) {
+        let revision = self.revision + 1;
+        *self = terrain;
+        self.revision = revision;
+    }
+
+    // takes the terrain out, leaving the grid empty
+    pub fn take(&mut self) -> Terrain {
+        let empty = Terrain::new(self.width, self.height);
+        let taken = mem::replace(self, empty);
+        self.revision = taken.revision + 1;
+        taken
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<(&TerrainKind, TerrainCell)> {
+        let cell = (*self.cells.get(self.idx(x, y)?)?)?;
+        Some((&self.kinds[cell.kind as usize], cell))
+    }
+
+    pub fn blocks_movement(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_some_and(|(kind, _)| kind.blocks_movement)
+    }
+
+    pub fn blocks_sight(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_some_and(|(kind, _)| kind.blocks_sight)
+    }
+
+    // every cell with terrain on it
+    pub fn cells(&self) -> impl Iterator<Item = (Position, &TerrainKind, TerrainCell)> + '_ {
+        let width = self.width;
+        self.cells.iter().enumerate().filter_map(move |(i, cell)| {
+            let cell = (*cell)?;
+            let pos = Position::new(i as i32 % width, i as i32 / width);
+            Some((pos, &self.kinds[cell.kind as usize], cell))
+        })
+    }
+
+    // picks up changes to the blueprints the terrain was made from. cells keep how they look,
+    // only how they block and what they're made of changes. returns how many kinds were updated
+    pub fn reapply(&mut self, kinds: &TerrainKinds) -> usize {
+        let mut changed = 0;
+        for kind in self.kinds.iter_mut() {
+            if let Some(new) = kinds.get(&kind.name) {
+                kind.looks = new.looks.clone();
+                kind.elevation = new.elevation;
+                kind.blocks_movement = new.blocks_movement;
+                kind.blocks_sight = new.blocks_sight;
+                kind.material = new.material;
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            self.revision += 1;
+        }
+        changed
+    }
+}
+
+// puts the terrain blueprint `name` at `pos`, rolling how it looks. false if `name` isn't
+// terrain or `pos` is off the map
+pub fn place(world: &mut World, name: &str, pos: Position) -> bool {
+    let kind = match world.read_resource::<TerrainKinds>().get(name) {
+        Some(kind) => kind.clone(),
+        None => return false,
+    };
+    let mut rng = world.write_resource::<GameRng>();
+    let mut terrain = world.write_resource::<Terrain>();
+    terrain.place(pos.x, pos.y, &kind, rng.stream(Stream::Cosmetic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    fn kind(name: &str, blocks: bool) -> TerrainKind {
+        TerrainKind {
+            name: String::from(name),
+            looks: RandomRenderable {
+                glyphs: String::from("."),
+                fg_colors: vec![(255, 255, 255)],
+                bg_colors: None,
+            },
+            elevation: Elevation::Floor,
+            blocks_movement: blocks,
+            blocks_sight: blocks,
+            material: Material::Stone,
+        }
+    }
+
+    #[test]
+    fn places_only_on_the_grid() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut terrain = Terrain::new(3, 2);
+        let floor = kind("floor", false);
+
+        for &(x, y) in &[(-1, 0), (0, -1), (3, 0), (0, 2)] {
+            assert!(
+                !terrain.place(x, y, &floor, &mut rng),
+                "placed at {:?}",
+                (x, y)
+            );
+        }
+        assert_eq!(terrain.revision(), 0);
+        assert!(terrain.place(2, 1, &floor, &mut rng));
+        assert_eq!(terrain.cells().count(), 1);
+    }
+
+    #[test]
+    fn places_the_same_kind_once() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut terrain = Terrain::new(3, 3);
+        let (floor, wall) = (kind("floor", false), kind("wall", true));
+
+        terrain.place(0, 0, &floor, &mut rng);
+        terrain.place(1, 0, &wall, &mut rng);
+        terrain.place(2, 0, &floor, &mut rng);
+        assert_eq!(terrain.kinds.len(), 2);
+        assert_eq!(
+            terrain.get(0, 0).unwrap().1.kind,
+            terrain.get(2, 0).unwrap().1.kind
+        );
+        assert!(terrain.blocks_movement(1, 0));
+        assert!(!terrain.blocks_sight(2, 0));
+    }
+
+    #[test]
+    fn reapplying_bumps_the_revision() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut terrain = Terrain::new(2, 2);
+        terrain.place(0, 0, &kind("wall", false), &mut rng);
+        let revision = terrain.revision();
+
+        // nothing placed is in there
+        let mut kinds = TerrainKinds::default();
+        kinds
+            .kinds
+            .insert(String::from("rubble"), kind("rubble", true));
+        assert_eq!(terrain.reapply(&kinds), 0);
+        assert_eq!(terrain.revision(), revision);
+
+        kinds.kinds.insert(String::from("wall"), kind("wall", true));
+        assert_eq!(terrain.reapply(&kinds), 1);
+        assert!(terrain.revision() > revision);
+        assert!(terrain.blocks_movement(0, 0));
+    }
+}
@@ -1,10 +1,11 @@
-use crate::CONFIG;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 
 pub type TurnQueue = BinaryHeap<Turn>;
 
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct WorldTime {
     pub tick: u64,
     pub world_turns: u32,
@@ -44,8 +45,8 @@ impl WorldTime {
         }
     }
 
-    pub fn determine_world_turn(&mut self) {
-        self.world_turns = (self.tick / CONFIG.base_turn_time as u64) as u32
+    pub fn determine_world_turn(&mut self, base_turn_time: u32) {
+        self.world_turns = (self.tick / base_turn_time as u64) as u32
     }
 
     pub fn increment_player_turn(&mut self) {
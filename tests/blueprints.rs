@@ -0,0 +1,169 @@
+extern crate rust_rl;
+
+use rust_rl::bodyparts::{ArmorTag, BodyPartTag};
+use rust_rl::components::{Corporeal, Name, Position, Renderable};
+use rust_rl::entity_factory::{BlueprintError, EntityFactory};
+use rust_rl::rng::GameRng;
+use specs::prelude::*;
+use std::fs;
+use std::process::{self, Command};
+
+#[test]
+fn loads_the_game_blueprints() {
+    let factory = EntityFactory::new("blueprints").unwrap();
+    assert!(!factory.is_empty());
+}
+
+fn load_errors(path: &str) -> Vec<BlueprintError> {
+    match EntityFactory::new(path) {
+        Ok(_) => panic!("the broken blueprints in {} loaded", path),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn reports_missing_parents() {
+    match load_errors("tests/fixtures/missing_parent").as_slice() {
+        [BlueprintError::MissingParent { path, parent }] => {
+            assert!(path.ends_with("orphan.ron"));
+            assert_eq!(parent, "nowhere");
+        }
+        errors => panic!("expected one missing parent, got {:?}", errors),
+    }
+}
+
+#[test]
+fn reports_inheritance_cycles() {
+    match load_errors("tests/fixtures/cycle").as_slice() {
+        [BlueprintError::Cycle { chain }] => assert_eq!(chain, &["chicken", "egg", "chicken"]),
+        errors => panic!("expected one cycle, got {:?}", errors),
+    }
+}
+
+#[test]
+fn reports_unknown_component_keys() {
+    match load_errors("tests/fixtures/unknown_component").as_slice() {
+        [BlueprintError::UnknownComponent {
+            path,
+            key,
+            position,
+        }] => {
+            assert!(path.ends_with("typo.ron"));
+            assert_eq!(key, "helth");
+            assert_eq!(*position, Some((5, 5)));
+        }
+        errors => panic!("expected one unknown component, got {:?}", errors),
+    }
+}
+
+#[test]
+fn validate_blueprints_exit_code() {
+    let validate = |path: &str| {
+        Command::new(env!("CARGO_BIN_EXE_validate-blueprints"))
+            .arg(path)
+            .output()
+            .unwrap()
+            .status
+    };
+    assert!(validate("blueprints").success());
+    for broken in &["missing_parent", "cycle", "unknown_component"] {
+        let status = validate(&format!("tests/fixtures/{}", broken));
+        assert_eq!(status.code(), Some(1), "{} passed validation", broken);
+    }
+}
+
+// `\u` followed by something other than hex digits is an error, not a panic
+#[test]
+fn reports_bad_unicode_escapes() {
+    match load_errors("tests/fixtures/bad_unicode_escape").as_slice() {
+        [BlueprintError::Parse {
+            position, message, ..
+        }] => {
+            assert_eq!(message, "invalid unicode escape");
+            assert_eq!(*position, Some((2, 26)));
+        }
+        errors => panic!("expected one parse error, got {:?}", errors),
+    }
+}
+
+// tags are added to the parents' tags, not replacing them
+#[test]
+fn queries_inherited_tags() {
+    let factory = EntityFactory::new("blueprints").unwrap();
+    let undead_monsters = factory.query().tagged("monster").tagged("undead").names();
+    assert_eq!(undead_monsters, ["creatures.zombie"]);
+}
+
+#[test]
+fn loads_body_plans() {
+    let factory = EntityFactory::new("blueprints").unwrap();
+    let plans = factory.body_plans();
+    let names = [
+        "bodies.humanoid",
+        "bodies.insectoid",
+        "bodies.quadruped",
+        "bodies.serpent",
+    ];
+    assert_eq!(plans.names(), names);
+    assert!(plans.build("bodies.blob").is_err());
+
+    let serpent = plans.build("bodies.serpent").unwrap();
+    let core = serpent.core();
+    assert_eq!(core.tags, [BodyPartTag::Core, BodyPartTag::Mobility]);
+    let parts: Vec<&String> = core.children.iter().map(|part| &part.name).collect();
+    assert_eq!(parts, ["Head", "Tail"]);
+    let head = &core.children[0];
+    assert_eq!(head.armor_tags, [ArmorTag::Head, ArmorTag::Jewelry(1)]);
+}
+
+const CRITTER: &str = r#"(
+    name: Some((name: "Critter")),
+    corporeal: Some((max_hp: 5, hp: 5, base_damage: 1)),
+    renderable: Some((glyph: 'c', fg_color: (255, 255, 255), bg_color: None)),
+    position: Some((x: 1, y: 1)),
+)"#;
+
+const CHANGED_CRITTER: &str = r#"(
+    name: Some((name: "Critter")),
+    corporeal: Some((max_hp: 9, hp: 9, base_damage: 1)),
+    position: Some((x: 7, y: 7)),
+)"#;
+
+// only what changed gets put back onto live entities, and never their position
+#[test]
+fn reload_reapplies_the_diff() {
+    let dir = std::env::temp_dir().join(format!("rust_rl_reload_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("critter.ron");
+    fs::write(&file, CRITTER).unwrap();
+
+    let mut factory = EntityFactory::new(dir.to_str().unwrap()).unwrap();
+    let mut world = World::new();
+    factory.setup(&mut world);
+    world.insert(GameRng::new(1));
+    let placed = Some(Position::new(3, 3));
+    let critter = factory
+        .build(String::from("critter"), &mut world, placed)
+        .unwrap();
+
+    fs::write(&file, CHANGED_CRITTER).unwrap();
+    let changes = factory.reload();
+    fs::remove_dir_all(&dir).unwrap();
+    let changes = changes.unwrap();
+
+    match changes.as_slice() {
+        [change] => {
+            assert_eq!(change.changed, ["corporeal", "position"]);
+            assert_eq!(change.removed, ["renderable"]);
+        }
+        changes => panic!("expected one changed blueprint, got {:?}", changes),
+    }
+    assert_eq!(factory.reapply(&mut world, &changes), 1);
+
+    let corporeal = world.read_storage::<Corporeal>();
+    assert_eq!(corporeal.get(critter).unwrap().max_hp, 9);
+    assert!(world.read_storage::<Renderable>().get(critter).is_none());
+    assert!(world.read_storage::<Name>().get(critter).is_some());
+    let position = world.read_storage::<Position>();
+    assert_eq!(position.get(critter), Some(&Position::new(3, 3)));
+}
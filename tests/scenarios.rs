@@ -0,0 +1,351 @@
+extern crate rust_rl;
+extern crate shrev;
+extern crate specs;
+
+use rust_rl::backend::Key;
+use rust_rl::components::{Actor, Corporeal, PlayerControl, Position, Renderable};
+use rust_rl::dungeon::Dungeon;
+use rust_rl::ecs::State;
+use rust_rl::events::{self, AttackEvent, DeathEvent, MoveEvent};
+use rust_rl::scenario::{Scenario, ScenarioBuilder};
+use rust_rl::systems::render::SeenTiles;
+use rust_rl::systems::statistics::Statistics;
+use shrev::EventChannel;
+use specs::prelude::*;
+
+// more than enough frames for any one turn to come back around to the player
+const MAX_FRAMES: usize = 200;
+
+fn build(map: &str) -> Scenario {
+    let mut scenario = ScenarioBuilder::new(map).build().unwrap();
+    assert!(scenario.until_player_turn(MAX_FRAMES));
+    scenario
+}
+
+// presses `key` on the player's turn and plays on until their next one. an action that takes
+// no time, like walking into a wall, leaves it the player's turn
+fn act(scenario: &mut Scenario, key: Key) {
+    scenario.press(key);
+    for _ in 0..5 {
+        scenario.step();
+        if scenario.state() != State::PlayerTurn {
+            break;
+        }
+    }
+    assert!(scenario.until_player_turn(MAX_FRAMES));
+}
+
+#[test]
+fn walls_block_movement() {
+    let mut scenario = build(
+        "
+        ####
+        #@.#
+        ####
+        ",
+    );
+    let player = scenario.player();
+
+    act(&mut scenario, Key::Char('h'));
+    act(&mut scenario, Key::Char('k'));
+    assert_eq!(scenario.position(player), Some(Position::new(1, 1)));
+
+    act(&mut scenario, Key::Char('l'));
+    assert_eq!(scenario.position(player), Some(Position::new(2, 1)));
+    act(&mut scenario, Key::Char('l'));
+    assert_eq!(scenario.position(player), Some(Position::new(2, 1)));
+}
+
+#[test]
+fn bumping_into_a_monster_attacks_it() {
+    let mut scenario = build(
+        "
+        #####
+        #@Z.#
+        #####
+        ",
+    );
+    let player = scenario.player();
+    let zombie = scenario.entity('Z');
+    assert_eq!(scenario.hp(zombie), Some(15));
+
+    act(&mut scenario, Key::Char('l'));
+    assert_eq!(scenario.hp(zombie), Some(5));
+    // the player stays put, and so does the zombie it hit
+    assert_eq!(scenario.position(player), Some(Position::new(1, 1)));
+    assert_eq!(scenario.position(zombie), Some(Position::new(2, 1)));
+}
+
+#[test]
+fn monsters_die_when_out_of_hp() {
+    let mut scenario = build(
+        "
+        ####
+        #@M#
+        ####
+        ",
+    );
+    let monster = scenario.entity('M');
+
+    act(&mut scenario, Key::Char('l'));
+    act(&mut scenario, Key::Char('.'));
+    assert!(!scenario.is_alive(monster));
+
+    // and the player can walk where it stood
+    act(&mut scenario, Key::Char('l'));
+    assert_eq!(
+        scenario.position(scenario.player()),
+        Some(Position::new(2, 1))
+    );
+}
+
+#[test]
+fn quicker_actors_get_more_turns() {
+    // the monsters are walled off from the player and each other, so all they do is wait
+    let mut scenario = ScenarioBuilder::new(
+        "
+        #######
+        #@#f#s#
+        #######
+        ",
+    )
+    .legend('f', "creatures.base_monster")
+    .legend('s', "creatures.base_monster")
+    .build()
+    .unwrap();
+    let (fast, slow) = (scenario.entity('f'), scenario.entity('s'));
+    {
+        let mut actors = scenario.world_mut().write_storage::<Actor>();
+        actors.get_mut(fast).unwrap().stats.agility = 50;
+        actors.get_mut(slow).unwrap().stats.agility = 1;
+    }
+
+    assert!(scenario.until_player_turn(MAX_FRAMES));
+    act(&mut scenario, Key::Char('.'));
+    // once they've both acted, the quicker one is up first
+    let order: Vec<Entity> = scenario
+        .turn_order()
+        .into_iter()
+        .map(|(_, ent)| ent)
+        .filter(|ent| *ent == fast || *ent == slow)
+        .collect();
+    assert_eq!(order, vec![fast, slow]);
+
+    let mut turns = [(fast, 0), (slow, 0)];
+    let mut last_turns = [None, None];
+    while scenario.tick() < 20_000 {
+        act(&mut scenario, Key::Char('.'));
+        let order = scenario.turn_order();
+        for (i, (ent, count)) in turns.iter_mut().enumerate() {
+            let next_turn = order.iter().find(|(_, e)| e == ent).map(|(tick, _)| *tick);
+            if next_turn != last_turns[i] {
+                *count += 1;
+                last_turns[i] = next_turn;
+            }
+        }
+    }
+
+    let (fast_turns, slow_turns) = (turns[0].1, turns[1].1);
+    assert!(
+        fast_turns > slow_turns,
+        "fast: {}, slow: {}",
+        fast_turns,
+        slow_turns
+    );
+}
+
+#[test]
+fn attacks_and_deaths_are_logged() {
+    let mut scenario = build(
+        "
+        #####
+        #@Z.#
+        #####
+        ",
+    );
+
+    act(&mut scenario, Key::Char('l'));
+    act(&mut scenario, Key::Char('l'));
+
+    // newest first
+    let mut messages = scenario.messages();
+    messages.reverse();
+    let attacks: Vec<&String> = messages
+        .iter()
+        .filter(|message| message.starts_with("Player"))
+        .collect();
+    assert_eq!(
+        attacks,
+        vec![
+            "Player attempts to attack Zombie!",
+            "Player hits Zombie for 10 damage!!",
+            "Player attempts to attack Zombie!",
+            "Player hits Zombie for 10 damage!!",
+        ]
+    );
+    assert!(messages.contains(&String::from("Zombie is vanquished!!!")));
+}
+
+// everything on the level but the player, and what the player has seen of it
+fn level(scenario: &Scenario) -> (Vec<(i32, i32, char, Option<i32>)>, Vec<(i32, i32, char)>) {
+    let world = scenario.world();
+    let players = world.read_storage::<PlayerControl>();
+    let corporeal = world.read_storage::<Corporeal>();
+    let mut things: Vec<_> = (
+        &world.read_storage::<Position>(),
+        &world.read_storage::<Renderable>(),
+        corporeal.maybe(),
+        !&players,
+    )
+        .join()
+        .map(|(pos, renderable, corporeal, _)| {
+            (pos.x, pos.y, renderable.glyph, corporeal.map(|c| c.hp))
+        })
+        .collect();
+    things.sort();
+
+    let mut seen: Vec<_> = world
+        .read_resource::<SeenTiles>()
+        .tiles
+        .items
+        .iter()
+        .filter_map(|tile| tile.map(|tile| (tile.position.x, tile.position.y, tile.glyph)))
+        .collect();
+    seen.sort();
+    (things, seen)
+}
+
+#[test]
+fn levels_are_kept_while_the_player_is_away() {
+    // the zombie is walled in, so it can't wander off while the player is around
+    let mut scenario = build(
+        "
+        ########
+        #@>.#Z##
+        ########
+        ",
+    );
+    let zombie = scenario.entity('Z');
+    scenario
+        .world_mut()
+        .write_storage::<Corporeal>()
+        .get_mut(zombie)
+        .unwrap()
+        .hp = 7;
+
+    act(&mut scenario, Key::Char('l'));
+    let left = level(&scenario);
+    assert!(left.0.contains(&(5, 1, 'Z', Some(7))));
+    assert!(!left.1.is_empty());
+
+    act(&mut scenario, Key::Char('>'));
+    assert_eq!(scenario.world().read_resource::<Dungeon>().depth, 2);
+    assert_ne!(level(&scenario), left);
+
+    act(&mut scenario, Key::Char('<'));
+    assert_eq!(scenario.world().read_resource::<Dungeon>().depth, 1);
+    assert_eq!(
+        scenario.position(scenario.player()),
+        Some(Position::new(2, 1))
+    );
+    assert_eq!(level(&scenario), left);
+}
+
+#[test]
+fn attacks_deaths_and_steps_are_events() {
+    let mut scenario = build(
+        "
+        ####
+        #@M#
+        ####
+        ",
+    );
+    let player = scenario.player();
+    let monster = scenario.entity('M');
+    let mut attacks = events::reader::<AttackEvent>(scenario.world_mut());
+    let mut deaths = events::reader::<DeathEvent>(scenario.world_mut());
+    let mut moves = events::reader::<MoveEvent>(scenario.world_mut());
+
+    act(&mut scenario, Key::Char('l'));
+    act(&mut scenario, Key::Char('.'));
+    act(&mut scenario, Key::Char('l'));
+
+    let world = scenario.world();
+    // the monster hits back before it dies
+    let attacks: Vec<AttackEvent> = world
+        .read_resource::<EventChannel<AttackEvent>>()
+        .read(&mut attacks)
+        .cloned()
+        .collect();
+    assert_eq!(
+        attacks,
+        vec![
+            AttackEvent::new(player, monster, 10),
+            AttackEvent::new(monster, player, 10),
+        ]
+    );
+    let deaths: Vec<DeathEvent> = world
+        .read_resource::<EventChannel<DeathEvent>>()
+        .read(&mut deaths)
+        .cloned()
+        .collect();
+    assert_eq!(deaths.len(), 1);
+    assert_eq!(deaths[0].entity, monster);
+    assert_eq!(deaths[0].killer, Some(player));
+    let moves: Vec<MoveEvent> = world
+        .read_resource::<EventChannel<MoveEvent>>()
+        .read(&mut moves)
+        .cloned()
+        .collect();
+    assert_eq!(moves, vec![MoveEvent::new(player, 1, 1, 2, 1)]);
+
+    // the statistics are counted from the same events
+    let statistics = world.read_resource::<Statistics>();
+    assert_eq!(
+        (statistics.attacks, statistics.kills, statistics.steps),
+        (1, 1, 1)
+    );
+}
+
+// types `text` into the wizard console and gives it
+fn wizard(scenario: &mut Scenario, text: &str) {
+    scenario.press(Key::Char('`'));
+    for _ in 0..5 {
+        scenario.step();
+        if scenario.state() == State::Console {
+            break;
+        }
+    }
+    assert_eq!(scenario.state(), State::Console);
+    for c in text.chars() {
+        scenario.press(Key::Char(c));
+        scenario.step();
+    }
+    // a frame to give the command and close the console, one for the wizard to carry it out and
+    // one for whatever state that leads to
+    scenario.press(Key::Enter);
+    scenario.step_n(3);
+    assert!(scenario.until_player_turn(MAX_FRAMES));
+}
+
+#[test]
+fn regenerated_levels_are_thrown_away() {
+    let mut scenario = build(
+        "
+        ########
+        #@>.#Z##
+        ########
+        ",
+    );
+    let zombie = scenario.entity('Z');
+
+    wizard(&mut scenario, "regenerate");
+    assert!(!scenario.is_alive(zombie));
+    assert!(scenario.is_alive(scenario.player()));
+    assert!(scenario.position(scenario.player()).is_some());
+    let dungeon = scenario.world().read_resource::<Dungeon>();
+    assert_eq!(dungeon.depth, 1);
+    assert!(!dungeon.regenerate);
+    // nothing to come back to
+    assert_eq!(dungeon.levels().count(), 0);
+}